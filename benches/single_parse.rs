@@ -0,0 +1,71 @@
+//! Single-parse obfuscation against the former two-pass path.
+//!
+//! `cargo bench --features bench --bench single_parse`; pass a number to
+//! change the rounds per sample, e.g. `... --bench single_parse -- 200`.  The
+//! `bench` feature builds the two-pass baseline into the library.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use java_dataset_converter_llm::obfuscator::{
+    Limits, Naming, obfuscate_source, obfuscate_two_pass,
+};
+
+const SAMPLES: usize = 7;
+
+/// A test class with `methods` test methods, each with locals, a loop and
+/// string literals.
+fn test_class(methods: usize) -> String {
+    let body: String = (0..methods)
+        .map(|i| {
+            format!(
+                "    @Test public void test{i}() {{ int count = {i}; String s = \"v{i}\"; \
+                 for (int j = 0; j < count; j++) {{ s = s + j; }} assertEquals(s, value(count)); }}\n"
+            )
+        })
+        .collect();
+    format!("public class Bench {{\n    private int value;\n{body}}}\n")
+}
+
+/// Median time of one call of `f`, over `SAMPLES` samples of `rounds` calls.
+fn median<T>(rounds: u32, mut f: impl FnMut() -> T) -> Duration {
+    let mut samples: Vec<Duration> = (0..SAMPLES)
+        .map(|_| {
+            let started = Instant::now();
+            for _ in 0..rounds {
+                black_box(f());
+            }
+            started.elapsed() / rounds
+        })
+        .collect();
+    samples.sort();
+    samples[SAMPLES / 2]
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; any number is the rounds per sample.
+    let rounds = std::env::args()
+        .skip(1)
+        .find_map(|a| a.parse().ok())
+        .unwrap_or(50);
+    let naming = Naming::default();
+    let limits = Limits::default();
+
+    println!(
+        "{:<12} {:>8} {:>12} {:>14} {:>8}",
+        "source", "bytes", "two-pass", "single-parse", "speedup"
+    );
+    for methods in [10, 50, 200] {
+        let src = test_class(methods);
+        let two_pass = median(rounds, || obfuscate_two_pass(&src, &naming).unwrap());
+        let single = median(rounds, || obfuscate_source(&src, &naming, &limits).unwrap());
+        println!(
+            "{:<12} {:>8} {:>12.1?} {:>14.1?} {:>7.2}x",
+            format!("{} methods", methods),
+            src.len(),
+            two_pass,
+            single,
+            two_pass.as_secs_f64() / single.as_secs_f64()
+        );
+    }
+}
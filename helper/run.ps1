@@ -1,7 +1,4 @@
-# Run for each directory
+# Run once over the whole dataset; files are assigned to train/val/test by a
+# stable hash of their project directory, so reruns produce the same split.
 
-cargo run -- --input ../../out/dataset/test/parse_ok/ --output dataset\val\java_obfuscated\ --jsonl-output dataset\val\jsonl\
-
-cargo run -- --input ../../out/dataset/val/parse_ok/ --output dataset\val\java_obfuscated\ --jsonl-output dataset\val\jsonl\
-
-cargo run -- --input ../../out/dataset/train/parse_ok/ --output dataset\val\java_obfuscated\ --jsonl-output dataset\val\jsonl\
+cargo run -- --input ../../out/dataset/ --output dataset\java_obfuscated\ --jsonl-output dataset\jsonl\ --split --split-by project --split-ratios 80,10,10
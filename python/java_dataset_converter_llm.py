@@ -0,0 +1,98 @@
+"""ctypes wrapper around the converter's shared library.
+
+Build the library with ``cargo build --release``; it is picked up from
+``target/release`` (or ``target/debug``) next to this directory, or from the
+path in ``JDC_LIBRARY``.
+
+    >>> from java_dataset_converter_llm import Converter
+    >>> conv = Converter()
+    >>> obfuscated, fallback = conv.obfuscate(conv.sanitize(source))
+    >>> record, fallback = conv.jsonl_record(source)
+"""
+
+import ctypes
+import os
+import sys
+from pathlib import Path
+
+ABI_VERSION = 1
+
+OK = 0
+INVALID_ARGUMENT = 1
+CONVERSION_FAILED = 2
+PANIC = 3
+
+
+class ConversionError(Exception):
+    """A call returned a status other than ``OK``."""
+
+    def __init__(self, status: int, message: str):
+        super().__init__(message)
+        self.status = status
+
+
+def _library_name() -> str:
+    if sys.platform == "win32":
+        return "java_dataset_converter_llm.dll"
+    if sys.platform == "darwin":
+        return "libjava_dataset_converter_llm.dylib"
+    return "libjava_dataset_converter_llm.so"
+
+
+def _find_library() -> str:
+    if "JDC_LIBRARY" in os.environ:
+        return os.environ["JDC_LIBRARY"]
+    target = Path(__file__).resolve().parent.parent / "target"
+    for profile in ("release", "debug"):
+        candidate = target / profile / _library_name()
+        if candidate.exists():
+            return str(candidate)
+    raise FileNotFoundError(f"{_library_name()} not found; run `cargo build --release`")
+
+
+class Converter:
+    def __init__(self, path: str | None = None):
+        lib = ctypes.CDLL(path or _find_library())
+        lib.jdc_abi_version.restype = ctypes.c_uint32
+        version = lib.jdc_abi_version()
+        if version != ABI_VERSION:
+            raise RuntimeError(f"library ABI {version}, wrapper expects {ABI_VERSION}")
+
+        out = ctypes.POINTER(ctypes.c_void_p)
+        lib.jdc_sanitize_structural.argtypes = [ctypes.c_char_p, out]
+        for name in ("jdc_obfuscate_str_checked", "jdc_jsonl_record"):
+            getattr(lib, name).argtypes = [ctypes.c_char_p, out, ctypes.POINTER(ctypes.c_bool)]
+        for name in ("jdc_sanitize_structural", "jdc_obfuscate_str_checked", "jdc_jsonl_record"):
+            getattr(lib, name).restype = ctypes.c_int
+        lib.jdc_string_free.argtypes = [ctypes.c_void_p]
+        lib.jdc_string_free.restype = None
+        self._lib = lib
+
+    def _take(self, status: int, out: ctypes.c_void_p) -> str:
+        # Copy the owned string, then hand it back to the library.
+        try:
+            text = ctypes.string_at(out.value).decode("utf-8") if out.value else ""
+        finally:
+            self._lib.jdc_string_free(out)
+        if status != OK:
+            raise ConversionError(status, text)
+        return text
+
+    def sanitize(self, source: str) -> str:
+        out = ctypes.c_void_p()
+        status = self._lib.jdc_sanitize_structural(source.encode("utf-8"), ctypes.byref(out))
+        return self._take(status, out)
+
+    def _with_fallback(self, function, source: str) -> tuple[str, bool]:
+        out = ctypes.c_void_p()
+        fallback = ctypes.c_bool()
+        status = function(source.encode("utf-8"), ctypes.byref(out), ctypes.byref(fallback))
+        return self._take(status, out), fallback.value
+
+    def obfuscate(self, sanitized: str) -> tuple[str, bool]:
+        """Obfuscated source and whether the literal-blanker fallback was needed."""
+        return self._with_fallback(self._lib.jdc_obfuscate_str_checked, sanitized)
+
+    def jsonl_record(self, source: str) -> tuple[str, bool]:
+        """The JSONL line the pipeline writes for a raw source."""
+        return self._with_fallback(self._lib.jdc_jsonl_record, source)
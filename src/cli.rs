@@ -1,25 +1,248 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+
+use crate::commands::FilterStage;
+use crate::dedup::DedupPolicy;
+use crate::encoding::Encoding;
+use crate::pipeline::Shard;
+use crate::server::Endpoint;
+use crate::split::{SplitGroup, SplitRatios};
+
+/// Exit code of a run that succeeded.
+pub const EXIT_OK: u8 = 0;
+/// Exit code of a run in which a file failed.
+pub const EXIT_FAILURE: u8 = 1;
+/// Exit code of `filter` when the source only converted through the
+/// literal-blanker fallback.  2 is left to clap's usage errors.
+pub const EXIT_FALLBACK: u8 = 3;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Arguments of `convert`, which also runs when no subcommand is given.
+    #[command(flatten)]
+    pub convert: Args,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Convert a tree of Java sources into JSONL pairs (the default)
+    Convert(Args),
+    /// Write the sanitized source of a file or directory
+    Sanitize(StageArgs),
+    /// Print the literal placeholders and the literal store as JSON lines
+    Blank(StageArgs),
+    /// Write the obfuscated source of a file or directory
+    Obfuscate(StageArgs),
+    /// Convert sources in memory and re-check existing JSONL pairs
+    Verify(VerifyArgs),
+    /// Summarise the pairs of a JSONL file or directory
+    Stats(StageArgs),
+    /// Assign sources or JSONL pairs to train / val / test
+    Split(SplitArgs),
+    /// Remove duplicate pairs from a JSONL output directory
+    Dedup(DedupArgs),
+    /// Print what the pipeline sees in a source as JSON lines
+    Inspect(StageArgs),
+    /// Read one source from stdin and write the selected stage to stdout
+    Filter(FilterArgs),
+    /// Serve obfuscation requests as JSON-RPC on a local socket
+    Serve(ServeArgs),
+}
+
+/// Arguments shared by the single-stage subcommands.
+#[derive(clap::Args, Debug)]
+pub struct StageArgs {
+    #[arg(help = "A single file or a directory searched recursively")]
+    pub path: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        help = "Output file, or output directory mirroring PATH when it is a \
+                directory; stdout when omitted"
+    )]
+    pub output: Option<PathBuf>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "CONFIG_JSON",
+        help = "Take the sanitizer steps, naming and output schema from a config file"
+    )]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct VerifyArgs {
+    #[command(flatten)]
+    pub stage: StageArgs,
+
+    #[arg(
+        long,
+        value_name = "JSONL",
+        help = "Copy the JSONL records that pass every check to this file"
+    )]
+    pub clean: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct FilterArgs {
+    #[arg(
+        short,
+        long,
+        value_enum,
+        default_value_t = FilterStage::Jsonl,
+        help = "Last stage to apply; the stages before it run too"
+    )]
+    pub stage: FilterStage,
+
+    #[arg(
+        short,
+        long,
+        default_value = "stdin",
+        help = "Name of the source in error messages"
+    )]
+    pub name: String,
+
+    #[arg(
+        short,
+        long,
+        value_name = "CONFIG_JSON",
+        help = "Take the sanitizer steps, naming and output schema from a config file"
+    )]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ServeArgs {
+    #[arg(
+        short,
+        long,
+        default_value = "127.0.0.1:7878",
+        value_parser = Endpoint::parse,
+        help = "Loopback address, or unix:PATH for a Unix socket"
+    )]
+    pub listen: Endpoint,
+
+    #[arg(
+        short,
+        long,
+        default_value_t = 0,
+        help = "Worker threads for requests; 0 uses one per CPU"
+    )]
+    pub threads: usize,
+
+    #[arg(
+        short,
+        long,
+        value_name = "CONFIG_JSON",
+        help = "Take the per-file limits from a config file"
+    )]
+    pub config: Option<PathBuf>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct SplitArgs {
+    #[command(flatten)]
+    pub stage: StageArgs,
+
+    #[arg(
+        long = "split-ratios",
+        value_parser = SplitRatios::parse,
+        help = "Relative train,val,test sizes [default: 80,10,10]"
+    )]
+    pub split_ratios: Option<SplitRatios>,
+
+    #[arg(
+        long = "split-by",
+        value_enum,
+        help = "Group files before splitting [default: file]"
+    )]
+    pub split_by: Option<SplitGroup>,
+
+    #[arg(
+        long = "split-seed",
+        help = "Seed mixed into the split hash [default: 0]"
+    )]
+    pub split_seed: Option<u64>,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DedupArgs {
+    #[arg(help = "JSONL output directory of a previous convert run")]
+    pub dir: PathBuf,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Read the train / val / test sub-directories"
+    )]
+    pub split: bool,
+
+    #[arg(
+        long = "near-dup-threshold",
+        help = "Estimated Jaccard similarity at which two pairs count as \
+                near-duplicates [default: 0.8]"
+    )]
+    pub near_dup_threshold: Option<f64>,
+
+    #[arg(
+        long = "dedup-policy",
+        value_enum,
+        help = "Which member of a duplicate cluster is kept [default: first]"
+    )]
+    pub dedup_policy: Option<DedupPolicy>,
+
+    #[arg(
+        short,
+        long,
+        value_name = "CONFIG_JSON",
+        help = "Take the output schema and dedup settings from a config file"
+    )]
+    pub config: Option<PathBuf>,
+}
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
 pub struct Args {
+    #[arg(
+        short,
+        long,
+        value_name = "CONFIG_JSON",
+        help = "JSON file with every pipeline option; flags given on the \
+                command line override it"
+    )]
+    pub config: Option<PathBuf>,
+
     #[arg(
         short,
         long,
         help = "Input directory of the set of java files that needs to be converted"
     )]
-    pub input: String,
+    pub input: Option<String>,
 
     #[arg(
         short,
         long,
         help = "Output directory of the set of converted java files"
     )]
-    pub output: String,
+    pub output: Option<String>,
 
     #[arg(short, long, help = "Output directory for the jsonL files")]
     pub jsonl_output: Option<String>,
 
+    #[arg(
+        long,
+        value_enum,
+        help = "Encoding of input files without a byte order mark \
+                [default: detect UTF-16, then UTF-8, else windows-1252]"
+    )]
+    pub encoding: Option<Encoding>,
+
     #[arg(
         short = 'b',
         long = "blanked-subdir",
@@ -28,4 +251,94 @@ pub struct Args {
                 sibling 'jsonl_blanked/' sub-directory"
     )]
     pub blanked_subdir: bool,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Assign each file to train/val/test by a stable hash and write \
+                each split to its own sub-directory of the JSONL output"
+    )]
+    pub split: bool,
+
+    #[arg(
+        long = "split-ratios",
+        value_parser = SplitRatios::parse,
+        help = "Relative train,val,test sizes used with --split [default: 80,10,10]"
+    )]
+    pub split_ratios: Option<SplitRatios>,
+
+    #[arg(
+        long = "split-by",
+        value_enum,
+        help = "Group files before splitting so a group never straddles splits \
+                [default: file]"
+    )]
+    pub split_by: Option<SplitGroup>,
+
+    #[arg(
+        long = "split-seed",
+        help = "Seed mixed into the split hash; change it to draw a different split \
+                [default: 0]"
+    )]
+    pub split_seed: Option<u64>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "After conversion, remove exact and near-duplicate pairs and \
+                write dedup_report.json next to the JSONL output"
+    )]
+    pub dedup: bool,
+
+    #[arg(
+        long = "near-dup-threshold",
+        help = "Estimated Jaccard similarity at which two pairs count as \
+                near-duplicates [default: 0.8]"
+    )]
+    pub near_dup_threshold: Option<f64>,
+
+    #[arg(
+        long = "dedup-policy",
+        value_enum,
+        help = "Which member of a duplicate cluster is kept [default: first]"
+    )]
+    pub dedup_policy: Option<DedupPolicy>,
+
+    #[arg(
+        long,
+        value_name = "INDEX/COUNT",
+        value_parser = Shard::parse,
+        help = "Convert only the inputs of shard INDEX out of COUNT (e.g. 0/4)"
+    )]
+    pub shard: Option<Shard>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with = "only_failed",
+        help = "Reprocess every input, ignoring the run manifest"
+    )]
+    pub force: bool,
+
+    #[arg(
+        long = "only-failed",
+        default_value_t = false,
+        help = "Reprocess only inputs whose last recorded outcome was a failure"
+    )]
+    pub only_failed: bool,
+
+    #[arg(
+        long,
+        value_name = "ERRORS_JSONL",
+        conflicts_with_all = ["force", "only_failed"],
+        help = "Reprocess exactly the files listed in a previous errors.jsonl"
+    )]
+    pub retry: Option<PathBuf>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Stop at the first file that fails instead of logging it and carrying on"
+    )]
+    pub strict: bool,
 }
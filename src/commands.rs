@@ -0,0 +1,703 @@
+//! Single stages of the pipeline, run on one file or a directory.  These back
+//! the CLI subcommands and return values; printing is left to the caller.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::Serialize;
+
+use crate::config::{Config, EFFECTIVE_CONFIG_FILE};
+
+use crate::dedup::{DedupConfig, DedupReport, find_duplicates, move_removed, read_records};
+use crate::encoding::{Encoding, decode, read_source};
+use crate::error::{Error, Result};
+use crate::error_log::ERROR_LOG_FILE;
+use crate::helper::{get_files, write_atomic};
+use crate::literal_blanker::blank_literals;
+use crate::manifest::{MANIFEST_FILE, input_key};
+use crate::obfuscator::{
+    Limits, LiteralRepair, Naming, SourcePosition, obfuscate_source, parse_error_locations,
+};
+use crate::pipeline::{
+    FileOutcome, MemorySink, Pipeline, PipelineConfig, Source, SplitConfig, blanked_subdir_of,
+};
+use crate::processor::{OutputSchema, count_tokens, pair_of};
+use crate::sanitizer::{
+    Sanitized, SanitizerSteps, sanitize_structural_counted, sanitize_structural_with,
+};
+use crate::split::{Split, assign_split, group_key, java_package};
+use crate::summary::{TokenLengths, renamed_identifiers};
+
+/// Name of the report written by [`dedup_output`].
+pub const DEDUP_REPORT_FILE: &str = "dedup_report.json";
+
+/// `path` itself when it is a file, otherwise every `.<extension>` file below
+/// it in a stable order.
+pub fn files_under(path: &Path, extension: &str) -> io::Result<Vec<PathBuf>> {
+    if path.is_file() {
+        Ok(vec![path.to_path_buf()])
+    } else {
+        get_files(&path.to_string_lossy(), extension)
+    }
+}
+
+/// Every JSONL pair file under `path`: run bookkeeping (manifest, error log)
+/// and pairs already moved to a `_duplicates` directory are left out.
+pub fn pair_files_under(path: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = files_under(path, "jsonl")?;
+    files.retain(|f| {
+        let bookkeeping = f
+            .file_name()
+            .is_some_and(|n| n == MANIFEST_FILE || n == ERROR_LOG_FILE);
+        let removed = f.components().any(|c| {
+            c.as_os_str()
+                .to_str()
+                .is_some_and(|c| c.ends_with("_duplicates"))
+        });
+        !bookkeeping && !removed
+    });
+    Ok(files)
+}
+
+// ── sanitize / obfuscate ────────────────────────────────────────────────────
+
+/// The source at `path`, decoded as [`read_source`] does with `encoding`,
+/// after the enabled sanitizer `steps`.
+pub fn sanitize_file(
+    path: &Path,
+    encoding: Option<Encoding>,
+    steps: &SanitizerSteps,
+) -> io::Result<String> {
+    let raw = read_source(path, encoding)?.text;
+    Ok(sanitize_structural_with(&raw, steps))
+}
+
+/// Obfuscated source of one file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Obfuscated {
+    pub source: String,
+    /// Needed the literal-blanker fallback, so literals are blanked.
+    pub fallback: bool,
+}
+
+/// Sanitize and obfuscate the source at `path` within `limits`.
+pub fn obfuscate_file(
+    path: &Path,
+    encoding: Option<Encoding>,
+    steps: &SanitizerSteps,
+    naming: &Naming,
+    limits: &Limits,
+) -> Result<Obfuscated> {
+    let sanitized = sanitize_file(path, encoding, steps)?;
+    let obfuscation = obfuscate_source(&sanitized, naming, limits)
+        .map_err(|e| e.with_label(&path.to_string_lossy()))?;
+    Ok(Obfuscated {
+        source: obfuscation.source,
+        fallback: obfuscation.fallback,
+    })
+}
+
+// ── blank ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlankedLiteral {
+    pub placeholder: String,
+    pub original: String,
+}
+
+/// A sanitized source with its literals replaced by placeholders.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Blanked {
+    pub file: PathBuf,
+    pub blanked: String,
+    pub literals: Vec<BlankedLiteral>,
+}
+
+impl Blanked {
+    /// Run [`blank_literals`] over an already sanitized source.
+    pub fn of(file: &Path, sanitized: &str) -> Self {
+        let (blanked, store) = blank_literals(sanitized);
+        Blanked {
+            file: file.to_path_buf(),
+            blanked,
+            literals: store
+                .iter()
+                .map(|(placeholder, original)| BlankedLiteral {
+                    placeholder: placeholder.to_string(),
+                    original: original.to_string(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Run [`blank_literals`] over the sanitized source at `path`.
+pub fn blank_file(
+    path: &Path,
+    encoding: Option<Encoding>,
+    steps: &SanitizerSteps,
+) -> io::Result<Blanked> {
+    Ok(Blanked::of(path, &sanitize_file(path, encoding, steps)?))
+}
+
+// ── filter ──────────────────────────────────────────────────────────────────
+
+/// Last stage applied by [`filter_source`]; every earlier stage runs too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum FilterStage {
+    /// The sanitized source.
+    Sanitize,
+    /// The blanked source and its literal store, as one JSON line.
+    Blank,
+    /// The obfuscated source.
+    Obfuscate,
+    /// One JSONL pair, blanked when the fallback was needed.
+    #[default]
+    Jsonl,
+}
+
+/// Result of [`filter_source`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FilterOutput {
+    pub text: String,
+    /// Obfuscation needed the literal-blanker fallback.
+    pub fallback: bool,
+}
+
+/// Run one in-memory source through the stages up to `stage`, with the
+/// sanitizer steps, naming and output schema of `config`.  `label` names the
+/// source in errors.
+pub fn filter_source(
+    raw: &str,
+    stage: FilterStage,
+    config: &Config,
+    label: &str,
+) -> Result<FilterOutput> {
+    let sanitized = sanitize_structural_with(raw, &config.sanitizer);
+    let output = |text, fallback| Ok(FilterOutput { text, fallback });
+    match stage {
+        FilterStage::Sanitize => return output(sanitized, false),
+        FilterStage::Blank => {
+            let blanked = Blanked::of(Path::new(label), &sanitized);
+            return output(serde_json::to_string(&blanked)? + "\n", false);
+        }
+        FilterStage::Obfuscate | FilterStage::Jsonl => {}
+    }
+
+    let obfuscation = obfuscate_source(&sanitized, &config.naming, &config.limits)
+        .map_err(|e| e.with_label(label))?;
+    if stage == FilterStage::Obfuscate {
+        return output(obfuscation.source, obfuscation.fallback);
+    }
+    let pair = pair_of(&sanitized, &obfuscation, label)?;
+    output(
+        config.output.schema.to_line(&pair) + "\n",
+        obfuscation.fallback,
+    )
+}
+
+// ── inspect ─────────────────────────────────────────────────────────────────
+
+/// What the pipeline sees in one source, without writing anything.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Inspection {
+    pub file: PathBuf,
+    pub bytes: usize,
+    pub encoding: Encoding,
+    /// The file started with a byte order mark.
+    pub bom: bool,
+    pub tokens: usize,
+    pub literals: usize,
+    /// Characters repaired by the sanitizer's `mojibake` step.
+    pub mojibake_repairs: usize,
+    pub package: Option<String>,
+    /// Outermost ERROR / MISSING nodes of the sanitized source.
+    pub parse_errors: Vec<SourcePosition>,
+    pub needs_fallback: Option<bool>,
+    /// What the fallback did to each literal; empty without the fallback.
+    pub literal_repairs: Vec<LiteralRepair>,
+    pub identifiers_renamed: Option<usize>,
+    pub error: Option<String>,
+}
+
+pub fn inspect_file(
+    path: &Path,
+    encoding: Option<Encoding>,
+    steps: &SanitizerSteps,
+    naming: &Naming,
+    limits: &Limits,
+) -> io::Result<Inspection> {
+    let bytes = fs::read(path)?;
+    let decoded = decode(&bytes, encoding)?;
+    let Sanitized {
+        text: sanitized,
+        mojibake_repairs,
+    } = sanitize_structural_counted(&decoded.text, steps);
+    let (_, store) = blank_literals(&sanitized);
+    let obfuscated = obfuscate_source(&sanitized, naming, limits);
+    let (needs_fallback, identifiers_renamed, mut error) = match &obfuscated {
+        Ok(obf) => (
+            Some(obf.fallback),
+            Some(renamed_identifiers(&sanitized, &obf.source)),
+            None,
+        ),
+        Err(e) => (None, None, Some(e.to_string())),
+    };
+    let literal_repairs = obfuscated.map(|obf| obf.literals).unwrap_or_default();
+    let parse_errors = parse_error_locations(&sanitized, limits).unwrap_or_else(|limit| {
+        let e = Error::LimitExceeded {
+            label: String::new(),
+            limit,
+        };
+        error.get_or_insert(e.to_string());
+        Vec::new()
+    });
+    Ok(Inspection {
+        file: path.to_path_buf(),
+        bytes: bytes.len(),
+        encoding: decoded.encoding,
+        bom: decoded.bom,
+        tokens: count_tokens(&sanitized),
+        literals: store.len(),
+        mojibake_repairs,
+        package: java_package(&sanitized),
+        parse_errors,
+        needs_fallback,
+        literal_repairs,
+        identifiers_renamed,
+        error,
+    })
+}
+
+// ── verify ──────────────────────────────────────────────────────────────────
+
+/// Result of converting one source without writing it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceCheck {
+    pub file: PathBuf,
+    /// `clean`, `fallback`, `filtered` or `failed`.
+    pub outcome: &'static str,
+    pub stage: Option<&'static str>,
+    pub message: Option<String>,
+}
+
+impl SourceCheck {
+    pub fn passed(&self) -> bool {
+        self.outcome != "failed"
+    }
+}
+
+/// Run every source under `path` through the pipeline into memory, with the
+/// fallback enabled, and report how each converts.
+pub fn verify_sources(path: &Path, config: PipelineConfig) -> Result<Vec<SourceCheck>> {
+    let root = if path.is_file() {
+        path.parent().unwrap_or(Path::new("")).to_path_buf()
+    } else {
+        path.to_path_buf()
+    };
+    let source = Source::Files {
+        root,
+        files: files_under(path, "java")?,
+    };
+    let pipeline = Pipeline::new(config.blanked_fallback(true), MemorySink::new())?;
+    let reports = pipeline.run(source)?;
+    Ok(reports
+        .into_iter()
+        .map(|r| {
+            let (outcome, stage, message) = match r.outcome {
+                FileOutcome::Clean { .. } | FileOutcome::UpToDate => ("clean", None, None),
+                FileOutcome::Fallback { .. } | FileOutcome::Quarantined { .. } => {
+                    ("fallback", None, None)
+                }
+                FileOutcome::Filtered { reason } => ("filtered", None, Some(reason)),
+                FileOutcome::Failed { stage, message, .. } => {
+                    ("failed", Some(stage.as_str()), Some(message))
+                }
+            };
+            SourceCheck {
+                file: r.path,
+                outcome,
+                stage,
+                message,
+            }
+        })
+        .collect())
+}
+
+// ── stats ───────────────────────────────────────────────────────────────────
+
+/// Statistics of an existing JSONL dataset.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct DatasetStats {
+    pub files: usize,
+    pub pairs: usize,
+    /// Lines that are not a pair under the schema.
+    pub invalid_lines: usize,
+    /// Pairs whose prompt equals their response: nothing was renamed.
+    pub unchanged_pairs: usize,
+    pub avg_identifiers_renamed: f64,
+    pub prompt_tokens: TokenLengths,
+}
+
+/// Read every `.jsonl` file under `path` and summarise its pairs.
+pub fn dataset_stats(path: &Path, schema: &OutputSchema) -> io::Result<DatasetStats> {
+    let mut stats = DatasetStats::default();
+    let mut lengths = Vec::new();
+    let mut renamed = 0;
+    for file in pair_files_under(path)? {
+        stats.files += 1;
+        for line in fs::read_to_string(&file)?.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some(pair) = schema.parse_line(line) else {
+                stats.invalid_lines += 1;
+                continue;
+            };
+            stats.pairs += 1;
+            if pair.prompt == pair.response {
+                stats.unchanged_pairs += 1;
+            }
+            renamed += renamed_identifiers(&pair.response, &pair.prompt);
+            lengths.push(count_tokens(&pair.prompt));
+        }
+    }
+    lengths.sort_unstable();
+    stats.prompt_tokens = TokenLengths::of_sorted(&lengths);
+    if stats.pairs > 0 {
+        stats.avg_identifiers_renamed = renamed as f64 / stats.pairs as f64;
+    }
+    Ok(stats)
+}
+
+// ── split ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SplitAssignment {
+    pub file: PathBuf,
+    /// Input key the split was drawn for: the source's path relative to the
+    /// input root, as `convert` keys it.
+    pub key: String,
+    pub split: Split,
+}
+
+/// Assign every `.java` source or `.jsonl` pair under `path` to a split, as
+/// `convert --split` would when `path` is its input or JSONL output
+/// directory.  A pair is keyed as the source it was made from
+/// (`a/T.java.jsonl` → `a/T.java`, without the `train` / `val` / `test`
+/// directory of a split output) and its response is the source for package
+/// grouping.
+pub fn assign_splits(
+    path: &Path,
+    split: &SplitConfig,
+    schema: &OutputSchema,
+) -> io::Result<Vec<SplitAssignment>> {
+    let root = if path.is_file() {
+        path.parent().unwrap_or(Path::new(""))
+    } else {
+        path
+    };
+    // The effective config of a `convert` run tells whether its pairs sit
+    // one split directory below the mirrored input tree.
+    let split_output =
+        Config::load(&root.join(EFFECTIVE_CONFIG_FILE)).is_ok_and(|c| c.split.is_some());
+    let mut files = files_under(path, "java")?;
+    files.extend(pair_files_under(path)?);
+    files.retain(|f| f.extension().is_some_and(|e| e == "java" || e == "jsonl"));
+    files.sort();
+    files.dedup();
+
+    let mut assignments = Vec::with_capacity(files.len());
+    for file in files {
+        let bytes = fs::read(&file)?;
+        let text = String::from_utf8_lossy(&bytes).into_owned();
+        let key = input_key(root, &file);
+        let (key, source) = match key.strip_suffix(".jsonl") {
+            Some(stem) => {
+                let stem = match stem.split_once('/') {
+                    Some((_, below)) if split_output => below,
+                    _ => stem,
+                };
+                let first = text.lines().next().unwrap_or_default();
+                (
+                    stem.to_string(),
+                    schema.parse_line(first).map(|p| p.response),
+                )
+            }
+            None => (key, Some(text)),
+        };
+        let group = group_key(
+            Path::new(""),
+            Path::new(&key),
+            source.as_deref(),
+            split.group,
+        );
+        assignments.push(SplitAssignment {
+            file,
+            split: assign_split(&group, &split.ratios, split.seed),
+            key,
+        });
+    }
+    Ok(assignments)
+}
+
+/// Copy each assigned file to `<output>/<split>/<key>`, plus `.jsonl` for
+/// pairs, so the copies mirror the input tree.
+pub fn copy_into_splits(assignments: &[SplitAssignment], output: &Path) -> io::Result<()> {
+    for a in assignments {
+        let mut target = output.join(a.split.as_str()).join(&a.key);
+        if a.file.extension().is_some_and(|e| e == "jsonl") {
+            target.as_mut_os_string().push(".jsonl");
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(&a.file, target)?;
+    }
+    Ok(())
+}
+
+// ── dedup ───────────────────────────────────────────────────────────────────
+
+/// Deduplicate the pairs of a JSONL output directory in place: move removed
+/// pairs to `_duplicates` siblings and write `dedup_report.json`.  With
+/// `split`, the `train` / `val` / `test` sub-directories are read instead of
+/// `jsonl_dir` itself.
+pub fn dedup_output(
+    jsonl_dir: &Path,
+    split: bool,
+    schema: &OutputSchema,
+    config: &DedupConfig,
+) -> io::Result<DedupReport> {
+    let dirs: Vec<(PathBuf, Option<Split>)> = if split {
+        Split::ALL
+            .into_iter()
+            .map(|s| (jsonl_dir.join(s.as_str()), Some(s)))
+            .collect()
+    } else {
+        vec![(jsonl_dir.to_path_buf(), None)]
+    };
+    let mut records = Vec::new();
+    for (dir, split) in &dirs {
+        records.extend(read_records(dir, *split, schema)?);
+        records.extend(read_records(&blanked_subdir_of(dir), *split, schema)?);
+    }
+
+    let report = find_duplicates(&records, config);
+    let read: Vec<PathBuf> = dirs
+        .iter()
+        .flat_map(|(dir, _)| [dir.clone(), blanked_subdir_of(dir)])
+        .collect();
+    move_removed(&report, &read)?;
+    let json = serde_json::to_string_pretty(&report)?;
+    write_atomic(&jsonl_dir.join(DEDUP_REPORT_FILE), |w| {
+        w.write_all(json.as_bytes())
+    })?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    use crate::split::{SplitGroup, SplitRatios};
+
+    const SOURCE: &str = "package p;\npublic class T {\n@Test public void testFoo() { String s = \"hi\"; assertEquals(\"hi\", s); }\n}";
+
+    fn source_dir() -> TempDir {
+        let dir = TempDir::new().unwrap();
+        fs::create_dir_all(dir.path().join("a")).unwrap();
+        fs::write(dir.path().join("a/T.java"), SOURCE).unwrap();
+        fs::write(dir.path().join("a/Bad.java"), [0xff, 0xfe, 0x41]).unwrap();
+        dir
+    }
+
+    #[test]
+    fn blank_and_inspect_a_single_file() {
+        let dir = source_dir();
+        let file = dir.path().join("a/T.java");
+        let steps = SanitizerSteps::default();
+
+        let blanked = blank_file(&file, None, &steps).unwrap();
+        assert_eq!(blanked.literals.len(), 2);
+        assert_eq!(blanked.literals[0].original, "\"hi\"");
+        assert!(blanked.blanked.contains(&blanked.literals[1].placeholder));
+
+        let inspection =
+            inspect_file(&file, None, &steps, &Naming::default(), &Limits::default()).unwrap();
+        assert_eq!(inspection.package.as_deref(), Some("p"));
+        assert_eq!(inspection.literals, 2);
+        assert_eq!(inspection.needs_fallback, Some(false));
+        assert!(inspection.literal_repairs.is_empty());
+        assert!(inspection.parse_errors.is_empty());
+        assert!(inspection.identifiers_renamed.unwrap() >= 2);
+    }
+
+    #[test]
+    fn obfuscate_and_inspect_apply_the_configured_limits() {
+        let dir = source_dir();
+        let file = dir.path().join("a/T.java");
+        let steps = SanitizerSteps::default();
+        let naming = Naming::default();
+        let limits = Limits {
+            max_nodes: Some(10),
+            ..Limits::default()
+        };
+
+        let err = obfuscate_file(&file, None, &steps, &naming, &limits).unwrap_err();
+        assert_eq!(err.category(), crate::error_log::ErrorCategory::Limit);
+        let inspection = inspect_file(&file, None, &steps, &naming, &limits).unwrap();
+        assert!(
+            inspection
+                .error
+                .unwrap()
+                .contains("resource limit exceeded")
+        );
+    }
+
+    #[test]
+    fn filter_stops_at_the_selected_stage() {
+        let config = Config::default();
+        let sanitized = filter_source(SOURCE, FilterStage::Sanitize, &config, "T.java").unwrap();
+        assert_eq!(sanitized.text, SOURCE);
+
+        let obfuscated = filter_source(SOURCE, FilterStage::Obfuscate, &config, "T.java").unwrap();
+        assert!(obfuscated.text.contains("func_1") && !obfuscated.fallback);
+
+        let line = filter_source(SOURCE, FilterStage::Jsonl, &config, "T.java").unwrap();
+        let pair = config
+            .output
+            .schema
+            .parse_line(line.text.trim_end())
+            .unwrap();
+        assert_eq!(pair.prompt, obfuscated.text);
+        assert_eq!(pair.response, SOURCE);
+
+        let err = filter_source("", FilterStage::Jsonl, &config, "X.java");
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn verify_reports_each_source() {
+        let dir = source_dir();
+        let checks = verify_sources(dir.path(), PipelineConfig::new()).unwrap();
+        assert_eq!(checks.len(), 2);
+        assert_eq!(checks[0].outcome, "failed");
+        assert_eq!(checks[0].stage, Some("read"));
+        assert!(checks[1].passed());
+    }
+
+    #[test]
+    fn split_keys_pairs_like_their_sources() {
+        let dir = source_dir();
+        let pair = OutputSchema::default().to_line(&crate::processor::PromptResponse {
+            prompt: "x".to_string(),
+            response: SOURCE.to_string(),
+        });
+        fs::write(dir.path().join("a/T.java.jsonl"), pair).unwrap();
+        fs::remove_file(dir.path().join("a/Bad.java")).unwrap();
+
+        let config = SplitConfig {
+            ratios: SplitRatios::new(1.0, 1.0, 1.0).unwrap(),
+            group: SplitGroup::File,
+            seed: 7,
+        };
+        let assignments = assign_splits(dir.path(), &config, &OutputSchema::default()).unwrap();
+        assert_eq!(assignments.len(), 2);
+        assert_eq!(assignments[0].split, assignments[1].split);
+
+        let out = dir.path().join("out");
+        copy_into_splits(&assignments, &out).unwrap();
+        let split_dir = out.join(assignments[0].split.as_str());
+        assert!(split_dir.join("a/T.java").is_file());
+        assert!(split_dir.join("a/T.java.jsonl").is_file());
+    }
+
+    #[test]
+    fn split_of_a_split_output_matches_convert() {
+        let dir = TempDir::new().unwrap();
+        let input = dir.path().join("in");
+        let jsonl = dir.path().join("jsonl");
+        for project in ["a", "b", "c", "d"] {
+            fs::create_dir_all(input.join(project)).unwrap();
+            let source = SOURCE.replace("testFoo", &format!("test_{}", project));
+            fs::write(input.join(project).join("T.java"), source).unwrap();
+        }
+        let split = SplitConfig {
+            ratios: SplitRatios::new(1.0, 1.0, 1.0).unwrap(),
+            group: SplitGroup::Project,
+            seed: 3,
+        };
+        let config = Config {
+            split: Some(split),
+            ..Config::default()
+        };
+        fs::create_dir_all(&jsonl).unwrap();
+        config.write_effective(&jsonl).unwrap();
+        let pipeline = Pipeline::new(
+            config.pipeline_config(),
+            crate::pipeline::FsSink::new(&jsonl),
+        )
+        .unwrap();
+        pipeline.run(Source::Dir(input.clone())).unwrap();
+
+        let schema = OutputSchema::default();
+        let sources = assign_splits(&input, &split, &schema).unwrap();
+        let pairs = assign_splits(&jsonl, &split, &schema).unwrap();
+        assert_eq!(pairs.len(), 4);
+        for (source, pair) in sources.iter().zip(&pairs) {
+            assert_eq!(pair.key, source.key);
+            assert_eq!(pair.split, source.split);
+            let written = jsonl
+                .join(pair.split.as_str())
+                .join(format!("{}.jsonl", pair.key));
+            assert_eq!(pair.file, written);
+        }
+    }
+
+    #[test]
+    fn dedup_moves_nested_duplicates_keeping_their_path() {
+        let dir = TempDir::new().unwrap();
+        let jsonl = dir.path().join("jsonl");
+        let schema = OutputSchema::default();
+        let line = schema.to_line(&crate::processor::PromptResponse {
+            prompt: SOURCE.to_string(),
+            response: SOURCE.to_string(),
+        });
+        for key in ["a/T.java", "b/T.java"] {
+            let path = jsonl.join(format!("{}.jsonl", key));
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, &line).unwrap();
+        }
+        // Bookkeeping that happens to parse as a pair is never a record.
+        for name in [MANIFEST_FILE, ERROR_LOG_FILE] {
+            fs::write(jsonl.join(name), &line).unwrap();
+        }
+
+        let report = dedup_output(&jsonl, false, &schema, &DedupConfig::default()).unwrap();
+        assert_eq!(report.exact_duplicates, 1);
+        assert!(jsonl.join(MANIFEST_FILE).is_file() && jsonl.join(ERROR_LOG_FILE).is_file());
+        assert!(jsonl.join("a/T.java.jsonl").is_file());
+        assert!(!jsonl.join("b/T.java.jsonl").exists());
+        assert!(dir.path().join("jsonl_duplicates/b/T.java.jsonl").is_file());
+    }
+
+    #[test]
+    fn stats_count_pairs_and_invalid_lines() {
+        let dir = TempDir::new().unwrap();
+        let schema = OutputSchema::default();
+        let pair = crate::processor::PromptResponse {
+            prompt: "void func_1() {}".to_string(),
+            response: "void run() {}".to_string(),
+        };
+        let text = format!("{}\nnot json\n", schema.to_line(&pair));
+        fs::write(dir.path().join("a.jsonl"), text).unwrap();
+
+        let stats = dataset_stats(dir.path(), &schema).unwrap();
+        assert_eq!((stats.files, stats.pairs, stats.invalid_lines), (1, 1, 1));
+        assert_eq!(stats.avg_identifiers_renamed, 1.0);
+    }
+}
@@ -0,0 +1,303 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli::Args;
+use crate::dedup::DedupConfig;
+use crate::encoding::Encoding;
+use crate::helper::write_atomic;
+use crate::obfuscator::{Limits, Naming};
+use crate::pipeline::{Filters, PipelineConfig, Shard, SplitConfig};
+use crate::processor::OutputSchema;
+use crate::sanitizer::SanitizerSteps;
+use crate::split::SplitRatios;
+
+/// Name of the merged configuration written next to the JSONL output.
+pub const EFFECTIVE_CONFIG_FILE: &str = "effective_config.json";
+
+/// Which inputs are converted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InputConfig {
+    pub dir: Option<PathBuf>,
+    /// Globs over `/`-separated paths relative to `dir`; empty includes every
+    /// `.java` file.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Encoding of sources without a byte order mark; unset detects it.
+    pub encoding: Option<Encoding>,
+}
+
+/// Where and how pairs are written.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputConfig {
+    pub dir: Option<PathBuf>,
+    /// JSONL output directory; defaults to `dir`.
+    pub jsonl_dir: Option<PathBuf>,
+    /// Write fallback pairs to the `_blanked` sibling instead of quarantining.
+    pub blanked_fallback: bool,
+    pub schema: OutputSchema,
+}
+
+/// Every option of a conversion run, as read from a `--config` JSON file.
+///
+/// Missing sections take their defaults; `split` and `dedup` enable their
+/// stage when present.  Relative paths are resolved against the working
+/// directory.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub input: InputConfig,
+    pub output: OutputConfig,
+    pub naming: Naming,
+    pub sanitizer: SanitizerSteps,
+    pub filters: Filters,
+    pub limits: Limits,
+    pub shard: Option<Shard>,
+    pub split: Option<SplitConfig>,
+    pub dedup: Option<DedupConfig>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, message)
+}
+
+impl Config {
+    /// Read a config file.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        serde_json::from_str(&text)
+            .map_err(|e| invalid(format!("invalid config {}: {}", path.display(), e)))
+    }
+
+    /// The config file at `path`, or the defaults when there is none.
+    pub fn load_or_default(path: Option<&Path>) -> io::Result<Self> {
+        path.map_or_else(|| Ok(Config::default()), Config::load)
+    }
+
+    /// The config file named by `--config` (or the defaults) with every flag
+    /// given on the command line applied on top, validated.
+    pub fn from_args(args: &Args) -> io::Result<Self> {
+        let mut config = Config::load_or_default(args.config.as_deref())?;
+        config.apply_args(args);
+        config.validate().map_err(invalid)?;
+        Ok(config)
+    }
+
+    /// Override settings with the flags present in `args`.
+    pub fn apply_args(&mut self, args: &Args) {
+        if let Some(dir) = &args.input {
+            self.input.dir = Some(dir.into());
+        }
+        if let Some(dir) = &args.output {
+            self.output.dir = Some(dir.into());
+        }
+        if let Some(dir) = &args.jsonl_output {
+            self.output.jsonl_dir = Some(dir.into());
+        }
+        if args.encoding.is_some() {
+            self.input.encoding = args.encoding;
+        }
+        if args.blanked_subdir {
+            self.output.blanked_fallback = true;
+        }
+        if args.shard.is_some() {
+            self.shard = args.shard;
+        }
+
+        if args.split {
+            self.split.get_or_insert_with(SplitConfig::default);
+        }
+        if let Some(split) = &mut self.split {
+            if let Some(ratios) = args.split_ratios {
+                split.ratios = ratios;
+            }
+            if let Some(group) = args.split_by {
+                split.group = group;
+            }
+            if let Some(seed) = args.split_seed {
+                split.seed = seed;
+            }
+        }
+
+        if args.dedup {
+            self.dedup.get_or_insert_with(DedupConfig::default);
+        }
+        if let Some(dedup) = &mut self.dedup {
+            if let Some(threshold) = args.near_dup_threshold {
+                dedup.near_threshold = threshold;
+            }
+            if let Some(policy) = args.dedup_policy {
+                dedup.policy = policy;
+            }
+        }
+    }
+
+    /// Check the settings of a conversion run and normalise the split ratios.
+    pub fn validate(&mut self) -> Result<(), String> {
+        if self.input.dir.is_none() {
+            return Err("no input directory: pass --input or set input.dir".to_string());
+        }
+        if self.jsonl_dir().is_none() {
+            return Err("no output directory: pass --output or set output.dir".to_string());
+        }
+        self.check_settings()
+    }
+
+    /// `self` after [`Config::check_settings`], for subcommands to call once
+    /// their own flags are applied.
+    pub fn checked(mut self) -> io::Result<Self> {
+        self.check_settings().map_err(invalid)?;
+        Ok(self)
+    }
+
+    /// Check the settings shared by every subcommand and normalise the split
+    /// ratios, which a config file may give as e.g. `80/10/10`.
+    pub fn check_settings(&mut self) -> Result<(), String> {
+        if let Some(split) = &mut self.split {
+            let r = split.ratios;
+            split.ratios = SplitRatios::new(r.train, r.val, r.test)?;
+        }
+        if let Some(dedup) = &self.dedup
+            && !(0.0..=1.0).contains(&dedup.near_threshold)
+        {
+            return Err(format!(
+                "dedup.near_threshold must be between 0 and 1, got {}",
+                dedup.near_threshold
+            ));
+        }
+        let schema = &self.output.schema;
+        if schema.prompt_field.is_empty() || schema.prompt_field == schema.response_field {
+            return Err("output.schema needs two distinct, non-empty field names".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn input_dir(&self) -> Option<&Path> {
+        self.input.dir.as_deref()
+    }
+
+    /// Directory the JSONL pairs, manifest and reports are written to.
+    pub fn jsonl_dir(&self) -> Option<&Path> {
+        self.output
+            .jsonl_dir
+            .as_deref()
+            .or(self.output.dir.as_deref())
+    }
+
+    /// Pipeline settings for this config.  Run-mode settings (selection,
+    /// manifest, error log) are left to the caller.
+    pub fn pipeline_config(&self) -> PipelineConfig {
+        PipelineConfig::new()
+            .blanked_fallback(self.output.blanked_fallback)
+            .split(self.split)
+            .include(self.input.include.clone())
+            .exclude(self.input.exclude.clone())
+            .encoding(self.input.encoding)
+            .shard(self.shard)
+            .filters(self.filters)
+            .limits(self.limits)
+            .naming(self.naming.clone())
+            .sanitizer(self.sanitizer)
+            .schema(self.output.schema.clone())
+    }
+
+    /// Write this config to `dir/effective_config.json`, so the run can be
+    /// repeated with `--config`.
+    pub fn write_effective(&self, dir: &Path) -> io::Result<PathBuf> {
+        let path = dir.join(EFFECTIVE_CONFIG_FILE);
+        let json = serde_json::to_string_pretty(self)?;
+        write_atomic(&path, |w| w.write_all(json.as_bytes()))?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use tempfile::TempDir;
+
+    use crate::split::SplitGroup;
+
+    fn args(argv: &[&str]) -> Args {
+        Args::parse_from(std::iter::once("prog").chain(argv.iter().copied()))
+    }
+
+    #[test]
+    fn config_file_fills_gaps_and_flags_override_it() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("run.json");
+        fs::write(
+            &path,
+            r#"{
+                "input": {"dir": "in", "exclude": ["generated/**"]},
+                "output": {"dir": "out", "schema": {"prompt_field": "input"}},
+                "naming": {"function_prefix": "m_"},
+                "split": {"ratios": {"train": 8, "val": 1, "test": 1}, "group": "project"}
+            }"#,
+        )
+        .unwrap();
+
+        let path = path.to_str().unwrap();
+        let config = Config::from_args(&args(&["--config", path, "-o", "cli_out"])).unwrap();
+        assert_eq!(config.input_dir(), Some(Path::new("in")));
+        assert_eq!(config.jsonl_dir(), Some(Path::new("cli_out")));
+        assert_eq!(config.output.schema.prompt_field, "input");
+        assert_eq!(config.output.schema.response_field, "response");
+        assert_eq!(config.naming.function_prefix, "m_");
+        assert_eq!(config.naming.variable_prefix, "var_");
+        let split = config.split.unwrap();
+        assert_eq!(split.group, SplitGroup::Project);
+        assert!((split.ratios.train - 0.8).abs() < 1e-9);
+
+        let config =
+            Config::from_args(&args(&["--config", path, "--split-by", "package"])).unwrap();
+        assert_eq!(config.split.unwrap().group, SplitGroup::Package);
+        assert!(config.dedup.is_none());
+    }
+
+    #[test]
+    fn effective_config_round_trips() {
+        let dir = TempDir::new().unwrap();
+        let config = Config::from_args(&args(&[
+            "-i", "in", "-o", "out", "--split", "--dedup", "--shard", "1/4",
+        ]))
+        .unwrap();
+        let written = config.write_effective(dir.path()).unwrap();
+        assert_eq!(Config::load(&written).unwrap(), config);
+    }
+
+    #[test]
+    fn subcommand_configs_are_checked_without_input_or_output() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("run.json");
+        fs::write(
+            &path,
+            r#"{"split": {"ratios": {"train": 80, "val": 10, "test": 10}}}"#,
+        )
+        .unwrap();
+        let config = Config::load(&path).unwrap().checked().unwrap();
+        let ratios = config.split.unwrap().ratios;
+        assert!((ratios.train - 0.8).abs() < 1e-9 && (ratios.test - 0.1).abs() < 1e-9);
+
+        fs::write(&path, r#"{"dedup": {"near_threshold": 80}}"#).unwrap();
+        assert!(Config::load(&path).unwrap().checked().is_err());
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("run.json");
+        fs::write(&path, r#"{"input": {"dirs": "in"}}"#).unwrap();
+        assert!(Config::load(&path).is_err());
+
+        assert!(Config::from_args(&args(&["-o", "out"])).is_err());
+        fs::write(&path, r#"{"shard": {"index": 4, "count": 4}}"#).unwrap();
+        let argv = ["--config", path.to_str().unwrap(), "-i", "in", "-o", "out"];
+        assert!(Config::from_args(&args(&argv)).is_err());
+    }
+}
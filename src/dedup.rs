@@ -0,0 +1,471 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::pair_files_under;
+use crate::helper::stable_hash;
+use crate::processor::{OutputSchema, tokens};
+use crate::split::Split;
+
+// ---------------------------------------------------------------------------
+// Configuration
+// ---------------------------------------------------------------------------
+
+/// Which member of a duplicate cluster survives.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DedupPolicy {
+    /// Keep the member with the smallest id (path order).
+    #[default]
+    First,
+    /// Keep a `train` member when the cluster has one, so duplicates are
+    /// dropped from the evaluation splits rather than from training.
+    PreferTrain,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DedupConfig {
+    /// Estimated Jaccard similarity at or above which two records are
+    /// considered near-duplicates.
+    pub near_threshold: f64,
+    /// Number of tokens per shingle.
+    pub shingle_size: usize,
+    pub policy: DedupPolicy,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        DedupConfig {
+            near_threshold: 0.8,
+            shingle_size: 5,
+            policy: DedupPolicy::First,
+        }
+    }
+}
+
+// MinHash signature length and LSH banding (BANDS × ROWS = NUM_HASHES).
+// 16 bands of 8 rows put the LSH candidate threshold at roughly 0.7, just
+// below the default near-duplicate threshold.
+const NUM_HASHES: usize = 128;
+const BANDS: usize = 16;
+const ROWS: usize = NUM_HASHES / BANDS;
+
+// ---------------------------------------------------------------------------
+// Records and report
+// ---------------------------------------------------------------------------
+
+/// One JSONL pair as seen by the dedup stage.
+#[derive(Debug, Clone)]
+pub struct DedupRecord {
+    /// Stable identifier, normally the JSONL file path.
+    pub id: String,
+    pub split: Option<Split>,
+    /// The obfuscated side of the pair.  Identifiers are already normalised to
+    /// `func_N` / `var_N`, so renaming differences cannot hide a duplicate.
+    pub prompt: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateKind {
+    Exact,
+    Near,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DuplicateCluster {
+    pub kind: DuplicateKind,
+    pub representative: String,
+    pub members: Vec<String>,
+    pub splits: Vec<Split>,
+    /// `true` when the members span more than one split (train/test leakage).
+    pub cross_split: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DedupReport {
+    pub total_records: usize,
+    pub exact_duplicates: usize,
+    pub near_duplicates: usize,
+    pub cross_split_clusters: usize,
+    pub clusters: Vec<DuplicateCluster>,
+    /// Ids of every record that is not its cluster's representative.
+    pub removed: Vec<String>,
+}
+
+// ---------------------------------------------------------------------------
+// Normalisation and hashing
+// ---------------------------------------------------------------------------
+
+/// Canonical token string for duplicate detection.
+///
+/// Whitespace is dropped by the tokenizer, and the name that follows `class`
+/// / `interface` / `enum` is replaced because generated suites give every
+/// otherwise-identical test its own `TestClassNNNN`.
+pub fn normalize(src: &str) -> Vec<&str> {
+    let mut out: Vec<&str> = Vec::new();
+    for tok in tokens(src) {
+        let after_type_keyword = matches!(out.last(), Some(&("class" | "interface" | "enum")));
+        out.push(if after_type_keyword { "$TYPE" } else { tok });
+    }
+    out
+}
+
+/// SplitMix64 finaliser, used to derive independent MinHash functions from a
+/// single stable hash.
+fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn shingle_hashes(toks: &[&str], k: usize) -> Vec<u64> {
+    let k = k.max(1);
+    if toks.len() <= k {
+        return vec![stable_hash(toks.join(" ").as_bytes())];
+    }
+    toks.windows(k)
+        .map(|w| stable_hash(w.join(" ").as_bytes()))
+        .collect()
+}
+
+fn minhash(shingles: &[u64]) -> [u64; NUM_HASHES] {
+    let mut sig = [u64::MAX; NUM_HASHES];
+    for &s in shingles {
+        for (i, slot) in sig.iter_mut().enumerate() {
+            let h = mix(s ^ mix(i as u64));
+            if h < *slot {
+                *slot = h;
+            }
+        }
+    }
+    sig
+}
+
+fn estimated_jaccard(a: &[u64; NUM_HASHES], b: &[u64; NUM_HASHES]) -> f64 {
+    let same = a.iter().zip(b.iter()).filter(|(x, y)| x == y).count();
+    same as f64 / NUM_HASHES as f64
+}
+
+// ---------------------------------------------------------------------------
+// Clustering
+// ---------------------------------------------------------------------------
+
+struct UnionFind {
+    parent: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new(n: usize) -> Self {
+        UnionFind {
+            parent: (0..n).collect(),
+        }
+    }
+
+    fn find(&mut self, x: usize) -> usize {
+        let mut root = x;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut cur = x;
+        while self.parent[cur] != root {
+            let next = self.parent[cur];
+            self.parent[cur] = root;
+            cur = next;
+        }
+        root
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (ra, rb) = (self.find(a), self.find(b));
+        if ra != rb {
+            // Smaller index becomes the root so clusters are stable.
+            let (lo, hi) = if ra < rb { (ra, rb) } else { (rb, ra) };
+            self.parent[hi] = lo;
+        }
+    }
+}
+
+fn pick_representative(members: &[usize], records: &[DedupRecord], policy: DedupPolicy) -> usize {
+    let by_id = |&&i: &&usize| records[i].id.clone();
+    match policy {
+        DedupPolicy::First => *members.iter().min_by_key(by_id).unwrap(),
+        DedupPolicy::PreferTrain => members
+            .iter()
+            .filter(|&&i| records[i].split == Some(Split::Train))
+            .min_by_key(by_id)
+            .or_else(|| members.iter().min_by_key(by_id))
+            .copied()
+            .unwrap(),
+    }
+}
+
+/// Find exact and near-duplicate clusters among `records`.
+///
+/// Exact duplicates share the hash of their normalised token stream.  One
+/// member per exact group then takes part in MinHash/LSH near-duplicate
+/// detection; candidate pairs from any shared LSH band are confirmed against
+/// `config.near_threshold` before being merged.
+pub fn find_duplicates(records: &[DedupRecord], config: &DedupConfig) -> DedupReport {
+    let normalized: Vec<Vec<&str>> = records.iter().map(|r| normalize(&r.prompt)).collect();
+
+    // ── 1. exact duplicates ────────────────────────────────────────────────
+    let mut uf = UnionFind::new(records.len());
+    let mut exact_groups: HashMap<u64, usize> = HashMap::new();
+    let mut exact_member = vec![false; records.len()];
+    for (i, toks) in normalized.iter().enumerate() {
+        let h = stable_hash(toks.join(" ").as_bytes());
+        match exact_groups.get(&h) {
+            Some(&first) => {
+                uf.union(first, i);
+                exact_member[i] = true;
+                exact_member[first] = true;
+            }
+            None => {
+                exact_groups.insert(h, i);
+            }
+        }
+    }
+
+    // ── 2. near duplicates (one signature per exact group) ────────────────
+    let mut reps: Vec<usize> = exact_groups.values().copied().collect();
+    reps.sort_unstable();
+    let signatures: Vec<[u64; NUM_HASHES]> = reps
+        .iter()
+        .map(|&i| minhash(&shingle_hashes(&normalized[i], config.shingle_size)))
+        .collect();
+
+    let mut near_edges: BTreeSet<(usize, usize)> = BTreeSet::new();
+    for band in 0..BANDS {
+        let mut buckets: HashMap<&[u64], Vec<usize>> = HashMap::new();
+        for (j, sig) in signatures.iter().enumerate() {
+            buckets
+                .entry(&sig[band * ROWS..(band + 1) * ROWS])
+                .or_default()
+                .push(j);
+        }
+        for bucket in buckets.values().filter(|b| b.len() > 1) {
+            for (x, &a) in bucket.iter().enumerate() {
+                for &b in &bucket[x + 1..] {
+                    near_edges.insert((a, b));
+                }
+            }
+        }
+    }
+    let mut near_member = vec![false; records.len()];
+    for (a, b) in near_edges {
+        if estimated_jaccard(&signatures[a], &signatures[b]) >= config.near_threshold {
+            let (ia, ib) = (reps[a], reps[b]);
+            uf.union(ia, ib);
+            near_member[ia] = true;
+            near_member[ib] = true;
+        }
+    }
+
+    // ── 3. clusters and representatives ───────────────────────────────────
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..records.len() {
+        let root = uf.find(i);
+        groups.entry(root).or_default().push(i);
+    }
+
+    let mut report = DedupReport {
+        total_records: records.len(),
+        ..DedupReport::default()
+    };
+    for members in groups.into_values().filter(|m| m.len() > 1) {
+        let rep = pick_representative(&members, records, config.policy);
+        let kind = if members.iter().any(|&i| near_member[i]) {
+            DuplicateKind::Near
+        } else {
+            DuplicateKind::Exact
+        };
+        let splits: BTreeSet<Split> = members.iter().filter_map(|&i| records[i].split).collect();
+        let cross_split = splits.len() > 1;
+
+        for &i in &members {
+            if i == rep {
+                continue;
+            }
+            if exact_member[i] && !near_member[i] {
+                report.exact_duplicates += 1;
+            } else {
+                report.near_duplicates += 1;
+            }
+            report.removed.push(records[i].id.clone());
+        }
+        if cross_split {
+            report.cross_split_clusters += 1;
+        }
+
+        let mut ids: Vec<String> = members.iter().map(|&i| records[i].id.clone()).collect();
+        ids.sort();
+        report.clusters.push(DuplicateCluster {
+            kind,
+            representative: records[rep].id.clone(),
+            members: ids,
+            splits: splits.into_iter().collect(),
+            cross_split,
+        });
+    }
+    report.removed.sort();
+    report
+}
+
+// ---------------------------------------------------------------------------
+// Filesystem helpers
+// ---------------------------------------------------------------------------
+
+/// Read every pair file below `dir` ([`pair_files_under`]) as a dedup
+/// record, so run bookkeeping is never treated as a pair.
+///
+/// Each file produced by this tool holds exactly one pair, so only the first
+/// line is read.  Files that do not parse as a pair under `schema` are
+/// skipped.
+pub fn read_records(
+    dir: &Path,
+    split: Option<Split>,
+    schema: &OutputSchema,
+) -> io::Result<Vec<DedupRecord>> {
+    let mut records = Vec::new();
+    if !dir.is_dir() {
+        return Ok(records);
+    }
+    for path in pair_files_under(dir)? {
+        let content = fs::read_to_string(&path)?;
+        let Some(line) = content.lines().next() else {
+            continue;
+        };
+        if let Some(pair) = schema.parse_line(line) {
+            records.push(DedupRecord {
+                id: path.to_string_lossy().into_owned(),
+                split,
+                prompt: pair.prompt,
+            });
+        }
+    }
+    Ok(records)
+}
+
+/// Sibling directory that receives records removed as duplicates, mirroring
+/// the `<name>_blanked` convention: `<parent>/<name>_duplicates`.
+pub fn duplicates_subdir_of(jsonl_output_dir: &Path) -> PathBuf {
+    let parent = jsonl_output_dir.parent().unwrap_or(Path::new("."));
+    let dir_name = jsonl_output_dir
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("jsonl");
+    parent.join(format!("{}_duplicates", dir_name))
+}
+
+/// Move every record listed in `report.removed` into the `_duplicates`
+/// sibling of the directory in `dirs` it was read from, keeping its path
+/// below that directory.
+pub fn move_removed(report: &DedupReport, dirs: &[PathBuf]) -> io::Result<()> {
+    for id in &report.removed {
+        let path = Path::new(id);
+        let Some((dir, relative)) = dirs
+            .iter()
+            .filter_map(|d| Some((d.as_path(), path.strip_prefix(d).ok()?)))
+            .max_by_key(|(d, _)| d.as_os_str().len())
+            .or_else(|| Some((path.parent()?, Path::new(path.file_name()?))))
+        else {
+            continue;
+        };
+        let target = duplicates_subdir_of(dir).join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(path, target)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rec(id: &str, split: Option<Split>, prompt: &str) -> DedupRecord {
+        DedupRecord {
+            id: id.to_string(),
+            split,
+            prompt: prompt.to_string(),
+        }
+    }
+
+    const BODY: &str = "@Test public void func_1() { Foo var_1 = new Foo(); var_1.setBar(1); \
+        assertEquals(1, var_1.getBar()); var_1.reset(); assertEquals(0, var_1.getBar()); \
+        assertNotNull(var_1.toString()); assertTrue(var_1.isValid()); }";
+
+    #[test]
+    fn exact_duplicates_ignore_class_name_and_whitespace() {
+        let a = format!("public class TestClass1 {{\n{BODY}\n}}");
+        let b = format!("public class TestClass2 {{ {BODY} }}");
+        let records = vec![rec("a", None, &a), rec("b", None, &b)];
+        let report = find_duplicates(&records, &DedupConfig::default());
+
+        assert_eq!(report.exact_duplicates, 1);
+        assert_eq!(report.near_duplicates, 0);
+        assert_eq!(report.removed, vec!["b".to_string()]);
+        assert_eq!(report.clusters[0].kind, DuplicateKind::Exact);
+    }
+
+    #[test]
+    fn near_duplicates_are_clustered() {
+        let a = format!("public class TestClass1 {{ {BODY} }}");
+        let b = a.replace("setBar(1)", "setBar(2)");
+        let records = vec![rec("a", None, &a), rec("b", None, &b)];
+        let report = find_duplicates(&records, &DedupConfig::default());
+
+        assert_eq!(report.near_duplicates, 1, "{report:?}");
+        assert_eq!(report.clusters[0].kind, DuplicateKind::Near);
+    }
+
+    #[test]
+    fn distinct_records_are_kept() {
+        let records = vec![
+            rec("a", None, "class A { void func_1() { int var_1 = 1; } }"),
+            rec(
+                "b",
+                None,
+                "class B { void func_1() { String var_1 = foo(); bar(var_1, var_1); } }",
+            ),
+        ];
+        let report = find_duplicates(&records, &DedupConfig::default());
+        assert!(report.clusters.is_empty());
+        assert!(report.removed.is_empty());
+    }
+
+    #[test]
+    fn cross_split_leakage_is_reported_and_train_preferred() {
+        let a = format!("public class TestClass1 {{ {BODY} }}");
+        let records = vec![
+            rec("a_test", Some(Split::Test), &a),
+            rec("b_train", Some(Split::Train), &a),
+        ];
+        let config = DedupConfig {
+            policy: DedupPolicy::PreferTrain,
+            ..DedupConfig::default()
+        };
+        let report = find_duplicates(&records, &config);
+
+        assert_eq!(report.cross_split_clusters, 1);
+        assert!(report.clusters[0].cross_split);
+        assert_eq!(report.clusters[0].representative, "b_train");
+        assert_eq!(report.removed, vec!["a_test".to_string()]);
+    }
+
+    #[test]
+    fn duplicates_subdir_of_appends_suffix() {
+        assert_eq!(
+            duplicates_subdir_of(Path::new("out/jsonl/train")),
+            PathBuf::from("out/jsonl/train_duplicates")
+        );
+    }
+}
@@ -0,0 +1,310 @@
+//! Detection and transcoding of the text encoding of input sources.
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::Utf8Error;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Text encoding of an input source.
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ValueEnum,
+)]
+pub enum Encoding {
+    #[serde(rename = "utf-8")]
+    #[value(name = "utf-8")]
+    Utf8,
+    #[serde(rename = "utf-16le")]
+    #[value(name = "utf-16le")]
+    Utf16Le,
+    #[serde(rename = "utf-16be")]
+    #[value(name = "utf-16be")]
+    Utf16Be,
+    /// ISO-8859-1: every byte is the code point of the same value.
+    #[serde(rename = "iso-8859-1")]
+    #[value(name = "iso-8859-1")]
+    Latin1,
+    /// Latin-1 with printable characters in `0x80..=0x9F`.
+    #[serde(rename = "windows-1252")]
+    #[value(name = "windows-1252")]
+    Windows1252,
+}
+
+impl Encoding {
+    /// Name used in the manifest and the summary.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "iso-8859-1",
+            Encoding::Windows1252 => "windows-1252",
+        }
+    }
+
+    /// Decode `bytes`, which carry no byte order mark.
+    fn decode(self, bytes: &[u8]) -> Result<String, DecodeError> {
+        match self {
+            Encoding::Utf8 => std::str::from_utf8(bytes)
+                .map(str::to_string)
+                .map_err(DecodeError::Utf8),
+            Encoding::Utf16Le => decode_utf16(bytes, self, u16::from_le_bytes),
+            Encoding::Utf16Be => decode_utf16(bytes, self, u16::from_be_bytes),
+            Encoding::Latin1 => Ok(bytes.iter().map(|&b| char::from(b)).collect()),
+            Encoding::Windows1252 => Ok(bytes.iter().map(|&b| windows_1252(b)).collect()),
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Why a source could not be decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    Utf8(Utf8Error),
+    /// A UTF-16 source with an odd number of bytes.
+    OddLength(Encoding),
+    /// A UTF-16 code unit that is half of no surrogate pair, at byte
+    /// `offset` after any byte order mark.
+    UnpairedSurrogate {
+        encoding: Encoding,
+        offset: usize,
+    },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Utf8(e) => write!(f, "invalid UTF-8: {}", e),
+            DecodeError::OddLength(encoding) => {
+                write!(f, "invalid {}: odd number of bytes", encoding)
+            }
+            DecodeError::UnpairedSurrogate { encoding, offset } => write!(
+                f,
+                "invalid {}: unpaired surrogate at byte {}",
+                encoding, offset
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for io::Error {
+    fn from(e: DecodeError) -> Self {
+        io::Error::new(io::ErrorKind::InvalidData, e)
+    }
+}
+
+/// A source decoded to UTF-8.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoded {
+    /// The text, without its byte order mark.
+    pub text: String,
+    pub encoding: Encoding,
+    /// The source started with a byte order mark, which was stripped.
+    pub bom: bool,
+}
+
+/// Decode a source to UTF-8.
+///
+/// A byte order mark decides the encoding.  Without one, `configured` is
+/// used when set; otherwise the source is taken as UTF-16 when every other
+/// byte is mostly zero, as UTF-8 when it is valid UTF-8, and as Windows-1252
+/// otherwise.
+pub fn decode(bytes: &[u8], configured: Option<Encoding>) -> Result<Decoded, DecodeError> {
+    let boms: [(&[u8], Encoding); 3] = [
+        (b"\xEF\xBB\xBF", Encoding::Utf8),
+        (b"\xFF\xFE", Encoding::Utf16Le),
+        (b"\xFE\xFF", Encoding::Utf16Be),
+    ];
+    if let Some((bom, encoding)) = boms.iter().find(|(bom, _)| bytes.starts_with(bom)) {
+        return Ok(Decoded {
+            text: encoding.decode(&bytes[bom.len()..])?,
+            encoding: *encoding,
+            bom: true,
+        });
+    }
+
+    let encoding = configured
+        .or_else(|| utf16_without_bom(bytes))
+        .unwrap_or_else(|| match std::str::from_utf8(bytes) {
+            Ok(_) => Encoding::Utf8,
+            Err(_) => Encoding::Windows1252,
+        });
+    Ok(Decoded {
+        text: encoding.decode(bytes)?,
+        encoding,
+        bom: false,
+    })
+}
+
+/// [`decode`] the file at `path`.
+pub fn read_source(path: &Path, configured: Option<Encoding>) -> io::Result<Decoded> {
+    Ok(decode(&fs::read(path)?, configured)?)
+}
+
+/// UTF-16 byte order of a source without a byte order mark: mostly ASCII
+/// text leaves the high byte of most code units zero, and the low byte of
+/// hardly any.
+fn utf16_without_bom(bytes: &[u8]) -> Option<Encoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {
+        return None;
+    }
+    let units = bytes.len() / 2;
+    let zeros = |parity: usize| {
+        bytes
+            .iter()
+            .skip(parity)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    let (even, odd) = (zeros(0), zeros(1));
+    if odd * 2 > units && even * 10 < units {
+        Some(Encoding::Utf16Le)
+    } else if even * 2 > units && odd * 10 < units {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+fn decode_utf16(
+    bytes: &[u8],
+    encoding: Encoding,
+    unit: fn([u8; 2]) -> u16,
+) -> Result<String, DecodeError> {
+    if !bytes.len().is_multiple_of(2) {
+        return Err(DecodeError::OddLength(encoding));
+    }
+    let units = bytes.chunks_exact(2).map(|c| unit([c[0], c[1]]));
+    let mut text = String::with_capacity(bytes.len() / 2);
+    let mut offset = 0;
+    for c in char::decode_utf16(units) {
+        let c = c.map_err(|_| DecodeError::UnpairedSurrogate { encoding, offset })?;
+        offset += 2 * c.len_utf16();
+        text.push(c);
+    }
+    Ok(text)
+}
+
+/// Characters of the Windows-1252 bytes `0x80..=0x9F`.  The five bytes it
+/// leaves undefined map to the C1 control of the same value, as in Latin-1.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2C6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8D}', '\u{17D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2DC}', '\u{2122}', '\u{161}', '\u{203A}', '\u{153}', '\u{9D}', '\u{17E}', '\u{178}',
+];
+
+/// The character of a Windows-1252 byte.
+fn windows_1252(b: u8) -> char {
+    match b {
+        0x80..=0x9F => WINDOWS_1252_HIGH[usize::from(b - 0x80)],
+        _ => char::from(b),
+    }
+}
+
+/// The non-ASCII byte that `c` decodes from in Latin-1 or Windows-1252.
+pub(crate) fn single_byte_of(c: char) -> Option<u8> {
+    match u8::try_from(c) {
+        Ok(b) if b >= 0x80 => Some(b),
+        Ok(_) => None,
+        Err(_) => WINDOWS_1252_HIGH
+            .iter()
+            .position(|&h| h == c)
+            .map(|i| 0x80 + i as u8),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "class T { String s = \"héllo €\"; }";
+
+    fn utf16(text: &str, unit: fn(u16) -> [u8; 2]) -> Vec<u8> {
+        text.encode_utf16().flat_map(unit).collect()
+    }
+
+    #[test]
+    fn utf8_is_kept_and_its_bom_stripped() {
+        let decoded = decode(SOURCE.as_bytes(), None).unwrap();
+        assert_eq!(
+            (decoded.text.as_str(), decoded.encoding, decoded.bom),
+            (SOURCE, Encoding::Utf8, false)
+        );
+
+        let with_bom = [b"\xEF\xBB\xBF", SOURCE.as_bytes()].concat();
+        let decoded = decode(&with_bom, Some(Encoding::Latin1)).unwrap();
+        assert_eq!(
+            (decoded.text.as_str(), decoded.encoding, decoded.bom),
+            (SOURCE, Encoding::Utf8, true)
+        );
+    }
+
+    #[test]
+    fn utf16_is_detected_with_and_without_bom() {
+        let le = utf16(SOURCE, u16::to_le_bytes);
+        let be = utf16(SOURCE, u16::to_be_bytes);
+        for (bytes, encoding) in [(le, Encoding::Utf16Le), (be, Encoding::Utf16Be)] {
+            let decoded = decode(&bytes, None).unwrap();
+            assert_eq!(
+                (decoded.text.as_str(), decoded.encoding),
+                (SOURCE, encoding)
+            );
+            assert!(!decoded.bom);
+
+            let bom = utf16(
+                "\u{FEFF}",
+                match encoding {
+                    Encoding::Utf16Le => u16::to_le_bytes,
+                    _ => u16::to_be_bytes,
+                },
+            );
+            let decoded = decode(&[bom, bytes].concat(), None).unwrap();
+            assert_eq!((decoded.text.as_str(), decoded.bom), (SOURCE, true));
+        }
+    }
+
+    #[test]
+    fn invalid_utf8_is_transcoded_from_a_single_byte_encoding() {
+        // "héllo €" in Windows-1252: é = 0xE9, € = 0x80.
+        let bytes = b"class T { String s = \"h\xE9llo \x80\"; }";
+        let decoded = decode(bytes, None).unwrap();
+        assert_eq!(
+            (decoded.text.as_str(), decoded.encoding),
+            (SOURCE, Encoding::Windows1252)
+        );
+
+        let latin1 = decode(bytes, Some(Encoding::Latin1)).unwrap();
+        assert_eq!(latin1.text, SOURCE.replace('€', "\u{80}"));
+        assert!(matches!(
+            decode(bytes, Some(Encoding::Utf8)),
+            Err(DecodeError::Utf8(_))
+        ));
+    }
+
+    #[test]
+    fn broken_utf16_is_an_error() {
+        assert_eq!(
+            decode(b"\xFF\xFEa", None),
+            Err(DecodeError::OddLength(Encoding::Utf16Le))
+        );
+        assert_eq!(
+            decode(b"\xFF\xFEa\x00\x00\xD8b\x00", None),
+            Err(DecodeError::UnpairedSurrogate {
+                encoding: Encoding::Utf16Le,
+                offset: 2
+            })
+        );
+    }
+}
@@ -0,0 +1,253 @@
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::error_log::ErrorCategory;
+use crate::obfuscator::{LimitExceeded, SourcePosition};
+
+/// Errors returned by the library.
+///
+/// `label` names the source or pair an error concerns (an output path or an
+/// input key); it is empty when the caller passed an anonymous string.
+#[derive(Debug)]
+pub enum Error {
+    /// tree-sitter produced no usable tree for the source.
+    Parse {
+        label: String,
+        position: Option<SourcePosition>,
+    },
+    /// Prompt and response have different structural token counts.
+    TokenMismatch {
+        label: String,
+        prompt: usize,
+        response: usize,
+    },
+    /// Obfuscation produced an empty prompt.
+    EmptyOutput {
+        label: String,
+    },
+    /// Not every literal placeholder could be restored after renaming.
+    LiteralStoreMismatch {
+        label: String,
+        expected: usize,
+        restored: usize,
+    },
+    /// The source exceeds a per-file resource limit.
+    LimitExceeded {
+        label: String,
+        limit: LimitExceeded,
+    },
+    /// A strict run stopped at the first file that failed.
+    Aborted {
+        label: String,
+        stage: &'static str,
+        category: ErrorCategory,
+        message: String,
+    },
+    /// An output path does not have the required extension.
+    InvalidExtension {
+        path: PathBuf,
+        expected: &'static str,
+    },
+    /// A JSON document (configuration, JSONL record or report) could not be
+    /// parsed or serialized.
+    Json(serde_json::Error),
+    Io(io::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// Attach `label` to an error that does not name its source yet.
+    pub fn with_label(mut self, new_label: &str) -> Self {
+        match &mut self {
+            Error::Parse { label, .. }
+            | Error::TokenMismatch { label, .. }
+            | Error::EmptyOutput { label }
+            | Error::LiteralStoreMismatch { label, .. }
+            | Error::LimitExceeded { label, .. }
+                if label.is_empty() =>
+            {
+                *label = new_label.to_string();
+            }
+            _ => {}
+        }
+        self
+    }
+
+    /// Where a parse failure was found, when known.
+    pub fn position(&self) -> Option<SourcePosition> {
+        match self {
+            Error::Parse { position, .. } => *position,
+            _ => None,
+        }
+    }
+
+    /// Error-log category of this error.
+    pub fn category(&self) -> ErrorCategory {
+        match self {
+            Error::Parse { .. } => ErrorCategory::Parse,
+            Error::TokenMismatch { .. } => ErrorCategory::TokenMismatch,
+            Error::EmptyOutput { .. } => ErrorCategory::Empty,
+            Error::LiteralStoreMismatch { .. } => ErrorCategory::LiteralStoreMismatch,
+            Error::LimitExceeded { .. } => ErrorCategory::Limit,
+            Error::Aborted { category, .. } => *category,
+            Error::Json(_) => ErrorCategory::Json,
+            Error::InvalidExtension { .. } | Error::Io(_) => ErrorCategory::Write,
+        }
+    }
+}
+
+/// ` in <label>`, or nothing for an anonymous source.
+struct In<'a>(&'a str);
+
+impl fmt::Display for In<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            write!(f, " in {}", self.0)
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse { label, position } => {
+                write!(f, "parse failure{}", In(label))?;
+                if let Some(p) = position {
+                    write!(f, " at line {}, column {}", p.line, p.column)?;
+                }
+                Ok(())
+            }
+            Error::TokenMismatch {
+                label,
+                prompt,
+                response,
+            } => write!(
+                f,
+                "token count mismatch{}: prompt={} response={}; a multi-byte \
+                 character was probably corrupted (e.g. é → Ã©)",
+                In(label),
+                prompt,
+                response
+            ),
+            Error::EmptyOutput { label } => write!(f, "obfuscated source is empty{}", In(label)),
+            Error::LiteralStoreMismatch {
+                label,
+                expected,
+                restored,
+            } => write!(
+                f,
+                "literal store mismatch{}: restored {} of {} literals",
+                In(label),
+                restored,
+                expected
+            ),
+            Error::LimitExceeded { label, limit } => {
+                write!(f, "resource limit exceeded{}: {}", In(label), limit)
+            }
+            Error::Aborted {
+                label,
+                stage,
+                message,
+                ..
+            } => write!(
+                f,
+                "aborted (strict mode){} at stage {}: {}",
+                In(label),
+                stage,
+                message
+            ),
+            Error::InvalidExtension { path, expected } => write!(
+                f,
+                "output file {} must have a .{} extension",
+                path.display(),
+                expected
+            ),
+            Error::Json(e) => write!(f, "invalid JSON: {}", e),
+            Error::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Json(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Json(e)
+    }
+}
+
+/// Lets binaries and `io::Result` callers keep using `?`.
+impl From<Error> for io::Error {
+    fn from(e: Error) -> Self {
+        match e {
+            Error::Io(e) => e,
+            Error::InvalidExtension { .. } => io::Error::new(io::ErrorKind::InvalidInput, e),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn with_label_fills_only_an_empty_label() {
+        let e = Error::EmptyOutput {
+            label: String::new(),
+        }
+        .with_label("a/T.java");
+        assert_eq!(e.to_string(), "obfuscated source is empty in a/T.java");
+
+        let e = Error::EmptyOutput {
+            label: "x.jsonl".to_string(),
+        }
+        .with_label("a/T.java");
+        assert_eq!(e.to_string(), "obfuscated source is empty in x.jsonl");
+    }
+
+    #[test]
+    fn categories_and_io_conversion() {
+        let mismatch = Error::TokenMismatch {
+            label: "p".to_string(),
+            prompt: 3,
+            response: 4,
+        };
+        assert_eq!(mismatch.category(), ErrorCategory::TokenMismatch);
+        assert!(mismatch.to_string().contains("prompt=3 response=4"));
+        assert!(!mismatch.to_string().contains('\n'));
+
+        let io: io::Error = mismatch.into();
+        assert_eq!(io.kind(), io::ErrorKind::InvalidData);
+
+        let json = Error::from(serde_json::from_str::<u32>("{").unwrap_err());
+        assert_eq!(json.category(), ErrorCategory::Json);
+        assert!(json.to_string().starts_with("invalid JSON: "));
+        assert_eq!(io::Error::from(json).kind(), io::ErrorKind::InvalidData);
+
+        let denied = Error::from(io::Error::from(io::ErrorKind::PermissionDenied));
+        assert_eq!(denied.category(), ErrorCategory::Write);
+        assert_eq!(
+            io::Error::from(denied).kind(),
+            io::ErrorKind::PermissionDenied
+        );
+    }
+}
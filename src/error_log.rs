@@ -0,0 +1,191 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::manifest::input_key;
+use crate::obfuscator::SourcePosition;
+
+/// File name of the structured error log inside the JSONL output directory.
+pub const ERROR_LOG_FILE: &str = "errors.jsonl";
+
+/// Coarse classification of a per-file failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCategory {
+    /// The file could not be read.
+    Read,
+    /// The file could not be decoded: invalid UTF-8 under a configured
+    /// `utf-8` encoding, or broken UTF-16.
+    Utf8,
+    /// The source does not parse cleanly.
+    Parse,
+    /// Prompt and response have different structural token counts.
+    TokenMismatch,
+    /// Obfuscation produced an empty result.
+    Empty,
+    /// Literal placeholders were lost between blanking and restoring.
+    LiteralStoreMismatch,
+    /// The source exceeds a per-file resource limit.
+    Limit,
+    /// Converting the file panicked; the message is the panic's.
+    Panic,
+    /// A JSON document (configuration or JSONL record) could not be parsed
+    /// or serialized.
+    Json,
+    /// An output could not be written.
+    Write,
+}
+
+/// One line of the error log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorRecord {
+    pub file: PathBuf,
+    /// Input key, relative to the input root as in the manifest.  Absent in
+    /// logs written by older versions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub input: Option<String>,
+    pub stage: String,
+    pub category: ErrorCategory,
+    pub message: String,
+    /// Content hash of the input, when it could be read.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_hash: Option<String>,
+    /// First tree-sitter ERROR / MISSING node in the sanitized source.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parse_error: Option<SourcePosition>,
+}
+
+/// JSONL error log of one run, shared by the worker threads.
+pub struct ErrorLog {
+    writer: Mutex<BufWriter<File>>,
+}
+
+impl ErrorLog {
+    /// Create or truncate the log at `path`, so it lists the failures of
+    /// this run only: a file fixed since an earlier run is not retried.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(ErrorLog {
+            writer: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Append `record` as one line.  The mutex keeps lines from parallel
+    /// threads from interleaving; each line is flushed immediately so the log
+    /// is complete even if the run is killed.
+    pub fn append(&self, record: &ErrorRecord) -> io::Result<()> {
+        let line = serde_json::to_string(record)?;
+        let mut writer = self.writer.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(writer, "{}", line)?;
+        writer.flush()
+    }
+}
+
+/// Read every record of an error log.  Unparseable lines are skipped.
+pub fn read_error_log(path: &Path) -> io::Result<Vec<ErrorRecord>> {
+    let mut records = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        if let Ok(record) = serde_json::from_str::<ErrorRecord>(&line?) {
+            records.push(record);
+        }
+    }
+    Ok(records)
+}
+
+/// The distinct input keys listed in an error log, in first-seen order.
+/// Records without a key are keyed by their file relative to `input_root`.
+pub fn failed_inputs(path: &Path, input_root: &Path) -> io::Result<Vec<String>> {
+    let mut keys: Vec<String> = Vec::new();
+    for record in read_error_log(path)? {
+        let key = record
+            .input
+            .unwrap_or_else(|| input_key(input_root, &record.file));
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn record(file: &str, category: ErrorCategory) -> ErrorRecord {
+        ErrorRecord {
+            file: PathBuf::from(file),
+            input: Some(file.to_string()),
+            stage: "obfuscate".to_string(),
+            category,
+            message: "boom".to_string(),
+            file_hash: Some("abc".to_string()),
+            parse_error: None,
+        }
+    }
+
+    #[test]
+    fn records_round_trip_and_failed_inputs_are_deduplicated() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(ERROR_LOG_FILE);
+        let log = ErrorLog::open(&path).unwrap();
+        log.append(&record("a.java", ErrorCategory::Parse)).unwrap();
+        log.append(&record("b.java", ErrorCategory::Utf8)).unwrap();
+        log.append(&record("a.java", ErrorCategory::Write)).unwrap();
+
+        let records = read_error_log(&path).unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[1], record("b.java", ErrorCategory::Utf8));
+        assert_eq!(
+            failed_inputs(&path, Path::new("in")).unwrap(),
+            vec!["a.java".to_string(), "b.java".to_string()]
+        );
+    }
+
+    #[test]
+    fn reopening_starts_a_new_log() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(ERROR_LOG_FILE);
+        let log = ErrorLog::open(&path).unwrap();
+        log.append(&record("fixed.java", ErrorCategory::Parse))
+            .unwrap();
+        drop(log);
+
+        let log = ErrorLog::open(&path).unwrap();
+        log.append(&record("b.java", ErrorCategory::Parse)).unwrap();
+        assert_eq!(
+            failed_inputs(&path, Path::new("in")).unwrap(),
+            vec!["b.java".to_string()]
+        );
+    }
+
+    #[test]
+    fn records_without_a_key_are_keyed_relative_to_the_input_root() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(ERROR_LOG_FILE);
+        let log = ErrorLog::open(&path).unwrap();
+        let legacy = ErrorRecord {
+            input: None,
+            ..record("./in/a/T.java", ErrorCategory::Parse)
+        };
+        log.append(&legacy).unwrap();
+
+        assert_eq!(
+            failed_inputs(&path, Path::new("./in")).unwrap(),
+            vec!["a/T.java".to_string()]
+        );
+    }
+
+    #[test]
+    fn category_is_serialized_snake_case() {
+        let json = serde_json::to_string(&record("a.java", ErrorCategory::TokenMismatch)).unwrap();
+        assert!(json.contains("\"category\":\"token_mismatch\""), "{json}");
+        assert!(
+            !json.contains("parse_error"),
+            "absent position must be omitted"
+        );
+    }
+}
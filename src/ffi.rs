@@ -0,0 +1,256 @@
+//! C ABI of the shared library, for callers such as the ctypes wrapper in
+//! `python/java_dataset_converter_llm.py`.
+//!
+//! Every conversion function takes a NUL-terminated UTF-8 source and writes
+//! an owned string to `*out`: the result on [`JDC_OK`], the error message
+//! otherwise.  Strings written to `*out` must be released with
+//! [`jdc_string_free`].  Panics never cross the boundary; they are reported
+//! as [`JDC_PANIC`].
+
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+
+use crate::commands::{FilterStage, filter_source};
+use crate::config::Config;
+use crate::helper::panic_message;
+use crate::obfuscator::obfuscate_str_checked;
+use crate::sanitizer::sanitize_structural;
+
+/// Incremented whenever a signature or status code changes meaning.
+pub const JDC_ABI_VERSION: u32 = 1;
+
+pub const JDC_OK: c_int = 0;
+/// A pointer argument was null or the source was not valid UTF-8.
+pub const JDC_INVALID_ARGUMENT: c_int = 1;
+/// The source could not be converted; `*out` holds the reason.
+pub const JDC_CONVERSION_FAILED: c_int = 2;
+/// The library panicked; `*out` holds the panic message.
+pub const JDC_PANIC: c_int = 3;
+
+/// Version of this ABI, so callers can refuse an incompatible library.
+#[unsafe(no_mangle)]
+pub extern "C" fn jdc_abi_version() -> u32 {
+    JDC_ABI_VERSION
+}
+
+/// Hand `text` to the caller.  Interior NUL bytes cannot cross a C string
+/// boundary and are reported instead.
+fn into_raw(text: String) -> Result<*mut c_char, String> {
+    CString::new(text)
+        .map(CString::into_raw)
+        .map_err(|e| format!("output contains a NUL byte at {}", e.nul_position()))
+}
+
+/// Run `f` on the source at `src` and store its result or error in `*out`.
+///
+/// # Safety
+///
+/// `src` must be null or a NUL-terminated string; `out` must be null or
+/// valid for a pointer write.
+unsafe fn call(
+    src: *const c_char,
+    out: *mut *mut c_char,
+    f: impl FnOnce(&str) -> Result<String, String>,
+) -> c_int {
+    if out.is_null() {
+        return JDC_INVALID_ARGUMENT;
+    }
+    // SAFETY: `out` is non-null and writable per the caller's contract.
+    unsafe { *out = ptr::null_mut() };
+    if src.is_null() {
+        return JDC_INVALID_ARGUMENT;
+    }
+    // SAFETY: `src` is a non-null, NUL-terminated string per the contract.
+    let (status, text) = match unsafe { CStr::from_ptr(src) }.to_str() {
+        Err(e) => (JDC_INVALID_ARGUMENT, format!("source is not UTF-8: {}", e)),
+        Ok(src) => match panic::catch_unwind(AssertUnwindSafe(|| f(src))) {
+            Ok(Ok(text)) => (JDC_OK, text),
+            Ok(Err(message)) => (JDC_CONVERSION_FAILED, message),
+            Err(payload) => (JDC_PANIC, panic_message(payload.as_ref())),
+        },
+    };
+    let (status, raw) = match into_raw(text) {
+        Ok(raw) => (status, raw),
+        Err(message) => (
+            JDC_CONVERSION_FAILED,
+            into_raw(message).expect("message has no NUL"),
+        ),
+    };
+    // SAFETY: as above.
+    unsafe { *out = raw };
+    status
+}
+
+/// Store `value` in `*flag` when the caller asked for it.
+///
+/// # Safety
+///
+/// `flag` must be null or valid for a write.
+unsafe fn set_flag(flag: *mut bool, value: bool) {
+    if !flag.is_null() {
+        // SAFETY: non-null and writable per the caller's contract.
+        unsafe { *flag = value };
+    }
+}
+
+/// `sanitize_structural` of `src`.
+///
+/// # Safety
+///
+/// `src` must be null or a NUL-terminated string, and `out` null or valid
+/// for a pointer write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn jdc_sanitize_structural(
+    src: *const c_char,
+    out: *mut *mut c_char,
+) -> c_int {
+    // SAFETY: forwarded from this function's contract.
+    unsafe { call(src, out, |src| Ok(sanitize_structural(src))) }
+}
+
+/// `obfuscate_str_checked` of `src`, which should already be sanitized.
+/// `*fallback` is set when the literal-blanker fallback was needed; it may be
+/// null.
+///
+/// # Safety
+///
+/// `src` must be null or a NUL-terminated string, and `out` and `fallback`
+/// null or valid for a write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn jdc_obfuscate_str_checked(
+    src: *const c_char,
+    out: *mut *mut c_char,
+    fallback: *mut bool,
+) -> c_int {
+    let mut needed_fallback = false;
+    // SAFETY: forwarded from this function's contract.
+    let status = unsafe {
+        call(src, out, |src| {
+            let (obfuscated, f) = obfuscate_str_checked(src).map_err(|e| e.to_string())?;
+            needed_fallback = f;
+            Ok(obfuscated)
+        })
+    };
+    // SAFETY: forwarded from this function's contract.
+    unsafe { set_flag(fallback, needed_fallback) };
+    status
+}
+
+/// The JSONL record the pipeline writes for the raw source `src`, without
+/// the trailing newline.  `*fallback` is set as for
+/// [`jdc_obfuscate_str_checked`].
+///
+/// # Safety
+///
+/// `src` must be null or a NUL-terminated string, and `out` and `fallback`
+/// null or valid for a write.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn jdc_jsonl_record(
+    src: *const c_char,
+    out: *mut *mut c_char,
+    fallback: *mut bool,
+) -> c_int {
+    let mut needed_fallback = false;
+    // SAFETY: forwarded from this function's contract.
+    let status = unsafe {
+        call(src, out, |src| {
+            let output = filter_source(src, FilterStage::Jsonl, &Config::default(), "")
+                .map_err(|e| e.to_string())?;
+            needed_fallback = output.fallback;
+            Ok(output.text.trim_end().to_string())
+        })
+    };
+    // SAFETY: forwarded from this function's contract.
+    unsafe { set_flag(fallback, needed_fallback) };
+    status
+}
+
+/// Release a string returned by this library.  Null is ignored.
+///
+/// # Safety
+///
+/// `s` must be null or a pointer written to `*out` by this library that has
+/// not been freed yet.
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn jdc_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        // SAFETY: `s` came from `CString::into_raw` per the contract.
+        drop(unsafe { CString::from_raw(s) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::processor::OutputSchema;
+
+    const SOURCE: &str = "public class T { @Test public void testFoo() { String s = \"hi\"; assertEquals(\"hi\", s); } }";
+
+    /// Call an ABI function the way a C caller would and take back the
+    /// status, the owned string and the fallback flag.
+    fn call_abi(
+        f: unsafe extern "C" fn(*const c_char, *mut *mut c_char, *mut bool) -> c_int,
+        src: &CStr,
+    ) -> (c_int, String, bool) {
+        let mut out = ptr::null_mut();
+        let mut fallback = true;
+        let status = unsafe { f(src.as_ptr(), &mut out, &mut fallback) };
+        assert!(!out.is_null());
+        let text = unsafe { CStr::from_ptr(out) }.to_str().unwrap().to_string();
+        unsafe { jdc_string_free(out) };
+        (status, text, fallback)
+    }
+
+    #[test]
+    fn conversions_return_owned_strings() {
+        let src = CString::new(SOURCE).unwrap();
+        let mut out = ptr::null_mut();
+        assert_eq!(
+            unsafe { jdc_sanitize_structural(src.as_ptr(), &mut out) },
+            JDC_OK
+        );
+        assert_eq!(
+            unsafe { CStr::from_ptr(out) }.to_str().unwrap(),
+            sanitize_structural(SOURCE)
+        );
+        unsafe { jdc_string_free(out) };
+
+        let (status, obfuscated, fallback) = call_abi(jdc_obfuscate_str_checked, &src);
+        assert_eq!((status, fallback), (JDC_OK, false));
+        assert!(obfuscated.contains("func_1") && obfuscated.contains("\"hi\""));
+
+        let (status, line, fallback) = call_abi(jdc_jsonl_record, &src);
+        assert_eq!((status, fallback), (JDC_OK, false));
+        let pair = OutputSchema::default().parse_line(&line).unwrap();
+        assert_eq!(pair.response, sanitize_structural(SOURCE));
+    }
+
+    #[test]
+    fn bad_arguments_and_failures_report_status() {
+        let mut out = ptr::null_mut();
+        assert_eq!(
+            unsafe { jdc_sanitize_structural(ptr::null(), &mut out) },
+            JDC_INVALID_ARGUMENT
+        );
+        assert!(out.is_null());
+        let src = CString::new("class T {}").unwrap();
+        assert_eq!(
+            unsafe { jdc_sanitize_structural(src.as_ptr(), ptr::null_mut()) },
+            JDC_INVALID_ARGUMENT
+        );
+
+        let invalid = CString::new(vec![b'c', 0xff]).unwrap();
+        let (status, message, _) = call_abi(jdc_jsonl_record, &invalid);
+        assert_eq!(status, JDC_INVALID_ARGUMENT);
+        assert!(message.contains("UTF-8"));
+
+        let (status, message, _) = call_abi(jdc_jsonl_record, c"");
+        assert_eq!(status, JDC_CONVERSION_FAILED);
+        assert!(!message.is_empty());
+
+        unsafe { jdc_string_free(ptr::null_mut()) };
+        assert_eq!(jdc_abi_version(), JDC_ABI_VERSION);
+    }
+}
@@ -1,22 +1,204 @@
-use std::fs;
-use std::io;
-use std::path::PathBuf;
+use std::any::Any;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 
+use regex::Regex;
+use walkdir::WalkDir;
+
+/// Collect every file under `dir` (recursively) whose extension matches
+/// `extension` case-insensitively.
+///
+/// The result is sorted so that runs over the same tree always visit files in
+/// the same order.
 pub fn get_files(dir: &str, extension: &str) -> io::Result<Vec<PathBuf>> {
     let mut matching_files = Vec::new();
 
-    for entry in fs::read_dir(dir)? {
-        let entry = entry?;
+    for entry in WalkDir::new(dir).follow_links(true) {
+        let entry = entry.map_err(io::Error::other)?;
         let path = entry.path();
 
-        if path.is_file() {
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                if ext.eq_ignore_ascii_case(extension) {
-                    matching_files.push(path);
-                }
-            }
+        if path.is_file()
+            && let Some(ext) = path.extension().and_then(|e| e.to_str())
+            && ext.eq_ignore_ascii_case(extension)
+        {
+            matching_files.push(path.to_path_buf());
         }
     }
 
+    matching_files.sort();
     Ok(matching_files)
 }
+
+/// 64-bit FNV-1a hash of `bytes`.
+///
+/// Used wherever a hash must be stable across runs, platforms and compiler
+/// versions (split assignment, content fingerprints).  `std`'s `DefaultHasher`
+/// makes no such guarantee.
+pub fn stable_hash(bytes: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+
+    let mut hash = OFFSET;
+    for &b in bytes {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(PRIME);
+    }
+    hash
+}
+
+/// Compile a glob over `/`-separated relative paths into an anchored regex.
+///
+/// `**/` matches any number of leading directories (including none), `**`
+/// anything, `*` anything within one path component and `?` one character
+/// other than `/`.  Everything else matches literally.
+pub fn glob_regex(pattern: &str) -> Regex {
+    let mut re = String::from("^");
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        if let Some(tail) = rest.strip_prefix("**/") {
+            re.push_str("(?:.*/)?");
+            rest = tail;
+        } else if let Some(tail) = rest.strip_prefix("**") {
+            re.push_str(".*");
+            rest = tail;
+        } else {
+            match c {
+                '*' => re.push_str("[^/]*"),
+                '?' => re.push_str("[^/]"),
+                _ => re.push_str(&regex::escape(&c.to_string())),
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    re.push('$');
+    Regex::new(&re).expect("escaped glob is a valid regex")
+}
+
+/// The message of a caught panic, for payloads raised by `panic!` and friends.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+    payload
+        .downcast_ref::<&str>()
+        .map(|s| s.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "panic".to_string())
+}
+
+/// Write `path` atomically.
+///
+/// `write` fills a buffered writer backed by a temporary file in the same
+/// directory; the buffer is flushed and synced, and only then is the file
+/// renamed over `path`, and the directory is synced so the rename itself is
+/// durable.  A crash or error part-way leaves either the previous file or
+/// nothing — never a truncated one — and the temporary file is removed on
+/// error.
+///
+/// On Unix the file gets the mode of the file it replaces, or for a new file
+/// the mode `File::create` would give it (`0o666` less the umask) instead of
+/// the `0o600` of a temporary file.
+pub fn write_atomic<F>(path: &Path, write: F) -> io::Result<()>
+where
+    F: FnOnce(&mut dyn Write) -> io::Result<()>,
+{
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(".tmp-").suffix(".part");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        // The umask applies to the mode passed at creation.
+        builder.permissions(std::fs::Permissions::from_mode(0o666));
+    }
+    let tmp = builder.tempfile_in(dir)?;
+    #[cfg(unix)]
+    if let Ok(existing) = std::fs::metadata(path) {
+        tmp.as_file().set_permissions(existing.permissions())?;
+    }
+    {
+        let mut writer = BufWriter::new(tmp.as_file());
+        write(&mut writer)?;
+        writer.flush()?;
+    }
+    tmp.as_file().sync_all()?;
+    tmp.persist(path).map_err(|e| e.error)?;
+    #[cfg(unix)]
+    std::fs::File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_regex, write_atomic};
+    use std::fs;
+    use std::io;
+    use tempfile::TempDir;
+
+    #[test]
+    fn write_atomic_replaces_file() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("out.jsonl");
+        fs::write(&path, "old").unwrap();
+
+        write_atomic(&path, |w| w.write_all(b"new")).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_atomic_gives_created_mode_or_keeps_existing_one() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |p: &std::path::Path| fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        let dir = TempDir::new().unwrap();
+        let created = dir.path().join("created");
+        fs::write(&created, "").unwrap();
+
+        let new = dir.path().join("new.jsonl");
+        write_atomic(&new, |w| w.write_all(b"new")).unwrap();
+        assert_eq!(mode(&new), mode(&created));
+
+        let existing = dir.path().join("existing.jsonl");
+        fs::write(&existing, "old").unwrap();
+        fs::set_permissions(&existing, fs::Permissions::from_mode(0o640)).unwrap();
+        write_atomic(&existing, |w| w.write_all(b"new")).unwrap();
+        assert_eq!(mode(&existing), 0o640);
+    }
+
+    #[test]
+    fn write_atomic_error_keeps_previous_file_and_no_temp() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("out.jsonl");
+        fs::write(&path, "old").unwrap();
+
+        let result = write_atomic(&path, |w| {
+            w.write_all(b"partial")?;
+            Err(io::Error::other("interrupted"))
+        });
+        assert!(result.is_err());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+        assert_eq!(
+            fs::read_dir(dir.path()).unwrap().count(),
+            1,
+            "temp file must be removed"
+        );
+    }
+
+    #[test]
+    fn glob_regex_matches_path_components() {
+        let java = glob_regex("**/*.java");
+        assert!(java.is_match("T.java"));
+        assert!(java.is_match("a/b/T.java"));
+        assert!(!java.is_match("a/T.kt"));
+
+        let generated = glob_regex("generated/**");
+        assert!(generated.is_match("generated/a/T.java"));
+        assert!(!generated.is_match("src/generated/T.java"));
+
+        let one_level = glob_regex("src/*.java");
+        assert!(one_level.is_match("src/T.java"));
+        assert!(!one_level.is_match("src/a/T.java"));
+        assert!(glob_regex("T?.java").is_match("T1.java"));
+        assert!(!glob_regex("a.b").is_match("axb"));
+    }
+}
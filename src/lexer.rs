@@ -0,0 +1,541 @@
+//! Java lexer shared by the literal blanker and the sanitizer.
+//!
+//! It recognises only what those passes need to agree on: comments, string,
+//! text-block and char literals, identifiers and numbers.  Every other
+//! character, whitespace included, is a one-character [`TokenKind::Other`]
+//! token, so concatenating the tokens of a source gives back the source.
+//!
+//! String scanning carries the dataset corruption-recovery heuristic (see
+//! [`Recovery`]); because the blanker and the sanitizer both lex through
+//! here, they always see the same literals.
+
+use std::ops::Range;
+
+/// What a [`Token`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// `// …` up to, not including, the line break.
+    LineComment,
+    /// `/* … */`, or to the end of input when unterminated.
+    BlockComment,
+    /// `"…"`, or to the end of input when unterminated.
+    String,
+    /// Java 15+ `"""` text block.
+    TextBlock,
+    /// `'x'` or `'\x'`.  A `'` that does not start one is [`TokenKind::Other`].
+    Char,
+    Identifier,
+    /// A digit followed by letters, digits, `_` and `.` (`0x1F`, `1_000L`, `2.5f`).
+    Number,
+    /// Any other single character.
+    Other,
+}
+
+/// A token: its kind and byte range in the source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+    /// Whether a literal or block comment reached its closing delimiter.
+    /// Always true for other kinds.
+    pub closed: bool,
+}
+
+impl Token {
+    /// A string literal or text block.
+    pub fn is_string(&self) -> bool {
+        matches!(self.kind, TokenKind::String | TokenKind::TextBlock)
+    }
+
+    /// A string, text-block or char literal.
+    pub fn is_literal(&self) -> bool {
+        self.is_string() || self.kind == TokenKind::Char
+    }
+
+    pub fn text<'a>(&self, src: &'a str) -> &'a str {
+        &src[self.start..self.end]
+    }
+
+    /// Byte range of a literal's content, without its delimiters.  For other
+    /// kinds this is the whole token.
+    pub fn content(&self) -> Range<usize> {
+        let quotes = match self.kind {
+            TokenKind::TextBlock => 3,
+            TokenKind::String | TokenKind::Char => 1,
+            _ => 0,
+        };
+        let start = self.start + quotes;
+        let end = if self.closed {
+            self.end - quotes
+        } else {
+            self.end
+        };
+        start..end.max(start)
+    }
+}
+
+/// How a `"` preceded by an even run of backslashes is treated inside a
+/// string literal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Recovery {
+    /// Java semantics: the backslashes escape each other and the `"` closes
+    /// the string.
+    Strict,
+    /// Dataset files that went through one or more JSONL encoding passes
+    /// contain `\\"` where they should contain `\"`.  When the byte after
+    /// such a `"` cannot follow a string close (see `is_suspicious_close`),
+    /// the `"` is taken as an embedded quote and the string goes on.  A bare
+    /// `"` always closes.
+    #[default]
+    SuspiciousClose,
+}
+
+/// Returns `true` for bytes that cannot validly appear immediately after a
+/// closing `"` in well-formed Java source: a letter or digit, `_`, `$`, a
+/// backslash, an apostrophe, or a JSON structural character (`{`, `}`, `:`,
+/// `,`, `[`, `]`).
+fn is_suspicious_close(b: u8) -> bool {
+    matches!(
+        b,
+        b'a'..=b'z'
+            | b'A'..=b'Z'
+            | b'0'..=b'9'
+            | b'{'
+            | b'}'
+            | b':'
+            | b','
+            | b'['
+            | b']'
+            | b'\''
+            | b'_'
+            | b'$'
+            | b'\\'
+    )
+}
+
+/// Iterator over the [`Token`]s of a Java source.
+pub struct Lexer<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    i: usize,
+    recovery: Recovery,
+}
+
+impl<'a> Lexer<'a> {
+    /// A lexer over `src` using [`Recovery::SuspiciousClose`].
+    pub fn new(src: &'a str) -> Self {
+        Lexer {
+            src,
+            bytes: src.as_bytes(),
+            i: 0,
+            recovery: Recovery::default(),
+        }
+    }
+
+    pub fn recovery(mut self, recovery: Recovery) -> Self {
+        self.recovery = recovery;
+        self
+    }
+
+    /// End of the token starting at `start` and whether it was closed.
+    fn token_at(&self, start: usize) -> (TokenKind, usize, bool) {
+        let bytes = self.bytes;
+        let rest = &bytes[start..];
+        match bytes[start] {
+            b'/' if rest.starts_with(b"//") => {
+                let len = rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+                (TokenKind::LineComment, start + len, true)
+            }
+            b'/' if rest.starts_with(b"/*") => {
+                match rest[2..].windows(2).position(|w| w == b"*/") {
+                    Some(n) => (TokenKind::BlockComment, start + n + 4, true),
+                    None => (TokenKind::BlockComment, bytes.len(), false),
+                }
+            }
+            b'"' => match scan_text_block(bytes, start) {
+                Some((end, closed)) => (TokenKind::TextBlock, end, closed),
+                None => {
+                    let (end, closed) = self.scan_string(start);
+                    (TokenKind::String, end, closed)
+                }
+            },
+            b'\'' => match scan_char(self.src, start) {
+                Some(end) => (TokenKind::Char, end, true),
+                None => (TokenKind::Other, start + 1, true),
+            },
+            b'0'..=b'9' => {
+                let len = rest
+                    .iter()
+                    .position(|&b| !(b.is_ascii_alphanumeric() || b == b'_' || b == b'.'))
+                    .unwrap_or(rest.len());
+                (TokenKind::Number, start + len, true)
+            }
+            _ => {
+                let ch = self.src[start..].chars().next().unwrap_or('\0');
+                if is_identifier_start(ch) {
+                    let len = self.src[start..]
+                        .find(|c: char| !is_identifier_part(c))
+                        .unwrap_or(self.src.len() - start);
+                    (TokenKind::Identifier, start + len, true)
+                } else {
+                    (TokenKind::Other, start + ch.len_utf8(), true)
+                }
+            }
+        }
+    }
+
+    /// Scan a `"…"` string literal starting at `start`, applying the
+    /// recovery policy to quotes after an even backslash run.
+    fn scan_string(&self, start: usize) -> (usize, bool) {
+        let bytes = self.bytes;
+        let mut i = start + 1;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'\\' => {
+                    let run_start = i;
+                    while i < bytes.len() && bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    let run_len = i - run_start;
+                    if i < bytes.len() && bytes[i] == b'"' {
+                        i += 1;
+                        if run_len.is_multiple_of(2) {
+                            let next = bytes.get(i).copied().unwrap_or(b' ');
+                            let spurious = self.recovery == Recovery::SuspiciousClose
+                                && is_suspicious_close(next);
+                            if !spurious {
+                                return (i, true);
+                            }
+                        }
+                        // Odd run (escaped quote) or spurious close: go on.
+                    }
+                }
+                b'"' => return (i + 1, true),
+                _ => i += 1,
+            }
+        }
+        (bytes.len(), false)
+    }
+}
+
+impl Iterator for Lexer<'_> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Token> {
+        if self.i >= self.bytes.len() {
+            return None;
+        }
+        let start = self.i;
+        let (kind, end, closed) = self.token_at(start);
+        self.i = end;
+        Some(Token {
+            kind,
+            start,
+            end,
+            closed,
+        })
+    }
+}
+
+/// Scan a Java 15+ text block starting at byte offset `start`.
+///
+/// A text block opens with `"""`, optional spaces / tabs / form feeds and a
+/// line terminator; anything else is not a text block and yields `None`.
+/// Returns `(one_past_the_block, closed)`: the block ends after the first
+/// unescaped `"""`, or at the end of input when it is never closed.  Escapes
+/// (including `\"""` and line continuations) are skipped as two bytes; raw
+/// newlines are content.
+fn scan_text_block(bytes: &[u8], start: usize) -> Option<(usize, bool)> {
+    if !bytes[start..].starts_with(b"\"\"\"") {
+        return None;
+    }
+    let mut i = start + 3;
+    while i < bytes.len() && matches!(bytes[i], b' ' | b'\t' | b'\x0c') {
+        i += 1;
+    }
+    if !matches!(bytes.get(i), Some(b'\n' | b'\r')) {
+        return None;
+    }
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'"' if bytes[i..].starts_with(b"\"\"\"") => return Some((i + 3, true)),
+            _ => i += 1,
+        }
+    }
+    Some((bytes.len(), false))
+}
+
+/// One past the closing `'` of a char literal starting at `start`, or `None`
+/// if the source there is not one.
+///
+/// Accepts every JLS form: a single character other than `'`, `\`, CR or LF
+/// (multi-byte UTF-8 included), or one escape sequence (see [`escape_end`]).
+fn scan_char(src: &str, start: usize) -> Option<usize> {
+    let bytes = src.as_bytes();
+    let mut i = start + 1;
+    match *bytes.get(i)? {
+        b'\\' => i = escape_end(bytes, i)?,
+        b'\'' | b'\n' | b'\r' => return None,
+        _ => i += src[i..].chars().next()?.len_utf8(),
+    }
+    (bytes.get(i) == Some(&b'\'')).then_some(i + 1)
+}
+
+/// One past the escape sequence whose backslash is at `i`, or `None` if it
+/// is not a JLS escape: `\b \s \t \n \f \r \" \' \\`, an octal escape
+/// (`\0` to `\377`) or a Unicode escape (`\u` with any number of `u`s and
+/// four hex digits).
+fn escape_end(bytes: &[u8], i: usize) -> Option<usize> {
+    let at = |k: usize| bytes.get(k).copied();
+    match at(i + 1)? {
+        b'b' | b's' | b't' | b'n' | b'f' | b'r' | b'"' | b'\'' | b'\\' => Some(i + 2),
+        b'u' => {
+            let mut j = i + 1;
+            while at(j) == Some(b'u') {
+                j += 1;
+            }
+            let hex = bytes.get(j..j + 4)?;
+            hex.iter().all(u8::is_ascii_hexdigit).then_some(j + 4)
+        }
+        first @ b'0'..=b'7' => {
+            // `\0`–`\377`: three digits only when the first is 0–3.
+            let max = if first <= b'3' { 3 } else { 2 };
+            let mut j = i + 1;
+            while j < i + 1 + max && matches!(at(j), Some(b'0'..=b'7')) {
+                j += 1;
+            }
+            Some(j)
+        }
+        _ => None,
+    }
+}
+
+fn is_identifier_start(c: char) -> bool {
+    c.is_alphabetic() || c == '_' || c == '$'
+}
+
+fn is_identifier_part(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::literal_blanker::{blank_literals, blank_literals_permanently, restore_literals};
+    use crate::sanitizer::{extract_string_literal_spans, fix_raw_newlines_in_string_literals};
+
+    fn kinds(src: &str) -> Vec<(TokenKind, &str)> {
+        Lexer::new(src)
+            .filter(|t| t.kind != TokenKind::Other)
+            .map(|t| (t.kind, t.text(src)))
+            .collect()
+    }
+
+    #[test]
+    fn tokens_cover_the_source() {
+        let src = "int x1 = 0x1F; // \"no\"\nchar c = '\\''; /* 'a' */ s = \"é\\\"\";";
+        use TokenKind::*;
+        assert_eq!(
+            kinds(src),
+            vec![
+                (Identifier, "int"),
+                (Identifier, "x1"),
+                (Number, "0x1F"),
+                (LineComment, "// \"no\""),
+                (Identifier, "char"),
+                (Identifier, "c"),
+                (Char, "'\\''"),
+                (BlockComment, "/* 'a' */"),
+                (Identifier, "s"),
+                (String, "\"é\\\"\""),
+            ]
+        );
+        let joined: std::string::String = Lexer::new(src).map(|t| t.text(src)).collect();
+        assert_eq!(joined, src);
+    }
+
+    #[test]
+    fn recovery_policy_decides_even_backslash_quotes() {
+        let src = r#"f("{\\"key\\":1}")"#;
+        let strings = |recovery| {
+            Lexer::new(src)
+                .recovery(recovery)
+                .filter(Token::is_string)
+                .count()
+        };
+        assert_eq!(strings(Recovery::SuspiciousClose), 1);
+        assert_eq!(strings(Recovery::Strict), 2);
+    }
+
+    #[test]
+    fn literal_content_excludes_delimiters() {
+        let src = "\"\"\"\n  a\n  \"\"\" \"b\" \"c";
+        let contents: Vec<&str> = Lexer::new(src)
+            .filter(Token::is_string)
+            .map(|t| &src[t.content()])
+            .collect();
+        assert_eq!(contents, vec!["\n  a\n  ", "b", "c"]);
+    }
+
+    #[test]
+    fn char_literals_follow_the_jls() {
+        let char_len = |src: &str| scan_char(src, 0);
+        for literal in [
+            "'a'",
+            "'é'",
+            "'中'",
+            "'\\n'",
+            "'\\s'",
+            "'\\''",
+            "'\\\\'",
+            "'\\\"'",
+            "'\\0'",
+            "'\\77'",
+            "'\\377'",
+            "'\\u0041'",
+            "'\\uD83D'",
+            "'\\uuu00e9'",
+        ] {
+            assert_eq!(char_len(literal), Some(literal.len()), "{literal}");
+        }
+        for not_literal in [
+            "''",
+            "'ab'",
+            "'\\q'",
+            "'\\400'",
+            "'\\u00'",
+            "'\\u00g0'",
+            "'\n'",
+            "'",
+            "'\\'",
+        ] {
+            assert_eq!(char_len(not_literal), None, "{not_literal}");
+        }
+        // `\477` is `\47` followed by `7`: no closing quote where expected.
+        assert_eq!(char_len("'\\477'"), None);
+    }
+
+    #[test]
+    fn triple_quote_without_line_break_is_not_a_text_block() {
+        // `""` followed by a string: not valid Java, but lexed as two strings.
+        assert_eq!(scan_text_block(b"\"\"\"x\"", 0), None);
+        assert_eq!(scan_text_block(b"\"\"\" \n abc", 0), Some((9, false)));
+    }
+
+    // ── property tests ───────────────────────────────────────────────────────
+
+    /// Fragments that exercise every lexer decision: quotes after backslash
+    /// runs, suspicious and harmless next bytes, comments, char literals,
+    /// text blocks, raw newlines and multi-byte characters.
+    const FRAGMENTS: &[&str] = &[
+        "\"",
+        "\\",
+        "\\\\",
+        "\\\"",
+        "\\\\\"",
+        "'",
+        "'a'",
+        "'\\''",
+        "\"\"\"\n",
+        "\"\"\"",
+        "//",
+        "/*",
+        "*/",
+        "\n",
+        "\r",
+        " ",
+        "a",
+        "x1",
+        "9",
+        "{",
+        "}",
+        ":",
+        ",",
+        "[",
+        "]",
+        "(",
+        ")",
+        ";",
+        "_",
+        "$",
+        "é",
+        "中",
+        "🎉",
+        "+",
+        "'é'",
+        "'\\u00e9'",
+        "'\\377'",
+        "'\\0'",
+        "\\u",
+        "'\\uD83D'",
+    ];
+
+    /// xorshift64: deterministic, so a failure reproduces from its seed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % n as u64) as usize
+        }
+    }
+
+    fn random_source(rng: &mut Rng) -> String {
+        let len = rng.below(40);
+        (0..len)
+            .map(|_| FRAGMENTS[rng.below(FRAGMENTS.len())])
+            .collect()
+    }
+
+    fn string_count(src: &str) -> usize {
+        Lexer::new(src).filter(Token::is_string).count()
+    }
+
+    fn check(seed: u64, src: &str) {
+        let strings = string_count(src);
+        let literals = Lexer::new(src).filter(Token::is_literal).count();
+
+        let (blanked, store) = blank_literals(src);
+        assert_eq!(store.len(), literals, "seed {seed}: blank_literals {src:?}");
+        assert_eq!(
+            restore_literals(&blanked, &store).unwrap(),
+            src,
+            "seed {seed}: round trip {src:?}"
+        );
+        assert_eq!(
+            string_count(&blank_literals_permanently(src)),
+            strings,
+            "seed {seed}: blank_literals_permanently {src:?}"
+        );
+        assert_eq!(
+            extract_string_literal_spans(src).len(),
+            strings,
+            "seed {seed}: extract_string_literal_spans {src:?}"
+        );
+        let fixed = fix_raw_newlines_in_string_literals(src);
+        assert_eq!(
+            string_count(&fixed),
+            strings,
+            "seed {seed}: fix_raw_newlines_in_string_literals {src:?} -> {fixed:?}"
+        );
+        for token in Lexer::new(&fixed).filter(|t| t.kind == TokenKind::String) {
+            assert!(
+                !fixed[token.content()].contains(['\n', '\r']),
+                "seed {seed}: raw newline left in {src:?} -> {fixed:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn blanker_and_sanitizer_agree_on_random_sources() {
+        for seed in 1..=5000u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let src = random_source(&mut rng);
+            check(seed, &src);
+        }
+    }
+}
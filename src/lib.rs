@@ -1,6 +1,21 @@
 pub mod cli;
+pub mod commands;
+pub mod config;
+pub mod dedup;
+pub mod encoding;
+pub mod error;
+pub mod error_log;
+pub mod ffi;
 pub mod helper;
+pub mod lexer;
 pub mod literal_blanker;
+pub mod manifest;
 pub mod obfuscator;
+pub mod pipeline;
 pub mod processor;
+pub mod quarantine;
 pub mod sanitizer;
+pub mod server;
+pub mod split;
+pub mod summary;
+pub mod verify;
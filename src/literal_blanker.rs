@@ -1,3 +1,9 @@
+use std::ops::Range;
+
+use crate::error::{Error, Result};
+use crate::helper::stable_hash;
+use crate::lexer::{Lexer, TokenKind};
+
 #[derive(Debug, Clone, Default)]
 pub struct LiteralStore {
     entries: Vec<LiteralEntry>,
@@ -7,61 +13,79 @@ pub struct LiteralStore {
 struct LiteralEntry {
     placeholder: String,
     original: String,
+    /// Byte offset of the placeholder in the blanked source.
+    position: usize,
+}
+
+impl LiteralStore {
+    /// Number of literals blanked.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// `(placeholder, original literal)` pairs in source order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|e| (e.placeholder.as_str(), e.original.as_str()))
+    }
+
+    /// Byte range of every placeholder in `blanked` with the literal it
+    /// stands for, in source order.
+    ///
+    /// Fails with [`Error::LiteralStoreMismatch`] unless every placeholder is
+    /// still at the position recorded when `blanked` was produced.
+    pub fn placements(&self, blanked: &str) -> Result<Vec<(Range<usize>, &str)>> {
+        let mut placements = Vec::with_capacity(self.entries.len());
+        for (restored, entry) in self.entries.iter().enumerate() {
+            let range = entry.position..entry.position + entry.placeholder.len();
+            if blanked.get(range.clone()) != Some(entry.placeholder.as_str()) {
+                return Err(Error::LiteralStoreMismatch {
+                    label: String::new(),
+                    expected: self.entries.len(),
+                    restored,
+                });
+            }
+            placements.push((range, entry.original.as_str()));
+        }
+        Ok(placements)
+    }
+}
+
+/// Prefix of the string placeholders of `src`: `STR_<nonce>_`.  The nonce is
+/// derived from the source and bumped until the prefix does not occur in it,
+/// so no placeholder can ever match source text, comments included.
+fn placeholder_prefix(src: &str) -> String {
+    let mut nonce = stable_hash(src.as_bytes()) as u32;
+    loop {
+        let prefix = format!("STR_{:08x}_", nonce);
+        if !src.contains(&prefix) {
+            return prefix;
+        }
+        nonce = nonce.wrapping_add(1);
+    }
 }
 
 // ---------------------------------------------------------------------------
 // blank_literals
 // ---------------------------------------------------------------------------
 
+/// Replace every string / char literal in `src` with a dummy (`"_"` / `'X'`)
+/// that is never restored.  Used by the fallback path for sources whose
+/// literals cannot be restored reliably.
 pub fn blank_literals_permanently(src: &str) -> String {
-    let bytes = src.as_bytes();
-    let len = bytes.len();
     let mut out = String::with_capacity(src.len());
-    let mut i = 0;
-
-    while i < len {
-        // line comment
-        if i + 1 < len && bytes[i] == b'/' && bytes[i + 1] == b'/' {
-            let start = i;
-            while i < len && bytes[i] != b'\n' {
-                i += 1;
-            }
-            out.push_str(&src[start..i]);
-            continue;
-        }
-        // block comment
-        if i + 1 < len && bytes[i] == b'/' && bytes[i + 1] == b'*' {
-            let start = i;
-            i += 2;
-            while i + 1 < len && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
-                i += 1;
-            }
-            if i + 1 < len {
-                i += 2;
-            }
-            out.push_str(&src[start..i]);
-            continue;
-        }
-        // string literal — consume it entirely, emit dummy
-        if bytes[i] == b'"' {
-            let (_literal, end) = consume_string_literal(bytes, i);
-            out.push_str("\"_\"");
-            i = end;
-            continue;
-        }
-        // char literal
-        if bytes[i] == b'\'' {
-            if let Some((_literal, end)) = try_consume_char_literal(bytes, i) {
-                out.push_str("'X'");
-                i = end;
-                continue;
-            }
-        }
-        let ch = src[i..].chars().next().unwrap_or('\0');
-        out.push(ch);
-        i += ch.len_utf8();
+    for token in Lexer::new(src) {
+        out.push_str(match token.kind {
+            TokenKind::String | TokenKind::TextBlock => "\"_\"",
+            TokenKind::Char => "'X'",
+            _ => token.text(src),
+        });
     }
-
     out
 }
 
@@ -73,71 +97,33 @@ pub fn blank_literals_permanently(src: &str) -> String {
 /// * The blanked source is structurally valid Java (tree-sitter can parse it).
 /// * Placeholders are assigned in left-to-right source order.
 /// * Literals inside `//` and `/* */` comments are **not** blanked.
+/// * String placeholders (`"STR_<nonce>_<n>"`) never occur in `src`, and the
+///   store records where each placeholder was written.
+///
+/// Literal boundaries come from [`Lexer`], including its corruption-recovery
+/// heuristic for `\\"` inside strings; text blocks are one literal.
 pub fn blank_literals(src: &str) -> (String, LiteralStore) {
-    let bytes = src.as_bytes();
-    let len = bytes.len();
-
     let mut out = String::with_capacity(src.len());
     let mut store = LiteralStore::default();
-    let mut i = 0;
-
-    while i < len {
-        // ── line comment  // … \n ───────────────────────────────────────────
-        if i + 1 < len && bytes[i] == b'/' && bytes[i + 1] == b'/' {
-            let start = i;
-            while i < len && bytes[i] != b'\n' {
-                i += 1;
-            }
-            out.push_str(&src[start..i]);
-            continue;
-        }
+    let prefix = placeholder_prefix(src);
 
-        // ── block comment  /* … */ ──────────────────────────────────────────
-        if i + 1 < len && bytes[i] == b'/' && bytes[i + 1] == b'*' {
-            let start = i;
-            i += 2;
-            while i + 1 < len && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
-                i += 1;
-            }
-            if i + 1 < len {
-                i += 2; // consume '*/'
+    for token in Lexer::new(src) {
+        let placeholder = match token.kind {
+            TokenKind::String | TokenKind::TextBlock => {
+                format!("\"{}{}\"", prefix, store.entries.len())
             }
-            out.push_str(&src[start..i]);
-            continue;
-        }
-
-        // ── string literal  "…" ────────────────────────────────────────────
-        if bytes[i] == b'"' {
-            let (literal, end) = consume_string_literal(bytes, i);
-            let idx = store.entries.len();
-            let placeholder = format!("\"STR_{}\"", idx);
-            store.entries.push(LiteralEntry {
-                placeholder: placeholder.clone(),
-                original: literal,
-            });
-            out.push_str(&placeholder);
-            i = end;
-            continue;
-        }
-
-        // ── char literal  '…' ──────────────────────────────────────────────
-        if bytes[i] == b'\'' {
-            if let Some((literal, end)) = try_consume_char_literal(bytes, i) {
-                let placeholder = "'X'".to_string();
-                store.entries.push(LiteralEntry {
-                    placeholder: placeholder.clone(),
-                    original: literal,
-                });
-                out.push_str(&placeholder);
-                i = end;
+            TokenKind::Char => "'X'".to_string(),
+            _ => {
+                out.push_str(token.text(src));
                 continue;
             }
-        }
-
-        // ── verbatim passthrough ────────────────────────────────────────────
-        let ch = src[i..].chars().next().unwrap_or('\0');
-        out.push(ch);
-        i += ch.len_utf8();
+        };
+        store.entries.push(LiteralEntry {
+            position: out.len(),
+            original: token.text(src).to_string(),
+            placeholder: placeholder.clone(),
+        });
+        out.push_str(&placeholder);
     }
 
     (out, store)
@@ -150,192 +136,20 @@ pub fn blank_literals(src: &str) -> (String, LiteralStore) {
 /// Replace every placeholder inserted by [`blank_literals`] with its original
 /// literal, restoring the source to its pre-blanked form.
 ///
-/// Optimisation: instead of repeatedly calling `find()` + `replace_range()`
-/// on a mutable `String` (O(n × m) total), this does a single left-to-right
-/// scan that builds the output in one pass (O(n + total_literal_bytes)).
-/// Placeholders are guaranteed to appear in insertion order (left-to-right),
-/// so we can advance `rest` forward without back-tracking.
-pub fn restore_literals(blanked: &str, store: &LiteralStore) -> String {
-    if store.entries.is_empty() {
-        return blanked.to_string();
-    }
-
-    // Pre-size the output: it will be at least as long as `blanked` because
-    // original literals are usually longer than their placeholders.
+/// Placeholders are taken from the positions recorded in `store`, never
+/// searched for, so `blanked` must be the unmodified output of
+/// [`blank_literals`]; any placeholder not found at its position is an
+/// [`Error::LiteralStoreMismatch`].
+pub fn restore_literals(blanked: &str, store: &LiteralStore) -> Result<String> {
     let mut result = String::with_capacity(blanked.len());
-    let mut rest = blanked;
-
-    for entry in &store.entries {
-        match rest.find(&entry.placeholder) {
-            Some(pos) => {
-                // Push everything before the placeholder verbatim, then the
-                // original literal, then advance past the placeholder.
-                result.push_str(&rest[..pos]);
-                result.push_str(&entry.original);
-                rest = &rest[pos + entry.placeholder.len()..];
-            }
-            None => {
-                // A missing placeholder means the processing step deleted the
-                // literal — caller bug. Skip silently so the rest is still
-                // restored.
-            }
-        }
-    }
-
-    // Append whatever remains after the last placeholder.
-    result.push_str(rest);
-    result
-}
-
-// ---------------------------------------------------------------------------
-// Private helpers
-// ---------------------------------------------------------------------------
-
-/// Returns `true` for bytes that cannot validly appear immediately after a
-/// closing `"` in well-formed Java source.
-///
-/// This is used as a look-ahead heuristic inside [`consume_string_literal`]:
-/// when we encounter an even run of backslashes followed by `"`, that `"` is
-/// normally the closing quote.  But in dataset files that have been through one
-/// or more JSONL encoding passes, each `\"` inside a string was doubled to `\\"`.
-/// When `consume_string_literal` hits that `\\"`, it sees an even backslash run
-/// + `"` and would incorrectly close the string, leaving the rest of the content
-/// (e.g. `message\":\"val\"}`) as raw tokens outside any string — making the
-/// blanked source unparseable by tree-sitter.
-///
-/// The heuristic: if the character immediately following the candidate closing
-/// `"` is one that **cannot** start a valid Java token after a string literal
-/// (a letter, digit, or any JSON structural character: `{`, `}`, `:`, `,`,
-/// `[`, `]`, `_`, `$`, or `\`), the close is almost certainly spurious
-/// corruption and we treat the `"` as an embedded escaped quote instead.
-///
-/// Note: this lookahead fires **only** for backslash-preceded `"` (even run).
-/// A bare unescaped `"` always closes the string unconditionally.
-fn is_suspicious_after_even_backslash_close(b: u8) -> bool {
-    matches!(
-        b,
-        b'a'..=b'z'     // letter — identifier or JSON key/value
-        | b'A'..=b'Z'
-        | b'0'..=b'9'   // digit
-        | b'{'          // JSON object open
-        | b'}'          // JSON object close (safe to add: only fires for backslash-preceded ")
-        | b':'          // JSON key-value separator
-        | b','          // JSON array/object separator
-        | b'['          // JSON array open
-        | b']'          // JSON array close
-        | b'\''         // apostrophe — never directly follows a Java string close
-        | b'_'          // identifier char
-        | b'$'          // identifier char
-        | b'\\'         // another backslash run follows
-    )
-}
-
-/// Consume a double-quoted Java string literal starting at byte offset `start`.
-///
-/// Returns `(full_literal_text, one_past_closing_quote)`.
-///
-/// Handles standard Java escape sequences (`\"`, `\\`, etc.) and also applies
-/// a corruption-recovery heuristic for dataset files that contain `\\"` where
-/// they should contain `\"`: when an even backslash run precedes a `"` and the
-/// byte immediately after that `"` looks like it could not start a valid Java
-/// token after a string close, the `"` is treated as an embedded escaped quote
-/// and scanning continues.
-///
-/// Does **not** handle Java 15+ text blocks (triple-quote).
-fn consume_string_literal(bytes: &[u8], start: usize) -> (String, usize) {
-    debug_assert_eq!(bytes[start], b'"');
-    let mut i = start + 1;
-
-    while i < bytes.len() {
-        match bytes[i] {
-            b'\\' => {
-                // Count the full run of consecutive backslashes.
-                let run_start = i;
-                while i < bytes.len() && bytes[i] == b'\\' {
-                    i += 1;
-                }
-                let run_len = i - run_start;
-
-                if i < bytes.len() && bytes[i] == b'"' {
-                    if run_len % 2 == 0 {
-                        // Even run: the backslashes are all paired, so the `"`
-                        // is a candidate closing quote.
-                        let next = bytes.get(i + 1).copied().unwrap_or(b' ');
-                        if is_suspicious_after_even_backslash_close(next) {
-                            // Heuristic: looks like a corrupt embedded quote.
-                            // Keep scanning — do NOT close the string here.
-                            i += 1; // skip the `"`
-                        } else {
-                            // Real closing quote.
-                            i += 1; // skip the `"`
-                            break;
-                        }
-                    } else {
-                        // Odd run: the last `\` escapes the `"` — it is an
-                        // embedded quoted character, not the closing quote.
-                        i += 1; // skip the `"`
-                    }
-                }
-                // If not followed by `"`, the backslash run is just part of
-                // the string content (e.g. `\\n`, `\\t`, …); continue.
-            }
-            b'"' => {
-                // Bare unescaped closing quote — always terminates.
-                i += 1;
-                break;
-            }
-            _ => {
-                i += 1;
-            }
-        }
-    }
-
-    // CORRECTNESS: We must decode the byte slice as UTF-8, not byte-by-byte via
-    // `b as char` (Latin-1), which would corrupt any multi-byte character
-    // (e.g. `é` → `Ã©`) and cause token-count mismatches in the JSONL pairs.
-    (String::from_utf8_lossy(&bytes[start..i]).into_owned(), i)
-}
-
-/// Try to consume a char literal starting at `start`.
-///
-/// Returns `(literal_text, one_past_closing_quote)` on success, or `None` if
-/// the bytes at `start` do not look like a valid Java char literal.
-fn try_consume_char_literal(bytes: &[u8], start: usize) -> Option<(String, usize)> {
-    debug_assert_eq!(bytes[start], b'\'');
-
-    // Minimum char literal: 'X' → 3 bytes.
-    if start + 2 >= bytes.len() {
-        return None;
-    }
-
-    let mut i = start + 1;
-
-    // Escape sequence
-    if bytes[i] == b'\\' {
-        i += 1; // skip the backslash
-        if i >= bytes.len() {
-            return None;
-        }
-        i += 1; // skip the escaped char
-    } else {
-        // Ordinary character — must not be a newline or another `'`.
-        if bytes[i] == b'\n' || bytes[i] == b'\'' {
-            return None;
-        }
-        // Advance past the UTF-8 char (may be multi-byte).
-        // We work at byte level so just skip one byte here; for ASCII this is
-        // correct. For multi-byte chars the closing `'` will still be found.
-        i += 1;
-    }
-
-    // Closing quote
-    if i >= bytes.len() || bytes[i] != b'\'' {
-        return None;
-    }
-    i += 1;
-
-    // CORRECTNESS: decode as UTF-8, not byte-by-byte Latin-1 (`b as char`).
-    Some((String::from_utf8_lossy(&bytes[start..i]).into_owned(), i))
+    let mut last = 0;
+    for (range, original) in store.placements(blanked)? {
+        result.push_str(&blanked[last..range.start]);
+        result.push_str(original);
+        last = range.end;
+    }
+    result.push_str(&blanked[last..]);
+    Ok(result)
 }
 
 #[cfg(test)]
@@ -344,7 +158,7 @@ mod tests {
 
     fn round_trip(src: &str) -> String {
         let (blanked, store) = blank_literals(src);
-        restore_literals(&blanked, &store)
+        restore_literals(&blanked, &store).unwrap()
     }
 
     #[test]
@@ -420,6 +234,28 @@ mod tests {
         assert_eq!(round_trip(src), src);
     }
 
+    #[test]
+    fn round_trip_char_literals_of_every_jls_form() {
+        let src = concat!(
+            "char[] cs = { 'é', '中', '\\uD83D', '\\uuu00e9', '\\377', '\\0', '\\7', '\\s', '\\\"' };",
+            " String s = \"'é'\";",
+        );
+        let (blanked, store) = blank_literals(src);
+        assert_eq!(
+            blanked,
+            format!(
+                "char[] cs = {{ 'X', 'X', 'X', 'X', 'X', 'X', 'X', 'X', 'X' }}; String s = \"{}9\";",
+                placeholder_prefix(src)
+            )
+        );
+        assert_eq!(store.len(), 10);
+        assert_eq!(restore_literals(&blanked, &store).unwrap(), src);
+        assert_eq!(
+            blank_literals_permanently(src),
+            "char[] cs = { 'X', 'X', 'X', 'X', 'X', 'X', 'X', 'X', 'X' }; String s = \"_\";"
+        );
+    }
+
     #[test]
     fn round_trip_heavy_backslash_string() {
         let src = r#"String url = "jdbc:h2:file:path\\\\upstream\\\\modeshape";"#;
@@ -432,6 +268,35 @@ mod tests {
         assert_eq!(round_trip(src), src);
     }
 
+    const TEXT_BLOCK: &str = concat!(
+        "String json = \"\"\"\n",
+        "    {\"name\": \"it's\", \"q\": \\\"\"\"}\n",
+        "    line \\\n",
+        "    \"\"\";\n",
+        "char c = 'x'; String s = \"after\";",
+    );
+
+    #[test]
+    fn round_trip_text_block() {
+        assert_eq!(round_trip(TEXT_BLOCK), TEXT_BLOCK);
+        let (blanked, store) = blank_literals(TEXT_BLOCK);
+        assert_eq!(store.len(), 3);
+        let prefix = placeholder_prefix(TEXT_BLOCK);
+        assert_eq!(
+            blanked,
+            format!("String json = \"{prefix}0\";\nchar c = 'X'; String s = \"{prefix}2\";")
+        );
+        assert!(store.iter().next().unwrap().1.ends_with("    \"\"\""));
+    }
+
+    #[test]
+    fn permanent_blanking_treats_text_block_as_one_literal() {
+        assert_eq!(
+            blank_literals_permanently(TEXT_BLOCK),
+            "String json = \"_\";\nchar c = 'X'; String s = \"_\";"
+        );
+    }
+
     #[test]
     fn blanked_has_no_escaped_quotes() {
         let src = r#"assertThat(s).isEqualTo("{\"key\":\"val\"}");"#;
@@ -452,9 +317,43 @@ mod tests {
         let src = r#"assertEquals("a", "b", "c");"#;
         let (blanked, store) = blank_literals(src);
         assert_eq!(store.entries.len(), 3);
-        assert!(blanked.contains("\"STR_0\""));
-        assert!(blanked.contains("\"STR_1\""));
-        assert!(blanked.contains("\"STR_2\""));
+        let prefix = placeholder_prefix(src);
+        assert_eq!(
+            blanked,
+            format!("assertEquals(\"{prefix}0\", \"{prefix}1\", \"{prefix}2\");")
+        );
+    }
+
+    #[test]
+    fn placeholders_cannot_collide_with_source_text() {
+        let prefix = placeholder_prefix("");
+        // A comment and a string that spell out the first placeholder.
+        let src = format!("// \"{prefix}0\" 'X'\nf(\"{prefix}0\", 'a', \"b\");");
+        let (blanked, store) = blank_literals(&src);
+        let (first, _) = store.iter().next().unwrap();
+        assert!(!src.contains(first.trim_matches('"')), "{first}");
+        assert!(blanked.starts_with(&format!("// \"{prefix}0\" 'X'\n")));
+        assert_eq!(restore_literals(&blanked, &store).unwrap(), src);
+    }
+
+    #[test]
+    fn moved_or_missing_placeholders_are_an_error() {
+        let (blanked, store) = blank_literals("f(\"a\", 'b', \"c\");");
+        let shifted = format!(" {blanked}");
+        assert!(matches!(
+            restore_literals(&shifted, &store),
+            Err(Error::LiteralStoreMismatch {
+                expected: 3,
+                restored: 0,
+                ..
+            })
+        ));
+        let (placeholder, _) = store.iter().nth(2).unwrap();
+        let dropped = blanked.replace(placeholder, "\"\"");
+        assert!(matches!(
+            restore_literals(&dropped, &store),
+            Err(Error::LiteralStoreMismatch { restored: 2, .. })
+        ));
     }
 
     #[test]
@@ -528,7 +427,7 @@ mod tests {
             "method name must survive blanking"
         );
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -558,7 +457,7 @@ mod tests {
         );
         assert_eq!(store.entries.len(), 2, "exactly two string literals");
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -579,7 +478,7 @@ mod tests {
         assert!(!blanked.contains("\\\""), "no escaped quotes must remain");
         assert!(blanked.contains("c"), "local identifier 'c' must survive");
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -604,7 +503,7 @@ mod tests {
         );
         assert_eq!(store.entries.len(), 2, "exactly two string literals");
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -631,7 +530,7 @@ mod tests {
             "char literal '\"' must be replaced with 'X'"
         );
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -656,7 +555,7 @@ mod tests {
         );
         assert_eq!(store.entries.len(), 2, "exactly two string literals");
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -681,7 +580,7 @@ mod tests {
         );
         assert_eq!(store.entries.len(), 3, "exactly three string literals");
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -705,7 +604,7 @@ mod tests {
             "identifier 'assertTrue' must survive"
         );
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -719,7 +618,7 @@ mod tests {
         let (blanked, store) = blank_literals(src);
         assert_eq!(store.entries.len(), 1, "valid \\\\\\\\ must be one literal");
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -736,7 +635,7 @@ mod tests {
         );
         assert!(blanked.contains("isEqualTo"), "method call must survive");
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -753,7 +652,7 @@ mod tests {
         );
         assert!(blanked.contains("equalTo"), "method call must survive");
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -769,7 +668,7 @@ mod tests {
             "JSON string is one literal; TeamTagMap.class has none"
         );
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -786,7 +685,7 @@ mod tests {
         );
         assert!(blanked.contains("p.rot13"), "method call must survive");
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -818,7 +717,7 @@ mod tests {
             "no escaped quotes in blanked source"
         );
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
@@ -834,7 +733,7 @@ mod tests {
             "array initializer must produce exactly one literal"
         );
         assert_eq!(
-            restore_literals(&blanked, &store),
+            restore_literals(&blanked, &store).unwrap(),
             src,
             "round-trip must be lossless"
         );
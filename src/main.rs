@@ -1,67 +1,77 @@
-use java_dataset_converter_llm::cli::Args;
-use java_dataset_converter_llm::helper::get_files;
-use java_dataset_converter_llm::obfuscator::obfuscate_str_checked;
-use java_dataset_converter_llm::processor::{generate_jsonl_from_strings, generate_jsonl_raw};
-use java_dataset_converter_llm::sanitizer::sanitize_structural;
+use java_dataset_converter_llm::cli::{
+    Args, Cli, Command, EXIT_FAILURE, EXIT_FALLBACK, EXIT_OK, FilterArgs, ServeArgs, StageArgs,
+    VerifyArgs,
+};
+use java_dataset_converter_llm::commands::{
+    assign_splits, blank_file, copy_into_splits, dataset_stats, dedup_output, files_under,
+    filter_source, inspect_file, obfuscate_file, sanitize_file, verify_sources,
+};
+use java_dataset_converter_llm::config::Config;
+use java_dataset_converter_llm::dedup::DedupReport;
+use java_dataset_converter_llm::encoding::decode;
+use java_dataset_converter_llm::error_log::{ERROR_LOG_FILE, failed_inputs};
+use java_dataset_converter_llm::helper::write_atomic;
+use java_dataset_converter_llm::manifest::{MANIFEST_FILE, Selection, input_key};
+use java_dataset_converter_llm::pipeline::{
+    FileOutcome, FileReport, FsSink, Pipeline, Source, blanked_subdir_of,
+};
+use java_dataset_converter_llm::server::{RequestSettings, Server};
+use java_dataset_converter_llm::split::Split;
+use java_dataset_converter_llm::summary::SUMMARY_FILE;
+use java_dataset_converter_llm::verify::verify_dataset;
 
 use clap::Parser;
 use indicatif::{ProgressBar, ProgressStyle};
-use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::HashSet;
 use std::fs;
-use std::fs::OpenOptions;
-use std::io;
-use std::io::Write;
+use std::io::{self, Read, Write};
 use std::path::{Path, PathBuf};
-use std::sync::Mutex;
-
-fn is_processed(java_file: &Path, jsonl_output_dir: &Path) -> bool {
-    let file_name = java_file.file_name().unwrap().to_str().unwrap();
-    let clean = jsonl_output_dir
-        .join(format!("{}.jsonl", file_name))
-        .exists();
-    let blanked_dir = blanked_subdir_of(jsonl_output_dir);
-    let in_blanked = blanked_dir.join(format!("{}.jsonl", file_name)).exists();
-    clean || in_blanked
-}
-
-fn blanked_subdir_of(jsonl_output_dir: &Path) -> PathBuf {
-    let parent = jsonl_output_dir.parent().unwrap_or(Path::new("."));
-    let dir_name = jsonl_output_dir
-        .file_name()
-        .and_then(|n| n.to_str())
-        .unwrap_or("jsonl");
-    parent.join(format!("{}_blanked", dir_name))
-}
-
-fn log_error(log_path: &Path, java_file: &Path, stage: &str, err: &dyn std::error::Error) {
-    // Mutex-guard the log file so parallel threads don't interleave writes.
-    static LOG_LOCK: std::sync::OnceLock<Mutex<()>> = std::sync::OnceLock::new();
-    let _guard = LOG_LOCK.get_or_init(|| Mutex::new(())).lock();
-
-    let mut f = match OpenOptions::new().create(true).append(true).open(log_path) {
-        Ok(f) => f,
-        Err(_) => return,
-    };
-    let _ = writeln!(
-        f,
-        "[stage={}] file={} error={}",
-        stage,
-        java_file.display(),
-        err
+use std::process::ExitCode;
+use std::time::Instant;
+
+/// Print a dedup report summary.
+fn print_dedup(report: &DedupReport) {
+    eprintln!(
+        "Dedup: {} records, {} exact and {} near duplicates removed, {} clusters leak across splits",
+        report.total_records,
+        report.exact_duplicates,
+        report.near_duplicates,
+        report.cross_split_clusters
     );
 }
 
-fn full_sanitize(raw: &str) -> String {
-    sanitize_structural(raw)
+/// Print what happened to a file that did not simply convert cleanly.
+fn print_report(report: &FileReport) {
+    let file_name = report
+        .path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    match &report.outcome {
+        FileOutcome::Quarantined { .. } => eprintln!(
+            "Quarantining {} (corrupt source, --blanked-subdir not set)",
+            file_name
+        ),
+        FileOutcome::Failed { stage, message, .. } => {
+            eprintln!(
+                "Error at stage {} for {}: {}",
+                stage.as_str(),
+                file_name,
+                message
+            )
+        }
+        _ => {}
+    }
 }
 
-fn main() -> io::Result<()> {
-    let args = Args::parse();
-    let input_dir = Path::new(&args.input);
-    let jsonl_output_dir = match &args.jsonl_output {
-        Some(dir) => PathBuf::from(dir),
-        None => PathBuf::from(&args.output),
-    };
+/// `convert`: the full pipeline over an input tree.
+fn convert(args: &Args) -> io::Result<()> {
+    let started = Instant::now();
+    let config = Config::from_args(args).inspect_err(|e| eprintln!("{}", e))?;
+    // Both are set once the config has been validated.
+    let input_dir = config.input_dir().unwrap_or(Path::new("."));
+    let jsonl_output_dir = config.jsonl_dir().unwrap_or(Path::new(".")).to_path_buf();
 
     if !input_dir.exists() {
         eprintln!("Input directory does not exist: {}", input_dir.display());
@@ -71,27 +81,40 @@ fn main() -> io::Result<()> {
         ));
     }
 
-    fs::create_dir_all(&jsonl_output_dir)?;
-
-    // Create the blanked subdir eagerly only when the feature is enabled.
-    let jsonl_blanked_dir = if args.blanked_subdir {
-        let d = blanked_subdir_of(&jsonl_output_dir);
-        fs::create_dir_all(&d)?;
-        Some(d)
+    // One output directory per split (or just the root when not splitting).
+    let output_dirs: Vec<PathBuf> = if config.split.is_some() {
+        Split::ALL
+            .iter()
+            .map(|s| jsonl_output_dir.join(s.as_str()))
+            .collect()
     } else {
-        None
+        vec![jsonl_output_dir.clone()]
     };
+    for dir in &output_dirs {
+        fs::create_dir_all(dir)?;
+        // Create the blanked subdir eagerly only when the feature is enabled.
+        if config.output.blanked_fallback {
+            fs::create_dir_all(blanked_subdir_of(dir))?;
+        }
+    }
 
-    let error_log_path = jsonl_output_dir.join("error.log");
-    let java_files = get_files(input_dir.to_str().unwrap(), "java")?;
-    let total = java_files.len();
+    let selection = if args.force || args.retry.is_some() {
+        Selection::Force
+    } else if args.only_failed {
+        Selection::OnlyFailed
+    } else {
+        Selection::Changed
+    };
 
-    let already_processed = java_files
-        .iter()
-        .filter(|f| is_processed(f, &jsonl_output_dir))
-        .count();
+    config.write_effective(&jsonl_output_dir)?;
+    let pipeline_config = config
+        .pipeline_config()
+        .selection(selection)
+        .strict(args.strict)
+        .manifest(jsonl_output_dir.join(MANIFEST_FILE))
+        .error_log(jsonl_output_dir.join(ERROR_LOG_FILE));
 
-    let progress_bar = ProgressBar::new(total as u64);
+    let progress_bar = ProgressBar::new(0);
     progress_bar.set_style(
         ProgressStyle::with_template(
             "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta}) {msg}",
@@ -100,142 +123,346 @@ fn main() -> io::Result<()> {
         .progress_chars("#>-"),
     );
     progress_bar.set_message("Processing Java files...");
-    progress_bar.inc(already_processed as u64);
-
-    java_files
-        .par_iter()
-        .filter(|f| !is_processed(f, &jsonl_output_dir))
-        .for_each(|file| {
-            let file_name = file.file_name().unwrap().to_str().unwrap();
-
-            let raw = match fs::read_to_string(file) {
-                Ok(s) => s,
-                Err(e) => {
-                    eprintln!("Error reading {}: {}", file_name, e);
-                    log_error(&error_log_path, file, "read", &e);
-                    progress_bar.inc(1);
-                    return;
-                }
-            };
-            let sanitized_original = full_sanitize(&raw);
-
-            let (obfuscated, needed_fallback) = match obfuscate_str_checked(&sanitized_original) {
-                Ok(pair) => pair,
-                Err(e) => {
-                    eprintln!("Error obfuscating {}: {}", file_name, e);
-                    log_error(&error_log_path, file, "obfuscate", &e);
-                    progress_bar.inc(1);
-                    return;
-                }
-            };
-
-            // ── 3. Route & write JSONL ────────────────────────────────────────
-            if !needed_fallback {
-                // Clean source: write with real string content preserved.
-                let jsonl_file = jsonl_output_dir.join(format!("{}.jsonl", file_name));
-                if let Err(e) = generate_jsonl_raw(
-                    &sanitized_original,
-                    &obfuscated,
-                    jsonl_file.to_str().unwrap(),
-                ) {
-                    eprintln!("Error generating JSONL for {}: {}", file_name, e);
-                    log_error(&error_log_path, file, "generate_jsonl", &e);
-                }
-            } else if let Some(ref blanked_dir) = jsonl_blanked_dir {
-                // Corrupt source + --blanked-subdir: write blanked pair to sibling dir.
-                let jsonl_file = blanked_dir.join(format!("{}.jsonl", file_name));
-                if let Err(e) = generate_jsonl_from_strings(
-                    &sanitized_original,
-                    &obfuscated,
-                    jsonl_file.to_str().unwrap(),
-                ) {
-                    eprintln!("Error generating blanked JSONL for {}: {}", file_name, e);
-                    log_error(&error_log_path, file, "generate_jsonl_blanked", &e);
-                }
-            } else {
-                // Corrupt source + no flag: skip silently (file stays unprocessed).
-                eprintln!(
-                    "Skipping {} (corrupt source, --blanked-subdir not set)",
-                    file_name
-                );
-            }
 
-            progress_bar.inc(1);
-        });
+    // Read before the pipeline starts a new error log, which may be this one.
+    let retry = match &args.retry {
+        Some(path) => Some((path, failed_inputs(path, input_dir)?)),
+        None => None,
+    };
+    let bar = progress_bar.clone();
+    let pipeline = Pipeline::new(pipeline_config, FsSink::new(&jsonl_output_dir))?.on_progress(
+        move |report| {
+            print_report(report);
+            bar.inc(1);
+        },
+    );
+
+    let mut inputs = pipeline.discover(Source::Dir(input_dir.to_path_buf()))?;
+    if let Some((path, retry)) = retry {
+        let wanted: HashSet<&str> = retry.iter().map(String::as_str).collect();
+        inputs.retain(|input| wanted.contains(input.key.as_str()));
+        let found: HashSet<&str> = inputs.iter().map(|input| input.key.as_str()).collect();
+        for key in retry.iter().filter(|k| !found.contains(k.as_str())) {
+            eprintln!(
+                "Warning: {} from {} matches no input under {}",
+                key,
+                path.display(),
+                input_dir.display()
+            );
+        }
+    }
+    progress_bar.set_length(inputs.len() as u64);
+    let reports = pipeline.run_inputs(&inputs);
+
+    if reports.is_err() {
+        progress_bar.abandon_with_message("Aborted.");
+    } else {
+        progress_bar.finish_with_message("Done.");
+    }
+    reports?;
+    let (_, stats) = pipeline.finish()?;
+
+    if let Some(dedup) = &config.dedup {
+        let report = stats.time("dedup", || {
+            dedup_output(
+                &jsonl_output_dir,
+                config.split.is_some(),
+                &config.output.schema,
+                dedup,
+            )
+        })?;
+        print_dedup(&report);
+    }
 
-    progress_bar.finish_with_message("Done.");
+    let summary = stats.finish(started.elapsed());
+    let json = serde_json::to_string_pretty(&summary)?;
+    write_atomic(&jsonl_output_dir.join(SUMMARY_FILE), |w| {
+        w.write_all(json.as_bytes())
+    })?;
+    eprintln!("{}", summary);
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use crate::full_sanitize;
-
-    #[test]
-    fn full_sanitize_does_not_alter_valid_backslash_pairs() {
-        let raw = r#"assertThat(result).isEqualTo("\\\\");"#;
-        let result = full_sanitize(raw);
-        assert_eq!(
-            result, raw,
-            "full_sanitize must not alter a valid \\\\\\\\ string literal"
-        );
+/// Where the result for `file` goes: stdout (`None`) for a single file
+/// without `--output`, `--output` itself for a single file, and a mirror of
+/// `file`'s path below `--output` for a directory.
+fn stage_output(
+    stage: &StageArgs,
+    file: &Path,
+    extension: Option<&str>,
+) -> io::Result<Option<PathBuf>> {
+    let Some(output) = &stage.output else {
+        if stage.path.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--output is required when PATH is a directory",
+            ));
+        }
+        return Ok(None);
+    };
+    if stage.path.is_file() {
+        return Ok(Some(output.clone()));
     }
+    let mut path = output.join(input_key(&stage.path, file));
+    if let Some(extension) = extension {
+        path.as_mut_os_string().push(format!(".{}", extension));
+    }
+    Ok(Some(path))
+}
 
-    #[test]
-    fn full_sanitize_fixes_escaped_apostrophe() {
-        let raw = "Arrays.fill(buf, (byte) \\'Q\\');";
-        let result = full_sanitize(raw);
-        assert!(
-            result.contains("'Q'"),
-            "full_sanitize must convert \\' to '"
-        );
-        assert!(
-            !result.contains("\\'"),
-            "full_sanitize must remove all \\' sequences"
-        );
+/// Write `text` to `output`, or to stdout when there is none.
+fn emit(output: Option<&Path>, text: &str) -> io::Result<()> {
+    match output {
+        None => io::stdout().write_all(text.as_bytes()),
+        Some(path) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            write_atomic(path, |w| w.write_all(text.as_bytes()))
+        }
     }
+}
 
-    // ── blanked_subdir_of helper ─────────────────────────────────────────────
+/// Write one JSON line per record to `--output`, or to stdout.
+fn emit_json_lines<T: Serialize>(stage: &StageArgs, records: &[T]) -> io::Result<()> {
+    let mut text = String::new();
+    for record in records {
+        text.push_str(&serde_json::to_string(record)?);
+        text.push('\n');
+    }
+    let output = match &stage.output {
+        Some(path) if path.is_dir() => Some(path.join("report.jsonl")),
+        other => other.clone(),
+    };
+    emit(output.as_deref(), &text)
+}
 
-    use crate::blanked_subdir_of;
-    use std::path::Path;
+/// `sanitize` and `obfuscate`: write the transformed source of each file.
+fn transform(
+    stage: &StageArgs,
+    f: impl Fn(&Path, &Config) -> java_dataset_converter_llm::error::Result<String>,
+) -> io::Result<()> {
+    let config = Config::load_or_default(stage.config.as_deref())?.checked()?;
+    let mut failed = 0;
+    for file in files_under(&stage.path, "java")? {
+        match f(&file, &config) {
+            Ok(text) => emit(stage_output(stage, &file, None)?.as_deref(), &text)?,
+            Err(e) => {
+                failed += 1;
+                eprintln!("Error for {}: {}", file.display(), e);
+            }
+        }
+    }
+    check_failures(failed)
+}
 
-    #[test]
-    fn blanked_subdir_of_appends_blanked_suffix() {
-        let dir = Path::new("train/jsonl");
-        let blanked = blanked_subdir_of(dir);
-        assert_eq!(
-            blanked,
-            std::path::PathBuf::from("train/jsonl_blanked"),
-            "sibling directory must be '<parent>/<name>_blanked'"
+/// `verify`: convert `.java` sources in memory and re-check `.jsonl` pairs.
+/// A single file is treated by its extension.
+fn verify(args: &VerifyArgs) -> io::Result<()> {
+    let stage = &args.stage;
+    let config = Config::load_or_default(stage.config.as_deref())?.checked()?;
+    let is_jsonl = stage.path.extension().is_some_and(|e| e == "jsonl");
+    let mut report = Vec::new();
+    let mut failed = 0;
+
+    if stage.path.is_dir() || !is_jsonl {
+        let checks = verify_sources(&stage.path, config.pipeline_config())?;
+        let sources_failed = checks.iter().filter(|c| !c.passed()).count();
+        eprintln!(
+            "Verified {} sources, {} failed",
+            checks.len(),
+            sources_failed
         );
+        failed += sources_failed;
+        for check in &checks {
+            report.push(serde_json::to_value(check)?);
+        }
     }
-
-    #[test]
-    fn blanked_subdir_of_nested_path() {
-        let dir = Path::new("data/splits/test/jsonl");
-        let blanked = blanked_subdir_of(dir);
-        assert_eq!(
-            blanked,
-            std::path::PathBuf::from("data/splits/test/jsonl_blanked")
+    if stage.path.is_dir() || is_jsonl {
+        let checks = verify_dataset(
+            &stage.path,
+            &config.output.schema,
+            &config.naming,
+            args.clean.as_deref(),
+        )?;
+        let records_failed = checks.iter().filter(|c| !c.passed()).count();
+        eprintln!(
+            "Verified {} records, {} failed",
+            checks.len(),
+            records_failed
         );
+        failed += records_failed;
+        for check in &checks {
+            report.push(serde_json::to_value(check)?);
+        }
     }
+    emit_json_lines(stage, &report)?;
+    check_failures(failed)
+}
 
-    #[test]
-    fn blanked_subdir_of_top_level_name() {
-        // When the input path has no parent component (e.g. "jsonl"), the
-        // platform may return "" or "." for the parent, yielding either
-        // "jsonl_blanked" or "./jsonl_blanked".  Both are equivalent paths;
-        // assert on the file_name component only.
-        let dir = Path::new("jsonl");
-        let blanked = blanked_subdir_of(dir);
-        assert_eq!(
-            blanked.file_name().and_then(|n| n.to_str()),
-            Some("jsonl_blanked"),
-            "basename of the blanked sibling must be 'jsonl_blanked'"
-        );
+fn check_failures(failed: usize) -> io::Result<()> {
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} file(s) failed", failed)))
+    }
+}
+
+/// `filter`: one source from stdin to stdout.  A source that cannot be read
+/// or converted is reported on stderr and mapped to [`EXIT_FAILURE`].
+fn filter(args: &FilterArgs) -> io::Result<ExitCode> {
+    let config = Config::load_or_default(args.config.as_deref())?.checked()?;
+    let mut bytes = Vec::new();
+    io::stdin().read_to_end(&mut bytes)?;
+    let raw = match decode(&bytes, config.input.encoding) {
+        Ok(decoded) => decoded.text,
+        Err(e) => {
+            eprintln!("{}: {}", args.name, e);
+            return Ok(ExitCode::from(EXIT_FAILURE));
+        }
+    };
+    match filter_source(&raw, args.stage, &config, &args.name) {
+        Ok(output) => {
+            io::stdout().write_all(output.text.as_bytes())?;
+            Ok(ExitCode::from(if output.fallback {
+                EXIT_FALLBACK
+            } else {
+                EXIT_OK
+            }))
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            Ok(ExitCode::from(EXIT_FAILURE))
+        }
     }
+}
 
+/// `serve`: answer obfuscation requests until the process is killed.
+fn serve(args: &ServeArgs) -> io::Result<()> {
+    let config = Config::load_or_default(args.config.as_deref())?.checked()?;
+    let settings = RequestSettings {
+        limits: config.limits,
+        naming: config.naming,
+        sanitizer: config.sanitizer,
+    };
+    let server = Server::bind(&args.listen, args.threads, settings)?
+        .on_error(|e| eprintln!("Warning: failed to accept a connection: {}", e));
+    eprintln!("Listening on {}", server.endpoint());
+    server.serve()
+}
+
+fn run_command(command: &Command) -> io::Result<ExitCode> {
+    match command {
+        Command::Filter(args) => return filter(args),
+        Command::Convert(args) => convert(args),
+        Command::Sanitize(stage) => transform(stage, |file, config| {
+            Ok(sanitize_file(
+                file,
+                config.input.encoding,
+                &config.sanitizer,
+            )?)
+        }),
+        Command::Obfuscate(stage) => transform(stage, |file, config| {
+            let obfuscated = obfuscate_file(
+                file,
+                config.input.encoding,
+                &config.sanitizer,
+                &config.naming,
+                &config.limits,
+            )?;
+            if obfuscated.fallback {
+                eprintln!("{} needed the literal-blanker fallback", file.display());
+            }
+            Ok(obfuscated.source)
+        }),
+        Command::Blank(stage) => {
+            let config = Config::load_or_default(stage.config.as_deref())?.checked()?;
+            let blanked = files_under(&stage.path, "java")?
+                .iter()
+                .map(|f| blank_file(f, config.input.encoding, &config.sanitizer))
+                .collect::<io::Result<Vec<_>>>()?;
+            emit_json_lines(stage, &blanked)
+        }
+        Command::Inspect(stage) => {
+            let config = Config::load_or_default(stage.config.as_deref())?.checked()?;
+            let inspections = files_under(&stage.path, "java")?
+                .iter()
+                .map(|f| {
+                    inspect_file(
+                        f,
+                        config.input.encoding,
+                        &config.sanitizer,
+                        &config.naming,
+                        &config.limits,
+                    )
+                })
+                .collect::<io::Result<Vec<_>>>()?;
+            emit_json_lines(stage, &inspections)
+        }
+        Command::Verify(args) => verify(args),
+        Command::Serve(args) => serve(args),
+        Command::Stats(stage) => {
+            let config = Config::load_or_default(stage.config.as_deref())?.checked()?;
+            let stats = dataset_stats(&stage.path, &config.output.schema)?;
+            emit(
+                stage.output.as_deref(),
+                &(serde_json::to_string_pretty(&stats)? + "\n"),
+            )
+        }
+        Command::Split(split) => {
+            let stage = &split.stage;
+            let mut config = Config::load_or_default(stage.config.as_deref())?;
+            let settings = config.split.get_or_insert_default();
+            if let Some(ratios) = split.split_ratios {
+                settings.ratios = ratios;
+            }
+            settings.group = split.split_by.unwrap_or(settings.group);
+            settings.seed = split.split_seed.unwrap_or(settings.seed);
+            let config = config.checked()?;
+            let settings = config.split.unwrap_or_default();
+
+            let assignments = assign_splits(&stage.path, &settings, &config.output.schema)?;
+            match &stage.output {
+                Some(output) => copy_into_splits(&assignments, output),
+                None => {
+                    let mut stdout = io::stdout().lock();
+                    for a in &assignments {
+                        writeln!(stdout, "{}\t{}", a.split, a.file.display())?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+        Command::Dedup(args) => {
+            let mut config = Config::load_or_default(args.config.as_deref())?;
+            let dedup = config.dedup.get_or_insert_default();
+            dedup.near_threshold = args.near_dup_threshold.unwrap_or(dedup.near_threshold);
+            dedup.policy = args.dedup_policy.unwrap_or(dedup.policy);
+            let config = config.checked()?;
+            let dedup = config.dedup.unwrap_or_default();
+            let split = args.split || config.split.is_some();
+            let report = dedup_output(&args.dir, split, &config.output.schema, &dedup)?;
+            print_dedup(&report);
+            Ok(())
+        }
+    }?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let result = match &cli.command {
+        Some(command) => run_command(command),
+        None => convert(&cli.convert).map(|()| ExitCode::SUCCESS),
+    };
+    result.unwrap_or_else(|e| {
+        eprintln!("Error: {}", e);
+        ExitCode::from(EXIT_FAILURE)
+    })
+}
+
+#[cfg(test)]
+mod tests {
     #[test]
     fn obfuscate_str_checked_clean_source_no_fallback() {
         use java_dataset_converter_llm::obfuscator::obfuscate_str_checked;
@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Component, Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::Encoding;
+use crate::helper::{stable_hash, write_atomic};
+use crate::quarantine;
+
+/// File name of the run manifest inside the JSONL output directory.
+pub const MANIFEST_FILE: &str = "manifest.jsonl";
+
+/// What happened to one input file on its last run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Outcome {
+    /// Parsed cleanly; the pair keeps real literal content.
+    Clean,
+    /// Needed the literal-blanker fallback and was written blanked.
+    Fallback,
+    /// Needed the fallback but blanked output was not enabled; the source was
+    /// quarantined instead.
+    Skipped,
+    /// Excluded by a configured filter (size or token length).
+    Filtered { reason: String },
+    /// Failed at `stage`; retried on the next run.
+    Failed { stage: String },
+}
+
+impl Outcome {
+    pub fn is_failed(&self) -> bool {
+        matches!(self, Outcome::Failed { .. })
+    }
+}
+
+/// One manifest line: the state of one input after its last run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Input path relative to the input root, `/`-separated.
+    pub input: String,
+    pub content_hash: String,
+    pub config_fingerprint: String,
+    #[serde(flatten)]
+    pub outcome: Outcome,
+    /// JSONL file written for this input, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub output: Option<PathBuf>,
+    /// Encoding the input was decoded from, once it could be decoded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
+}
+
+/// Which inputs a run reprocesses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Selection {
+    /// Inputs that are new, changed, processed under another config, or failed.
+    #[default]
+    Changed,
+    /// Every input.
+    Force,
+    /// Only inputs whose last recorded outcome is a failure.
+    OnlyFailed,
+}
+
+/// Run manifest: content hash, config fingerprint and outcome per input.
+///
+/// Stored as an append-only JSONL journal so that a crash mid-run loses at
+/// most the line being written; later lines for the same input override
+/// earlier ones.  [`Manifest::compact`] rewrites it with one line per input.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    entries: BTreeMap<String, ManifestEntry>,
+    /// Number of entries whose `output` is each file.
+    outputs: BTreeMap<PathBuf, usize>,
+    journal: Option<BufWriter<File>>,
+}
+
+impl Manifest {
+    /// Load the manifest at `path` (an absent file is an empty manifest) and
+    /// open it for appending.
+    ///
+    /// Lines that do not parse — e.g. a line truncated by a crash — are
+    /// ignored, so the affected input is simply reprocessed.  A truncated
+    /// last line is terminated before appending, so the next entry starts on
+    /// a line of its own.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut manifest = Manifest::default();
+        let mut terminated = true;
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(path)?);
+            let mut line = String::new();
+            while reader.read_line(&mut line)? > 0 {
+                terminated = line.ends_with('\n');
+                if let Ok(entry) = serde_json::from_str::<ManifestEntry>(&line) {
+                    manifest.insert(entry);
+                }
+                line.clear();
+            }
+        }
+        let mut journal = OpenOptions::new().create(true).append(true).open(path)?;
+        if !terminated {
+            journal.write_all(b"\n")?;
+        }
+        manifest.journal = Some(BufWriter::new(journal));
+        Ok(manifest)
+    }
+
+    pub fn get(&self, input: &str) -> Option<&ManifestEntry> {
+        self.entries.get(input)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.entries.values()
+    }
+
+    /// Decide whether `input` must be (re)processed under `selection`.
+    pub fn needs_processing(
+        &self,
+        input: &str,
+        content_hash: &str,
+        config_fingerprint: &str,
+        selection: Selection,
+    ) -> bool {
+        let entry = self.entries.get(input);
+        match selection {
+            Selection::Force => true,
+            Selection::OnlyFailed => entry.is_some_and(|e| e.outcome.is_failed()),
+            Selection::Changed => match entry {
+                None => true,
+                Some(e) => {
+                    e.outcome.is_failed()
+                        || e.content_hash != content_hash
+                        || e.config_fingerprint != config_fingerprint
+                }
+            },
+        }
+    }
+
+    /// Record `entry` and append it to the journal, flushing immediately.
+    pub fn record(&mut self, entry: ManifestEntry) -> io::Result<()> {
+        if let Some(journal) = self.journal.as_mut() {
+            writeln!(journal, "{}", serde_json::to_string(&entry)?)?;
+            journal.flush()?;
+        }
+        self.insert(entry);
+        Ok(())
+    }
+
+    fn insert(&mut self, entry: ManifestEntry) {
+        if let Some(output) = &entry.output {
+            *self.outputs.entry(output.clone()).or_default() += 1;
+        }
+        if let Some(previous) = self.entries.insert(entry.input.clone(), entry)
+            && let Some(output) = previous.output
+            && let Some(count) = self.outputs.get_mut(&output)
+        {
+            *count -= 1;
+            if *count == 0 {
+                self.outputs.remove(&output);
+            }
+        }
+    }
+
+    /// Remove the output previously written for `input` when the input no
+    /// longer maps to it (e.g. after its split changed, or a quarantined
+    /// source was fixed) and no other input's entry refers to it.
+    pub fn remove_stale_output(&self, input: &str, new_output: Option<&Path>) {
+        if let Some(entry) = self.entries.get(input)
+            && let Some(old) = entry.output.as_deref()
+            && Some(old) != new_output
+            && self.outputs.get(old) == Some(&1)
+        {
+            if entry.outcome == Outcome::Skipped {
+                quarantine::release(old);
+            } else {
+                let _ = fs::remove_file(old);
+            }
+        }
+    }
+
+    /// Rewrite the journal at `path` with exactly one line per input.
+    pub fn compact(&mut self, path: &Path) -> io::Result<()> {
+        self.journal = None;
+        write_atomic(path, |w| {
+            for entry in self.entries.values() {
+                writeln!(w, "{}", serde_json::to_string(entry)?)?;
+            }
+            Ok(())
+        })?;
+        let journal = OpenOptions::new().append(true).open(path)?;
+        self.journal = Some(BufWriter::new(journal));
+        Ok(())
+    }
+}
+
+/// Hex content hash of an input file.
+pub fn content_hash(bytes: &[u8]) -> String {
+    format!("{:016x}", stable_hash(bytes))
+}
+
+/// Fingerprint of every setting that influences the output of a file.
+///
+/// The crate version is mixed in so that upgrading the sanitizer or the
+/// naming scheme invalidates earlier results.
+pub fn config_fingerprint<T: Serialize>(settings: &T) -> String {
+    let json = serde_json::to_string(settings).unwrap_or_default();
+    let keyed = format!("{}|{}", env!("CARGO_PKG_VERSION"), json);
+    format!("{:016x}", stable_hash(keyed.as_bytes()))
+}
+
+/// Manifest key for `file`: its path relative to `input_root`, `/`-separated.
+pub fn input_key(input_root: &Path, file: &Path) -> String {
+    let relative = file.strip_prefix(input_root).unwrap_or(file);
+    relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn entry(input: &str, hash: &str, fp: &str, outcome: Outcome) -> ManifestEntry {
+        ManifestEntry {
+            input: input.to_string(),
+            content_hash: hash.to_string(),
+            config_fingerprint: fp.to_string(),
+            outcome,
+            output: None,
+            encoding: None,
+        }
+    }
+
+    #[test]
+    fn unchanged_input_is_skipped_and_changes_are_detected() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(MANIFEST_FILE);
+        let mut m = Manifest::open(&path).unwrap();
+        m.record(entry("a.java", "h1", "fp", Outcome::Clean))
+            .unwrap();
+
+        assert!(!m.needs_processing("a.java", "h1", "fp", Selection::Changed));
+        assert!(m.needs_processing("a.java", "h2", "fp", Selection::Changed));
+        assert!(m.needs_processing("a.java", "h1", "fp2", Selection::Changed));
+        assert!(m.needs_processing("b.java", "h1", "fp", Selection::Changed));
+        assert!(m.needs_processing("a.java", "h1", "fp", Selection::Force));
+    }
+
+    #[test]
+    fn failed_inputs_are_retried_and_selectable() {
+        let dir = TempDir::new().unwrap();
+        let mut m = Manifest::open(&dir.path().join(MANIFEST_FILE)).unwrap();
+        let failed = Outcome::Failed {
+            stage: "obfuscate".to_string(),
+        };
+        m.record(entry("bad.java", "h", "fp", failed)).unwrap();
+        m.record(entry("good.java", "h", "fp", Outcome::Clean))
+            .unwrap();
+
+        assert!(m.needs_processing("bad.java", "h", "fp", Selection::Changed));
+        assert!(m.needs_processing("bad.java", "h", "fp", Selection::OnlyFailed));
+        assert!(!m.needs_processing("good.java", "h", "fp", Selection::OnlyFailed));
+        assert!(!m.needs_processing("new.java", "h", "fp", Selection::OnlyFailed));
+    }
+
+    #[test]
+    fn journal_survives_reload_and_truncated_lines() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join(MANIFEST_FILE);
+        {
+            let mut m = Manifest::open(&path).unwrap();
+            m.record(entry("a.java", "h1", "fp", Outcome::Skipped))
+                .unwrap();
+            m.record(entry("a.java", "h2", "fp", Outcome::Fallback))
+                .unwrap();
+        }
+        // Simulate a crash halfway through writing a line.
+        let mut f = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(f, "{{\"input\":\"b.ja").unwrap();
+        drop(f);
+
+        {
+            let mut m = Manifest::open(&path).unwrap();
+            assert_eq!(m.get("a.java").unwrap().content_hash, "h2");
+            assert_eq!(m.get("a.java").unwrap().outcome, Outcome::Fallback);
+            assert!(m.get("b.java").is_none());
+            // Appended after the truncated line, without compacting.
+            m.record(entry("c.java", "h3", "fp", Outcome::Clean))
+                .unwrap();
+        }
+
+        let mut m = Manifest::open(&path).unwrap();
+        assert_eq!(m.get("c.java").unwrap().content_hash, "h3");
+        assert!(m.get("b.java").is_none());
+
+        m.compact(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    }
+
+    #[test]
+    fn stale_output_is_kept_while_another_entry_refers_to_it() {
+        let dir = TempDir::new().unwrap();
+        let shared = dir.path().join("T.java.jsonl");
+        fs::write(&shared, "{}").unwrap();
+        let mut m = Manifest::open(&dir.path().join(MANIFEST_FILE)).unwrap();
+        for input in ["a/T.java", "b/T.java"] {
+            m.record(ManifestEntry {
+                output: Some(shared.clone()),
+                ..entry(input, "h", "fp", Outcome::Clean)
+            })
+            .unwrap();
+        }
+
+        m.remove_stale_output("a/T.java", Some(&dir.path().join("a/T.java.jsonl")));
+        assert!(shared.is_file());
+
+        m.record(entry(
+            "b/T.java",
+            "h",
+            "fp",
+            Outcome::Filtered {
+                reason: "too big".to_string(),
+            },
+        ))
+        .unwrap();
+        m.remove_stale_output("a/T.java", None);
+        assert!(!shared.exists());
+    }
+
+    #[test]
+    fn fingerprint_depends_on_settings() {
+        assert_eq!(
+            config_fingerprint(&(1, true)),
+            config_fingerprint(&(1, true))
+        );
+        assert_ne!(
+            config_fingerprint(&(1, true)),
+            config_fingerprint(&(1, false))
+        );
+    }
+
+    #[test]
+    fn input_key_is_relative_and_slash_separated() {
+        let key = input_key(
+            Path::new("data"),
+            &Path::new("data").join("a").join("T.java"),
+        );
+        assert_eq!(key, "a/T.java");
+    }
+}
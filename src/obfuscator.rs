@@ -1,13 +1,20 @@
 // Disclaimer: Code is made using help of AI, so errors or some things might not be perfect.
 
 use std::cell::RefCell;
-use std::collections::HashMap;
-use std::fs;
-use std::io;
-
-use tree_sitter::{Node, Parser};
-
-use crate::literal_blanker::{blank_literals, blank_literals_permanently, restore_literals};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tree_sitter::{Node, ParseOptions, ParseState, Parser, Tree};
+
+use crate::encoding::read_source;
+use crate::error::{Error, Result};
+use crate::helper::{stable_hash, write_atomic};
+use crate::lexer::{Lexer, Token, TokenKind};
+use crate::literal_blanker::{LiteralStore, blank_literals, blank_literals_permanently};
 use crate::sanitizer::{sanitize_backslashes, sanitize_structural};
 
 thread_local! {
@@ -90,59 +97,147 @@ fn is_field_node(node: Node, parent: Node, field: &str) -> bool {
         .unwrap_or(false)
 }
 
-fn obfuscate_function_names(java_code: &str) -> String {
-    // Re-use the thread-local parser instead of creating a new one.
-    let tree = PARSER.with(|p| p.borrow_mut().parse(java_code, None));
+/// Prefixes of the generated identifiers: `func_1`, `var_1`, … by default.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Naming {
+    pub function_prefix: String,
+    pub variable_prefix: String,
+    /// Number the generated names by a permutation drawn from this seed
+    /// (`var_40213`, `var_977`, …) instead of counting up, so each seed gives
+    /// a different but equally consistent renaming.
+    pub seed: Option<u64>,
+}
 
-    let tree = match tree {
-        Some(t) => t,
-        None => return java_code.to_string(),
-    };
+impl Default for Naming {
+    fn default() -> Self {
+        Naming {
+            function_prefix: "func_".to_string(),
+            variable_prefix: "var_".to_string(),
+            seed: None,
+        }
+    }
+}
 
-    let root = tree.root_node();
-    let mut replacements: Vec<Replacement> = Vec::new();
-    let mut func_counter: usize = 1;
-
-    fn walk(
-        node: Node,
-        source: &str,
-        func_counter: &mut usize,
-        replacements: &mut Vec<Replacement>,
-    ) {
-        if node.kind() == "method_declaration" {
-            if let Some(name_node) = node.child_by_field_name("name") {
-                let start0 = name_node.start_byte() as usize;
-                let end0 = name_node.end_byte() as usize;
-
-                if let Some((start, end)) = trim_to_identifier_span(source, start0, end0) {
-                    let new_name = format!("func_{}", *func_counter);
-                    *func_counter += 1;
-                    replacements.push(Replacement {
-                        start,
-                        end,
-                        text: new_name,
-                    });
-                }
-            }
+/// Size of the range seeded names are permuted within; later names keep
+/// their sequential number, which lies outside the range.
+const SEEDED_RANGE: u64 = 1 << 16;
+
+/// Generator of names `<prefix>1`, `<prefix>2`, … or their seeded
+/// permutation.
+struct Names<'a> {
+    prefix: &'a str,
+    next: u64,
+    /// Multiplier (odd) and offset of an affine permutation mod
+    /// [`SEEDED_RANGE`].
+    permutation: Option<(u64, u64)>,
+}
+
+impl<'a> Names<'a> {
+    fn new(prefix: &'a str, seed: Option<u64>) -> Self {
+        let permutation = seed.map(|seed| {
+            let h = stable_hash(&seed.to_le_bytes());
+            (h | 1, h >> 32)
+        });
+        Names {
+            prefix,
+            next: 1,
+            permutation,
         }
+    }
 
-        let mut cursor = node.walk();
-        if cursor.goto_first_child() {
-            loop {
-                walk(cursor.node(), source, func_counter, replacements);
-                if !cursor.goto_next_sibling() {
-                    break;
-                }
+    fn fresh(&mut self) -> String {
+        let n = match self.permutation {
+            Some((a, b)) if self.next <= SEEDED_RANGE => {
+                (a.wrapping_mul(self.next - 1).wrapping_add(b) % SEEDED_RANGE) + 1
             }
+            _ => self.next,
+        };
+        self.next += 1;
+        format!("{}{}", self.prefix, n)
+    }
+}
+
+/// Event passed to the visitor of [`traverse`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Enter,
+    Leave,
+}
+
+/// Visit `root` and its descendants in pre-order with a tree cursor instead
+/// of recursion, so deeply nested sources (long `else if` chains, huge array
+/// initializers) cannot overflow a worker stack.
+///
+/// `visit` gets [`Step::Enter`] for every node and returns whether to
+/// descend into its children, then [`Step::Leave`] once the node's subtree is
+/// done.
+fn traverse<'t>(root: Node<'t>, mut visit: impl FnMut(Node<'t>, Step) -> bool) {
+    let mut cursor = root.walk();
+    let mut depth = 0usize;
+    loop {
+        let node = cursor.node();
+        if visit(node, Step::Enter) && cursor.goto_first_child() {
+            depth += 1;
+            continue;
+        }
+        visit(node, Step::Leave);
+        loop {
+            if depth == 0 {
+                return;
+            }
+            if cursor.goto_next_sibling() {
+                break;
+            }
+            cursor.goto_parent();
+            depth -= 1;
+            visit(cursor.node(), Step::Leave);
         }
     }
+}
 
-    walk(root, java_code, &mut func_counter, &mut replacements);
-    apply_replacements(java_code, &replacements)
+/// Children of `node`, in order.
+fn children<'t>(node: Node<'t>) -> impl Iterator<Item = Node<'t>> {
+    let mut cursor = node.walk();
+    let mut first = true;
+    std::iter::from_fn(move || {
+        let moved = if first {
+            first = false;
+            cursor.goto_first_child()
+        } else {
+            cursor.goto_next_sibling()
+        };
+        moved.then(|| cursor.node())
+    })
 }
 
-fn is_non_variable_identifier_context(ident: Node) -> bool {
-    let Some(parent) = ident.parent() else {
+/// Renames of every method declaration name below `root`.
+fn function_replacements(root: Node, source: &str, naming: &Naming) -> Vec<Replacement> {
+    let mut replacements: Vec<Replacement> = Vec::new();
+    let mut func_counter = Names::new(&naming.function_prefix, naming.seed);
+
+    traverse(root, |node, step| {
+        if step == Step::Enter
+            && node.kind() == "method_declaration"
+            && let Some(name_node) = node.child_by_field_name("name")
+            && let Some((start, end)) =
+                trim_to_identifier_span(source, name_node.start_byte(), name_node.end_byte())
+        {
+            replacements.push(Replacement {
+                start,
+                end,
+                text: func_counter.fresh(),
+            });
+        }
+        true
+    });
+    replacements
+}
+
+/// `parent` is the parent of `ident`, passed in because `Node::parent`
+/// walks down from the root and is slow in deeply nested trees.
+fn is_non_variable_identifier_context(ident: Node, parent: Option<Node>) -> bool {
+    let Some(parent) = parent else {
         return false;
     };
     let pk = parent.kind();
@@ -197,17 +292,16 @@ fn declare_identifier(
     java_code: &str,
     scopes: &mut [HashMap<String, String>],
     replacements: &mut Vec<Replacement>,
-    local_var_counter: &mut usize,
+    local_var_counter: &mut Names,
 ) {
-    let start0 = name_node.start_byte() as usize;
-    let end0 = name_node.end_byte() as usize;
+    let start0 = name_node.start_byte();
+    let end0 = name_node.end_byte();
     let Some((start, end)) = trim_to_identifier_span(java_code, start0, end0) else {
         return;
     };
 
     let name = &java_code[start..end];
-    let new_name = format!("var_{}", *local_var_counter);
-    *local_var_counter += 1;
+    let new_name = local_var_counter.fresh();
 
     if let Some(last) = scopes.last_mut() {
         last.insert(name.to_string(), new_name.clone());
@@ -220,433 +314,301 @@ fn declare_identifier(
     });
 }
 
-fn collect_class_fields(
-    node: Node,
-    java_code: &str,
-    class_scope: &mut HashMap<String, String>,
-    counter: &mut usize,
-) {
-    if node.kind() == "field_declaration" {
-        let mut c = node.walk();
-        if c.goto_first_child() {
-            loop {
-                let ch = c.node();
-                if ch.kind() == "variable_declarator" {
-                    if let Some(name_node) = ch.child_by_field_name("name") {
-                        let start = name_node.start_byte();
-                        let end = name_node.end_byte();
-                        if let Some((s, e)) = trim_to_identifier_span(java_code, start, end) {
-                            let name = &java_code[s..e];
-                            let new_name = format!("var_{}", *counter);
-                            *counter += 1;
-                            class_scope.insert(name.to_string(), new_name);
-                        }
-                    }
-                }
-                if !c.goto_next_sibling() {
-                    break;
-                }
-            }
+/// Name spans of the declarators of every field declaration below `root`.
+fn field_declarator_spans(root: Node, java_code: &str) -> Vec<(usize, usize)> {
+    let mut spans = Vec::new();
+    traverse(root, |node, step| {
+        if step == Step::Leave || node.kind() != "field_declaration" {
+            return true;
         }
-        // Don't recurse into field_declaration children further.
-        return;
-    }
-
-    let mut cursor = node.walk();
-    if cursor.goto_first_child() {
-        loop {
-            collect_class_fields(cursor.node(), java_code, class_scope, counter);
-            if !cursor.goto_next_sibling() {
-                break;
+        for ch in children(node) {
+            if ch.kind() == "variable_declarator"
+                && let Some(name_node) = ch.child_by_field_name("name")
+                && let Some(span) =
+                    trim_to_identifier_span(java_code, name_node.start_byte(), name_node.end_byte())
+            {
+                spans.push(span);
             }
         }
-    }
+        // Don't descend into field_declaration children further.
+        false
+    });
+    spans
 }
 
-fn obfuscate_code(java_code: &str) -> String {
-    // Re-use the thread-local parser instead of creating a new one.
-    let tree = PARSER.with(|p| p.borrow_mut().parse(java_code, None));
-
-    let tree = match tree {
-        Some(t) => t,
-        None => return java_code.to_string(),
+/// Declare the variables a node introduces in the innermost scope.
+fn declare_node(
+    node: Node,
+    java_code: &str,
+    scopes: &mut [HashMap<String, String>],
+    replacements: &mut Vec<Replacement>,
+    local_var_counter: &mut Names,
+) {
+    let mut declare = |name_node| {
+        declare_identifier(
+            name_node,
+            java_code,
+            scopes,
+            replacements,
+            local_var_counter,
+        )
     };
 
-    let root = tree.root_node();
-    let mut replacements: Vec<Replacement> = Vec::new();
-    let mut local_var_counter: usize = 1;
-
-    let mut class_scope: HashMap<String, String> = HashMap::new();
-    collect_class_fields(root, java_code, &mut class_scope, &mut local_var_counter);
-
-    // Also emit replacements for the field declaration sites themselves.
-    // (The field names at declaration must be renamed in the output too.)
-    fn emit_field_declaration_replacements(
-        node: Node,
-        java_code: &str,
-        class_scope: &HashMap<String, String>,
-        replacements: &mut Vec<Replacement>,
-    ) {
-        if node.kind() == "field_declaration" {
-            let mut c = node.walk();
-            if c.goto_first_child() {
-                loop {
-                    let ch = c.node();
-                    if ch.kind() == "variable_declarator" {
-                        if let Some(name_node) = ch.child_by_field_name("name") {
-                            let start = name_node.start_byte();
-                            let end = name_node.end_byte();
-                            if let Some((s, e)) = trim_to_identifier_span(java_code, start, end) {
-                                let name = &java_code[s..e];
-                                if let Some(new_name) = class_scope.get(name) {
-                                    replacements.push(Replacement {
-                                        start: s,
-                                        end: e,
-                                        text: new_name.clone(),
-                                    });
-                                }
-                            }
+    match node.kind() {
+        // Lambda parameters (in lambda scope)
+        "lambda_expression" => {
+            if let Some(params) = node.child_by_field_name("parameters") {
+                for p in children(params) {
+                    if p.kind() == "formal_parameter" {
+                        if let Some(name_node) = p.child_by_field_name("name") {
+                            declare(name_node);
                         }
-                    }
-                    if !c.goto_next_sibling() {
-                        break;
+                    } else if p.kind() == "identifier" {
+                        // single-identifier param
+                        declare(p);
                     }
                 }
             }
-            return;
         }
-        let mut cursor = node.walk();
-        if cursor.goto_first_child() {
-            loop {
-                emit_field_declaration_replacements(
-                    cursor.node(),
-                    java_code,
-                    class_scope,
-                    replacements,
-                );
-                if !cursor.goto_next_sibling() {
-                    break;
+        // Local variable declarations: int x = 0;  (also supports: int a=1, b=2;)
+        "local_variable_declaration" => {
+            for child in children(node) {
+                if child.kind() == "variable_declarator"
+                    && let Some(name_node) = child.child_by_field_name("name")
+                {
+                    declare(name_node);
                 }
             }
         }
-    }
-
-    emit_field_declaration_replacements(root, java_code, &class_scope, &mut replacements);
-
-    fn obfuscate_method(
-        method: Node,
-        java_code: &str,
-        replacements: &mut Vec<Replacement>,
-        local_var_counter: &mut usize,
-        class_scope: HashMap<String, String>,
-    ) {
-        let mut scopes: Vec<HashMap<String, String>> = vec![class_scope];
-
-        // Parameters are in method scope.
-        if let Some(params) = method.child_by_field_name("parameters") {
-            let mut c = params.walk();
-            if c.goto_first_child() {
-                loop {
-                    let p = c.node();
-                    if p.kind() == "formal_parameter" {
-                        if let Some(name_node) = p.child_by_field_name("name") {
-                            declare_identifier(
-                                name_node,
-                                java_code,
-                                &mut scopes,
-                                replacements,
-                                local_var_counter,
-                            );
-                        }
-                    }
-                    if !c.goto_next_sibling() {
-                        break;
-                    }
+        // Enhanced for: for (Type x : expr)
+        "enhanced_for_statement" => {
+            if let Some(name_node) = node.child_by_field_name("name") {
+                declare(name_node);
+            } else {
+                // Fallback: the last identifier before ":"
+                let last_ident = children(node)
+                    .take_while(|ch| ch.kind() != ":")
+                    .filter(|ch| ch.kind() == "identifier")
+                    .last();
+                if let Some(name_node) = last_ident {
+                    declare(name_node);
                 }
             }
         }
-
-        let Some(body) = method.child_by_field_name("body") else {
-            return;
-        };
-
-        fn walk(
-            node: Node,
-            java_code: &str,
-            scopes: &mut Vec<HashMap<String, String>>,
-            replacements: &mut Vec<Replacement>,
-            local_var_counter: &mut usize,
-        ) {
-            // Enter new scope for blocks
-            let opens_block_scope = node.kind() == "block";
-
-            // Enter new scope for lambdas (they introduce their own params)
-            let opens_lambda_scope = node.kind() == "lambda_expression";
-
-            let opens_for_scope = matches!(node.kind(), "for_statement" | "enhanced_for_statement");
-
-            if opens_block_scope || opens_lambda_scope || opens_for_scope {
-                scopes.push(HashMap::new());
+        // Catch clause parameter: catch (Exception e)
+        "catch_clause" => {
+            if let Some(param) = node.child_by_field_name("parameter")
+                && (param.kind() == "catch_formal_parameter" || param.kind() == "formal_parameter")
+                && let Some(name_node) = children(param).filter(|c| c.kind() == "identifier").last()
+            {
+                declare(name_node);
             }
-
-            // Handle lambda parameters (in lambda scope)
-            if node.kind() == "lambda_expression" {
-                if let Some(params) = node.child_by_field_name("parameters") {
-                    let mut c = params.walk();
-                    if c.goto_first_child() {
-                        loop {
-                            let p = c.node();
-                            if p.kind() == "formal_parameter" {
-                                if let Some(name_node) = p.child_by_field_name("name") {
-                                    declare_identifier(
-                                        name_node,
-                                        java_code,
-                                        scopes,
-                                        replacements,
-                                        local_var_counter,
-                                    );
-                                }
-                            } else if p.kind() == "identifier" {
-                                // single-identifier param
-                                declare_identifier(
-                                    p,
-                                    java_code,
-                                    scopes,
-                                    replacements,
-                                    local_var_counter,
-                                );
-                            }
-                            if !c.goto_next_sibling() {
-                                break;
-                            }
-                        }
-                    }
-                }
-            }
-
-            // Local variable declarations: int x = 0;  (also supports: int a=1, b=2;)
-            if node.kind() == "local_variable_declaration" {
-                let mut c = node.walk();
-                if c.goto_first_child() {
-                    loop {
-                        let child = c.node();
-                        if child.kind() == "variable_declarator" {
-                            if let Some(name_node) = child.child_by_field_name("name") {
-                                declare_identifier(
-                                    name_node,
-                                    java_code,
-                                    scopes,
-                                    replacements,
-                                    local_var_counter,
-                                );
-                            }
-                        }
-                        if !c.goto_next_sibling() {
-                            break;
-                        }
-                    }
-                }
+        }
+        // Try-with-resources: try (InputStream in = ...)
+        "resource" => {
+            if let Some(name_node) = node.child_by_field_name("name") {
+                declare(name_node);
             }
+        }
+        _ => {}
+    }
+}
 
-            // Enhanced for: for (Type x : expr)
-            if node.kind() == "enhanced_for_statement" {
-                let name_node_opt = node.child_by_field_name("name");
-
-                if let Some(name_node) = name_node_opt {
-                    declare_identifier(
-                        name_node,
-                        java_code,
-                        scopes,
-                        replacements,
-                        local_var_counter,
-                    );
-                } else {
-                    // Fallback: Walk children and collect identifiers until we hit ":"
-                    let mut c = node.walk();
-                    let mut last_ident: Option<Node> = None;
-                    if c.goto_first_child() {
-                        loop {
-                            let ch = c.node();
-                            if ch.kind() == ":" {
-                                break;
-                            }
-                            if ch.kind() == "identifier" {
-                                last_ident = Some(ch);
-                            }
-                            if !c.goto_next_sibling() {
-                                break;
-                            }
-                        }
-                    }
-                    if let Some(name_node) = last_ident {
-                        declare_identifier(
-                            name_node,
-                            java_code,
-                            scopes,
-                            replacements,
-                            local_var_counter,
-                        );
-                    }
-                }
-            }
+/// Rename a variable usage found in scope.
+fn rename_usage(
+    node: Node,
+    parent: Option<Node>,
+    java_code: &str,
+    scopes: &[HashMap<String, String>],
+    replacements: &mut Vec<Replacement>,
+) {
+    let Some((start, end)) = trim_to_identifier_span(java_code, node.start_byte(), node.end_byte())
+    else {
+        return;
+    };
+    let name = &java_code[start..end];
 
-            // Catch clause parameter: catch (Exception e)
-            if node.kind() == "catch_clause" {
-                if let Some(param) = node.child_by_field_name("parameter") {
-                    if param.kind() == "catch_formal_parameter"
-                        || param.kind() == "formal_parameter"
-                    {
-                        let mut name_node: Option<Node> = None;
-                        let mut c = param.walk();
-                        if c.goto_first_child() {
-                            loop {
-                                if c.node().kind() == "identifier" {
-                                    name_node = Some(c.node());
-                                }
-                                if !c.goto_next_sibling() {
-                                    break;
-                                }
-                            }
-                        }
-                        if let Some(n) = name_node {
-                            declare_identifier(
-                                n,
-                                java_code,
-                                scopes,
-                                replacements,
-                                local_var_counter,
-                            );
-                        }
-                    }
-                }
-            }
+    let skip_type = parent
+        .map(|p| {
+            matches!(
+                p.kind(),
+                "type_identifier" | "scoped_type_identifier" | "generic_type" | "array_type"
+            )
+        })
+        .unwrap_or(false);
+
+    if !skip_type && let Some(new_name) = lookup_scope(scopes, name) {
+        replacements.push(Replacement {
+            start,
+            end,
+            text: new_name,
+        });
+    }
+}
 
-            // Try-with-resources: try (InputStream in = ...)
-            if node.kind() == "resource" {
-                if let Some(name_node) = node.child_by_field_name("name") {
-                    declare_identifier(
-                        name_node,
-                        java_code,
-                        scopes,
-                        replacements,
-                        local_var_counter,
-                    );
-                }
-            }
+/// Whether `node` opens a scope of its own: blocks, lambdas (they introduce
+/// their own params) and `for` loops.
+fn opens_scope(node: Node) -> bool {
+    matches!(
+        node.kind(),
+        "block" | "lambda_expression" | "for_statement" | "enhanced_for_statement"
+    )
+}
 
-            // Identifier usages (variable references)
-            if node.kind() == "identifier" {
-                if !is_non_variable_identifier_context(node) {
-                    let start0 = node.start_byte() as usize;
-                    let end0 = node.end_byte() as usize;
-
-                    if let Some((start, end)) = trim_to_identifier_span(java_code, start0, end0) {
-                        let name = &java_code[start..end];
-
-                        let skip_type = node
-                            .parent()
-                            .map(|p| {
-                                matches!(
-                                    p.kind(),
-                                    "type_identifier"
-                                        | "scoped_type_identifier"
-                                        | "generic_type"
-                                        | "array_type"
-                                )
-                            })
-                            .unwrap_or(false);
-
-                        if !skip_type {
-                            if let Some(new_name) = lookup_scope(scopes, name) {
-                                replacements.push(Replacement {
-                                    start,
-                                    end,
-                                    text: new_name,
-                                });
-                            }
-                        }
-                    }
-                }
+fn obfuscate_method(
+    method: Node,
+    java_code: &str,
+    replacements: &mut Vec<Replacement>,
+    local_var_counter: &mut Names,
+    class_scope: HashMap<String, String>,
+) {
+    let mut scopes: Vec<HashMap<String, String>> = vec![class_scope];
+
+    // Parameters are in method scope.
+    if let Some(params) = method.child_by_field_name("parameters") {
+        for p in children(params) {
+            if p.kind() == "formal_parameter"
+                && let Some(name_node) = p.child_by_field_name("name")
+            {
+                declare_identifier(
+                    name_node,
+                    java_code,
+                    &mut scopes,
+                    replacements,
+                    local_var_counter,
+                );
             }
+        }
+    }
 
-            // Walk children
-            let mut cursor = node.walk();
-            if cursor.goto_first_child() {
-                loop {
-                    walk(
-                        cursor.node(),
-                        java_code,
-                        scopes,
-                        replacements,
-                        local_var_counter,
-                    );
-                    if !cursor.goto_next_sibling() {
-                        break;
-                    }
-                }
-            }
+    let Some(body) = method.child_by_field_name("body") else {
+        return;
+    };
 
-            if opens_block_scope || opens_lambda_scope || opens_for_scope {
+    // Ancestors of the current node within `body`.
+    let mut ancestors: Vec<Node> = Vec::new();
+    traverse(body, |node, step| {
+        if step == Step::Leave {
+            ancestors.pop();
+            if opens_scope(node) {
                 scopes.pop();
             }
+            return true;
         }
-
-        walk(
-            body,
+        let parent = ancestors.last().copied();
+        ancestors.push(node);
+        if opens_scope(node) {
+            scopes.push(HashMap::new());
+        }
+        declare_node(
+            node,
             java_code,
             &mut scopes,
             replacements,
             local_var_counter,
         );
+
+        // Identifier usages (variable references)
+        if node.kind() == "identifier" && !is_non_variable_identifier_context(node, parent) {
+            rename_usage(node, parent, java_code, &scopes, replacements);
+        }
+        true
+    });
+}
+
+/// Renames of fields, parameters and locals below `root`.
+fn variable_replacements(root: Node, java_code: &str, naming: &Naming) -> Vec<Replacement> {
+    let mut replacements: Vec<Replacement> = Vec::new();
+    let mut local_var_counter = Names::new(&naming.variable_prefix, naming.seed);
+
+    // Field names get a name each, in order; a later field with the same name
+    // overrides the earlier one.  The declaration sites are renamed too.
+    let fields = field_declarator_spans(root, java_code);
+    let mut class_scope: HashMap<String, String> = HashMap::new();
+    for &(s, e) in &fields {
+        class_scope.insert(java_code[s..e].to_string(), local_var_counter.fresh());
+    }
+    for (s, e) in fields {
+        if let Some(new_name) = class_scope.get(&java_code[s..e]) {
+            replacements.push(Replacement {
+                start: s,
+                end: e,
+                text: new_name.clone(),
+            });
+        }
     }
 
-    fn walk_methods(
-        node: Node,
-        java_code: &str,
-        replacements: &mut Vec<Replacement>,
-        local_var_counter: &mut usize,
-        class_scope: &HashMap<String, String>,
-    ) {
+    traverse(root, |node, step| {
         // Do not descend into ERROR nodes.
-        if node.is_error() {
-            return;
+        if step == Step::Leave || node.is_error() {
+            return false;
         }
-
         if node.kind() == "method_declaration" {
             obfuscate_method(
                 node,
                 java_code,
-                replacements,
-                local_var_counter,
+                &mut replacements,
+                &mut local_var_counter,
                 class_scope.clone(),
             );
         }
+        true
+    });
+    replacements
+}
 
-        let mut cursor = node.walk();
-        if cursor.goto_first_child() {
-            loop {
-                walk_methods(
-                    cursor.node(),
-                    java_code,
-                    replacements,
-                    local_var_counter,
-                    class_scope,
-                );
-                if !cursor.goto_next_sibling() {
-                    break;
-                }
-            }
-        }
-    }
+/// Rename methods and variables of `java_code`, whose parse tree is `root`.
+///
+/// Both rename sets come from the same tree.  A method name wins over a
+/// variable rename of the same span (a method of an anonymous class named
+/// like a local in scope).
+fn rename_identifiers(root: Node, java_code: &str, naming: &Naming) -> String {
+    apply_renames(java_code, rename_replacements(root, java_code, naming))
+}
+
+/// The replacements of [`rename_identifiers`], variables first.
+fn rename_replacements(root: Node, java_code: &str, naming: &Naming) -> Vec<Replacement> {
+    let mut replacements = variable_replacements(root, java_code, naming);
+    replacements.extend(function_replacements(root, java_code, naming));
+    replacements
+}
 
-    walk_methods(
-        root,
-        java_code,
-        &mut replacements,
-        &mut local_var_counter,
-        &class_scope,
-    );
+/// [`rename_identifiers`] over a blanked source, restoring its literals in
+/// the same pass.  Placeholders are replaced at the positions recorded in
+/// `store`; a rename that touches one is an [`Error::LiteralStoreMismatch`].
+fn rename_and_restore(
+    root: Node,
+    blanked: &str,
+    store: &LiteralStore,
+    naming: &Naming,
+) -> Result<String> {
+    let placements = store.placements(blanked)?;
+    let renames = rename_replacements(root, blanked, naming);
+    let untouched = placements
+        .iter()
+        .filter(|(range, _)| {
+            !renames
+                .iter()
+                .any(|r| r.start < range.end && range.start < r.end)
+        })
+        .count();
+    if untouched != placements.len() {
+        return Err(Error::LiteralStoreMismatch {
+            label: String::new(),
+            expected: placements.len(),
+            restored: untouched,
+        });
+    }
+    let literals = placements.into_iter().map(|(range, original)| Replacement {
+        start: range.start,
+        end: range.end,
+        text: original.to_string(),
+    });
+    Ok(apply_renames(blanked, renames.into_iter().chain(literals)))
+}
 
+/// Apply `replacements`; of several for the same span, the last one wins.
+fn apply_renames(java_code: &str, replacements: impl IntoIterator<Item = Replacement>) -> String {
     let mut dedup: HashMap<(usize, usize), String> = HashMap::new();
     for r in replacements {
         dedup.insert((r.start, r.end), r.text);
@@ -665,15 +627,217 @@ fn obfuscate_code(java_code: &str) -> String {
     apply_replacements(java_code, &replacements)
 }
 
-/// Returns `true` if the tree-sitter parse tree for `src` contains any ERROR
-/// nodes, indicating that the source is not valid Java.
-fn has_parse_errors(src: &str) -> bool {
-    PARSER.with(|p| {
-        p.borrow_mut()
-            .parse(src, None)
-            .map(|t| t.root_node().has_error())
-            .unwrap_or(true)
-    })
+/// Method renaming alone, with its own parse (the first pass of the former
+/// two-pass obfuscation).
+#[cfg(any(test, feature = "bench"))]
+fn obfuscate_function_names(java_code: &str, naming: &Naming) -> String {
+    match parse(java_code) {
+        Some(tree) => apply_renames(
+            java_code,
+            function_replacements(tree.root_node(), java_code, naming),
+        ),
+        None => java_code.to_string(),
+    }
+}
+
+/// Variable renaming alone, with its own parse (the second pass of the
+/// former two-pass obfuscation).
+#[cfg(any(test, feature = "bench"))]
+fn obfuscate_code(java_code: &str, naming: &Naming) -> String {
+    match parse(java_code) {
+        Some(tree) => apply_renames(
+            java_code,
+            variable_replacements(tree.root_node(), java_code, naming),
+        ),
+        None => java_code.to_string(),
+    }
+}
+
+/// Parse `src` with the thread-local parser.
+fn parse(src: &str) -> Option<Tree> {
+    PARSER.with(|p| p.borrow_mut().parse(src, None))
+}
+
+/// Per-file resource limits of obfuscation.  Unset limits are not checked.
+///
+/// A source over a limit fails with [`Error::LimitExceeded`], which the
+/// pipeline logs like any other per-file error.  (`filters.max_bytes`, by
+/// contrast, drops a file from the dataset without an error.)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Largest sanitized source, in bytes.
+    pub max_bytes: Option<usize>,
+    /// Most nodes in the parse tree.
+    pub max_nodes: Option<usize>,
+    /// Longest a single tree-sitter parse may take, in milliseconds.
+    pub parse_timeout_ms: Option<u64>,
+}
+
+/// The limit a source exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitExceeded {
+    Bytes { size: usize, max: usize },
+    Nodes { count: usize, max: usize },
+    ParseTimeout { max_ms: u64 },
+}
+
+impl fmt::Display for LimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LimitExceeded::Bytes { size, max } => {
+                write!(f, "{} bytes exceeds max_bytes {}", size, max)
+            }
+            LimitExceeded::Nodes { count, max } => {
+                write!(f, "{} parse tree nodes exceeds max_nodes {}", count, max)
+            }
+            LimitExceeded::ParseTimeout { max_ms } => {
+                write!(f, "parse took longer than parse_timeout_ms {}", max_ms)
+            }
+        }
+    }
+}
+
+impl Limits {
+    fn check_size(&self, src: &str) -> std::result::Result<(), LimitExceeded> {
+        match self.max_bytes {
+            Some(max) if src.len() > max => Err(LimitExceeded::Bytes {
+                size: src.len(),
+                max,
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Parse `src`, cancelling the parse once it runs past the timeout and
+    /// rejecting a tree with too many nodes.
+    fn parse(&self, src: &str) -> std::result::Result<Option<Tree>, LimitExceeded> {
+        let tree = match self.parse_timeout_ms {
+            None => parse(src),
+            Some(max_ms) => {
+                let deadline = Instant::now() + Duration::from_millis(max_ms);
+                let mut timed_out = false;
+                let mut progress = |_: &ParseState| {
+                    timed_out = Instant::now() >= deadline;
+                    timed_out
+                };
+                let bytes = src.as_bytes();
+                let tree = PARSER.with(|p| {
+                    let mut p = p.borrow_mut();
+                    let tree = p.parse_with_options(
+                        &mut |i, _| &bytes[i.min(bytes.len())..],
+                        None,
+                        Some(ParseOptions::new().progress_callback(&mut progress)),
+                    );
+                    // A cancelled parse would otherwise resume on the next call.
+                    if tree.is_none() {
+                        p.reset();
+                    }
+                    tree
+                });
+                if timed_out {
+                    return Err(LimitExceeded::ParseTimeout { max_ms });
+                }
+                tree
+            }
+        };
+        if let (Some(tree), Some(max)) = (&tree, self.max_nodes) {
+            let count = tree.root_node().descendant_count();
+            if count > max {
+                return Err(LimitExceeded::Nodes { count, max });
+            }
+        }
+        Ok(tree)
+    }
+}
+
+/// Returns `true` if the tree-sitter parse tree for `src`, parsed within
+/// `limits`, contains any ERROR nodes, indicating that the source is not
+/// valid Java.
+fn has_parse_errors(src: &str, limits: &Limits) -> std::result::Result<bool, LimitExceeded> {
+    Ok(limits.parse(src)?.is_none_or(|t| t.root_node().has_error()))
+}
+
+/// A location in a source file: byte offset plus 1-based line and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourcePosition {
+    pub byte: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl SourcePosition {
+    fn of(node: Node) -> Self {
+        let p = node.start_position();
+        SourcePosition {
+            byte: node.start_byte(),
+            line: p.row + 1,
+            column: p.column + 1,
+        }
+    }
+}
+
+/// Position of the first ERROR or MISSING node in `src`'s parse tree, in
+/// source order, or `None` when the source parses cleanly.
+pub fn first_parse_error(src: &str) -> Option<SourcePosition> {
+    located_parse_errors(parse(src), 1).into_iter().next()
+}
+
+/// Positions of every outermost ERROR or MISSING node in the tree of `src`,
+/// parsed within `limits`, in source order.  Empty when the source parses
+/// cleanly.
+pub fn parse_error_locations(
+    src: &str,
+    limits: &Limits,
+) -> std::result::Result<Vec<SourcePosition>, LimitExceeded> {
+    Ok(located_parse_errors(limits.parse(src)?, usize::MAX))
+}
+
+/// Collect up to `limit` ERROR / MISSING node positions of `tree`.  Nodes
+/// nested inside an ERROR node are not reported separately.
+fn located_parse_errors(tree: Option<Tree>, limit: usize) -> Vec<SourcePosition> {
+    let Some(tree) = tree else {
+        return Vec::new();
+    };
+    let root = tree.root_node();
+    if !root.has_error() {
+        return Vec::new();
+    }
+    let mut found: Vec<_> = error_nodes(root, limit)
+        .into_iter()
+        .map(SourcePosition::of)
+        .collect();
+
+    // `has_error` without a locatable node: blame the whole file.
+    if found.is_empty() {
+        found.push(SourcePosition::of(root));
+    }
+    found
+}
+
+/// Up to `limit` outermost ERROR / MISSING nodes below `root`, in source
+/// order.
+fn error_nodes(root: Node, limit: usize) -> Vec<Node> {
+    // Pre-order walk that only descends into subtrees containing an error.
+    let mut found = Vec::new();
+    let mut cursor = root.walk();
+    'walk: loop {
+        let node = cursor.node();
+        if node.is_error() || node.is_missing() {
+            found.push(node);
+            if found.len() >= limit {
+                break;
+            }
+        } else if node.has_error() && cursor.goto_first_child() {
+            continue;
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'walk;
+            }
+        }
+    }
+    found
 }
 
 /// Permanently blank all string/char literals using the same parse-error-aware
@@ -683,17 +847,18 @@ fn has_parse_errors(src: &str) -> bool {
 /// ERROR nodes (indicating corrupt `\\"` sequences splitting string boundaries
 /// early), retries after applying `sanitize_backslashes`. This ensures the
 /// response side of a JSONL pair is blanked with the same strategy as the
-/// prompt, so both always have the same number of `"_"` literals.
-pub fn blank_source(src: &str) -> String {
+/// prompt, so both always have the same number of `"_"` literals.  The check
+/// parses within `limits`.
+pub fn blank_source(src: &str, limits: &Limits) -> std::result::Result<String, LimitExceeded> {
     let blanked = blank_literals_permanently(src);
-    if has_parse_errors(&blanked) {
+    Ok(if has_parse_errors(&blanked, limits)? {
         blank_literals_permanently(&sanitize_backslashes(src))
     } else {
         blanked
-    }
+    })
 }
 
-pub fn obfuscate_str(sanitized_src: &str) -> io::Result<String> {
+pub fn obfuscate_str(sanitized_src: &str) -> Result<String> {
     Ok(obfuscate_str_checked(sanitized_src)?.0)
 }
 
@@ -709,39 +874,424 @@ pub fn obfuscate_str(sanitized_src: &str) -> io::Result<String> {
 ///   required backslash collapsing before tree-sitter could parse it.  The
 ///   resulting pair is still valid, but callers can optionally route it to a
 ///   separate `jsonl_blanked/` sub-directory for tracking / analysis.
-pub fn obfuscate_str_checked(sanitized_src: &str) -> io::Result<(String, bool)> {
+pub fn obfuscate_str_checked(sanitized_src: &str) -> Result<(String, bool)> {
+    obfuscate_str_checked_with(sanitized_src, &Naming::default())
+}
+
+/// [`obfuscate_str_checked`] with custom generated-name prefixes.
+pub fn obfuscate_str_checked_with(sanitized_src: &str, naming: &Naming) -> Result<(String, bool)> {
+    let obfuscation = obfuscate_source(sanitized_src, naming, &Limits::default())?;
+    Ok((obfuscation.source, obfuscation.fallback))
+}
+
+/// The former two-pass obfuscation: an error check, then method and variable
+/// renaming, each with its own parse, then restoring literals by searching
+/// for their placeholders.  Kept only as the baseline [`obfuscate_source`] is
+/// tested and benchmarked against, so it is built for tests and with the
+/// `bench` feature only.  `None` when renaming loses a placeholder.
+#[cfg(any(test, feature = "bench"))]
+#[doc(hidden)]
+pub fn obfuscate_two_pass(sanitized: &str, naming: &Naming) -> Option<(String, bool)> {
+    fn restore(renamed: &str, store: &LiteralStore) -> Option<String> {
+        let mut result = String::new();
+        let mut rest = renamed;
+        for (placeholder, original) in store.iter() {
+            let pos = rest.find(placeholder)?;
+            result.push_str(&rest[..pos]);
+            result.push_str(original);
+            rest = &rest[pos + placeholder.len()..];
+        }
+        Some(result + rest)
+    }
+    let (blanked, store) = blank_literals(sanitized);
+    if !has_parse_errors(&blanked, &Limits::default()).ok()? {
+        let renamed = obfuscate_code(&obfuscate_function_names(&blanked, naming), naming);
+        return Some((restore(&renamed, &store)?, false));
+    }
+    let recovered = blank_literals_permanently(&sanitize_backslashes(sanitized));
+    let renamed = obfuscate_code(&obfuscate_function_names(&recovered, naming), naming);
+    Some((renamed, true))
+}
+
+/// Result of [`obfuscate_source`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Obfuscation {
+    pub source: String,
+    /// The literal-blanker fallback was needed; `source` has repaired or
+    /// blanked literals.
+    pub fallback: bool,
+    /// Fallback only: the prompt and response of the blanked pair, i.e. the
+    /// obfuscated and the repaired source.  Computed from the trees already
+    /// parsed, so building the pair needs no re-parse.
+    pub blanked: Option<(String, String)>,
+    /// Fallback only: what was done to each literal of the repaired source,
+    /// in source order.
+    pub literals: Vec<LiteralRepair>,
+}
+
+/// How the fallback path treated one string or char literal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiteralStrategy {
+    /// Kept with its original content.
+    Restored,
+    /// Kept after collapsing its doubled backslashes.
+    Collapsed,
+    /// Permanently replaced by `"_"` / `'X'`.
+    Blanked,
+}
+
+/// One literal of a fallback source and the strategy used for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LiteralRepair {
+    /// 1-based line of the literal's opening quote.
+    pub line: usize,
+    pub strategy: LiteralStrategy,
+}
+
+/// Obfuscate a sanitized source, parsing it once.
+///
+/// The tree of the blanked source serves as the parse-error check and as the
+/// input of both the method and the variable renaming.  Only a corrupt source
+/// is parsed again, while [`repair_literals`] narrows the damage down.
+pub fn obfuscate_source(
+    sanitized_src: &str,
+    naming: &Naming,
+    limits: &Limits,
+) -> Result<Obfuscation> {
+    let limit_error = |limit| Error::LimitExceeded {
+        label: String::new(),
+        limit,
+    };
+    limits.check_size(sanitized_src).map_err(limit_error)?;
+
     // ── Clean path: reversible blanking ──────────────────────────────────────
     // Use blank_literals (which stores originals) so we can restore the real
-    // string content after identifier renaming.  Only fall back to the permanent
-    // blanker when the source is so corrupt that tree-sitter cannot parse it.
+    // string content after identifier renaming.  Only fall back when the
+    // source is so corrupt that tree-sitter cannot parse it.
     let (blanked, store) = blank_literals(sanitized_src);
 
-    if !has_parse_errors(&blanked) {
-        // Source is clean: rename identifiers, then restore original string values.
-        let func_name_obfuscated = obfuscate_function_names(&blanked);
-        let renamed = obfuscate_code(&func_name_obfuscated);
-        let restored = restore_literals(&renamed, &store);
-        return Ok((restored, false));
+    if let Some(tree) = limits.parse(&blanked).map_err(limit_error)?
+        && !tree.root_node().has_error()
+    {
+        // Source is clean: rename identifiers and restore the original
+        // literals in one pass over the blanked source.
+        let restored = rename_and_restore(tree.root_node(), &blanked, &store, naming)?;
+        return Ok(Obfuscation {
+            source: restored,
+            fallback: false,
+            blanked: None,
+            literals: Vec::new(),
+        });
     }
 
     // ── Fallback path: corrupt source (e.g. `\\"` sequences) ─────────────────
-    // Collapse backslashes so tree-sitter can parse, then permanently blank
-    // (we cannot restore originals reliably after backslash collapsing).
+    // Collapse, then blank, only the literals tree-sitter's errors overlap;
+    // every other literal keeps its real content.  A rename touching a
+    // placeholder in a tree still in error falls through to full blanking.
+    if let Some(repair) = repair_literals(sanitized_src, limits)?
+        && let Ok(obfuscated) = rename_and_restore(
+            repair.tree.root_node(),
+            &repair.blanked,
+            &repair.store,
+            naming,
+        )
+    {
+        return Ok(Obfuscation {
+            source: obfuscated.clone(),
+            fallback: true,
+            blanked: Some((obfuscated, repair.source)),
+            literals: repair.literals,
+        });
+    }
+
+    // Collapse backslashes everywhere and permanently blank every literal (we
+    // cannot restore originals reliably after that).
     let recovered = sanitize_backslashes(sanitized_src);
     let blanked_recovered = blank_literals_permanently(&recovered);
-    let func_name_obfuscated = obfuscate_function_names(&blanked_recovered);
-    Ok((obfuscate_code(&func_name_obfuscated), true))
+    let Some(tree) = limits.parse(&blanked_recovered).map_err(limit_error)? else {
+        return Err(Error::Parse {
+            label: String::new(),
+            position: None,
+        });
+    };
+    let obfuscated = rename_identifiers(tree.root_node(), &blanked_recovered, naming);
+
+    // Both blankers share one scanner, so the permanent blanking of the
+    // sanitized source failed to parse too and `blank_source` of it is
+    // `blanked_recovered`.  Re-blanking the renamed source only changes it
+    // when its tree still has errors.
+    let prompt = if tree.root_node().has_error() {
+        blank_source(&obfuscated, limits).map_err(limit_error)?
+    } else {
+        obfuscated.clone()
+    };
+    let literals = literal_lines(&recovered)
+        .into_iter()
+        .map(|(_, lines)| LiteralRepair {
+            line: lines.start() + 1,
+            strategy: LiteralStrategy::Blanked,
+        })
+        .collect();
+    Ok(Obfuscation {
+        source: obfuscated,
+        fallback: true,
+        blanked: Some((prompt, blanked_recovered)),
+        literals,
+    })
+}
+
+// ── Partial fallback ────────────────────────────────────────────────────────
+
+/// A corrupt source whose broken literals were collapsed or blanked.
+struct RepairedSource {
+    /// The source with its repaired literals.
+    source: String,
+    /// `blank_literals` of `source`, its store and its tree.
+    blanked: String,
+    store: LiteralStore,
+    tree: Tree,
+    /// The literals of `source` that the tree's errors overlap.
+    suspects: Vec<Token>,
+    literals: Vec<LiteralRepair>,
+}
+
+impl RepairedSource {
+    /// Blank and parse `source`; `None` when the parser gives up.
+    fn parse(source: String, limits: &Limits) -> Result<Option<Self>> {
+        let (blanked, store) = blank_literals(&source);
+        let Some(tree) = limits
+            .parse(&blanked)
+            .map_err(|limit| Error::LimitExceeded {
+                label: String::new(),
+                limit,
+            })?
+        else {
+            return Ok(None);
+        };
+        let suspects = if tree.root_node().has_error() {
+            error_literals(tree.root_node(), &blanked, &store, &source)?
+        } else {
+            Vec::new()
+        };
+        Ok(Some(RepairedSource {
+            source,
+            blanked,
+            store,
+            tree,
+            suspects,
+            literals: Vec::new(),
+        }))
+    }
+}
+
+/// Repair the literals of a corrupt source that its parse errors overlap:
+/// collapse their backslashes (`sanitize_backslashes`) while that leaves
+/// fewer literals in error, then blank the literals still in error.  Errors
+/// that overlap no literal are left alone, so the tree of the result can keep
+/// errors that no literal explains.
+///
+/// Lines are stable throughout, as collapsing never removes a newline.
+/// Returns `None` only when the parser gives up.
+fn repair_literals(src: &str, limits: &Limits) -> Result<Option<RepairedSource>> {
+    let Some(mut current) = RepairedSource::parse(src.to_string(), limits)? else {
+        return Ok(None);
+    };
+    let mut collapsed = HashSet::new();
+    while !current.suspects.is_empty() {
+        let (next, changed) = collapse_literals(&current.source, &current.suspects);
+        if changed.is_empty() {
+            break;
+        }
+        let Some(next) = RepairedSource::parse(next, limits)? else {
+            return Ok(None);
+        };
+        if next.suspects.len() >= current.suspects.len() {
+            break;
+        }
+        let line_of = |offset: usize| current.source[..offset].matches('\n').count();
+        collapsed.extend(changed.into_iter().map(line_of));
+        current = next;
+    }
+
+    // Blank what is left.  Placeholders parse like `"_"`, so the tree stays.
+    let literals = literal_repairs(src, &current.source, &collapsed, &current.suspects);
+    if !current.suspects.is_empty() {
+        let blanked = blank_tokens(&current.source, &current.suspects);
+        let Some(next) = RepairedSource::parse(blanked, limits)? else {
+            return Ok(None);
+        };
+        current = next;
+    }
+    current.literals = literals;
+    Ok(Some(current))
+}
+
+/// Every string and char literal of `src` with the 0-based lines it spans.
+fn literal_lines(src: &str) -> Vec<(Token, RangeInclusive<usize>)> {
+    let mut literals = Vec::new();
+    let mut line = 0;
+    for token in Lexer::new(src) {
+        let newlines = token.text(src).matches('\n').count();
+        if token.is_literal() {
+            literals.push((token, line..=line + newlines));
+        }
+        line += newlines;
+    }
+    literals
+}
+
+/// The literals of `source` that the ERROR / MISSING nodes of the tree of its
+/// `blanked` form overlap.  A MISSING node is empty; it counts for a literal
+/// it touches.
+fn error_literals(
+    root: Node,
+    blanked: &str,
+    store: &LiteralStore,
+    source: &str,
+) -> Result<Vec<Token>> {
+    let placements = store.placements(blanked)?;
+    let errors: Vec<Range<usize>> = error_nodes(root, usize::MAX)
+        .into_iter()
+        .map(|node| {
+            source_offset(&placements, node.start_byte(), false)
+                ..source_offset(&placements, node.end_byte(), true)
+        })
+        .collect();
+    Ok(Lexer::new(source)
+        .filter(|token| {
+            token.is_literal()
+                && errors.iter().any(|error| {
+                    if error.is_empty() {
+                        token.start <= error.start && error.start <= token.end
+                    } else {
+                        token.start < error.end && error.start < token.end
+                    }
+                })
+        })
+        .collect())
+}
+
+/// Offset in the source of byte `offset` of its blanked form.  An offset
+/// inside a placeholder maps to the start of its literal, or with `end` to
+/// the end of it.
+fn source_offset(placements: &[(Range<usize>, &str)], offset: usize, end: bool) -> usize {
+    let mut shift = 0isize;
+    for (range, original) in placements {
+        if offset <= range.start {
+            break;
+        }
+        if offset < range.end {
+            let start = range.start.saturating_add_signed(shift);
+            return if end { start + original.len() } else { start };
+        }
+        shift += original.len() as isize - range.len() as isize;
+    }
+    offset.saturating_add_signed(shift)
+}
+
+/// `source` with `sanitize_backslashes` applied to the given literals, and
+/// the offsets of the spans that changed.
+///
+/// A literal that closed early on a `\\"` leaves the rest of its content
+/// outside any literal, where a backslash is never valid Java.  The span
+/// collapsed therefore runs on over every following literal of the line that
+/// such a backslash separates from it.
+fn collapse_literals(source: &str, suspects: &[Token]) -> (String, Vec<usize>) {
+    let literals: Vec<Token> = Lexer::new(source).filter(|t| t.is_literal()).collect();
+    let mut out = String::with_capacity(source.len());
+    let mut changed = Vec::new();
+    let mut last = 0;
+    for suspect in suspects {
+        if suspect.start < last {
+            continue;
+        }
+        let mut end = suspect.end;
+        for next in literals.iter().filter(|t| t.start >= suspect.end) {
+            let gap = &source[end..next.start];
+            if !gap.contains('\\') || gap.contains('\n') {
+                break;
+            }
+            end = next.end;
+        }
+        let collapsed = sanitize_backslashes(&source[suspect.start..end]);
+        if collapsed.len() != end - suspect.start {
+            changed.push(suspect.start);
+        }
+        out.push_str(&source[last..suspect.start]);
+        out.push_str(&collapsed);
+        last = end;
+    }
+    out.push_str(&source[last..]);
+    (out, changed)
+}
+
+/// `source` with the given literals permanently blanked, like
+/// `blank_literals_permanently` does to all of them.
+fn blank_tokens(source: &str, literals: &[Token]) -> String {
+    let mut out = String::with_capacity(source.len());
+    let mut last = 0;
+    for token in literals {
+        out.push_str(&source[last..token.start]);
+        out.push_str(match token.kind {
+            TokenKind::Char => "'X'",
+            _ => "\"_\"",
+        });
+        last = token.end;
+    }
+    out.push_str(&source[last..]);
+    out
+}
+
+/// The [`LiteralRepair`] of every literal of `source`, which is `original`
+/// after collapsing the literals of the `collapsed` lines.  The `blanked`
+/// literals are blanked; a literal on a collapsed line is collapsed unless
+/// the same literal occurs on that line of `original`.
+fn literal_repairs(
+    original: &str,
+    source: &str,
+    collapsed: &HashSet<usize>,
+    blanked: &[Token],
+) -> Vec<LiteralRepair> {
+    let mut before: HashMap<usize, Vec<&str>> = HashMap::new();
+    for (token, lines) in literal_lines(original) {
+        before
+            .entry(*lines.start())
+            .or_default()
+            .push(token.text(original));
+    }
+    literal_lines(source)
+        .into_iter()
+        .map(|(token, lines)| {
+            let line = *lines.start();
+            let strategy = if blanked.contains(&token) {
+                LiteralStrategy::Blanked
+            } else if collapsed.contains(&line)
+                && !before
+                    .get(&line)
+                    .is_some_and(|texts| texts.contains(&token.text(source)))
+            {
+                LiteralStrategy::Collapsed
+            } else {
+                LiteralStrategy::Restored
+            };
+            LiteralRepair {
+                line: line + 1,
+                strategy,
+            }
+        })
+        .collect()
 }
 
 /// File-based wrapper kept for CLI tooling that wants obfuscated `.java` files
 /// on disk (e.g. for inspection or partial re-runs).
-pub fn obfuscate(input_file: &str, output_file: &str) -> io::Result<()> {
-    let raw_code = fs::read_to_string(input_file)?;
+pub fn obfuscate(input_file: &str, output_file: &str) -> Result<()> {
+    let raw_code = read_source(Path::new(input_file), None)?.text;
     // Use sanitize_structural only — no backslash collapsing — so the file on
     // disk is in the same state that generate_jsonl expects to read back.
     let sanitized = sanitize_structural(&raw_code);
-    let result = obfuscate_str(&sanitized)?;
-    fs::write(output_file, result)?;
+    let result = obfuscate_str(&sanitized).map_err(|e| e.with_label(input_file))?;
+    write_atomic(Path::new(output_file), |w| w.write_all(result.as_bytes()))?;
     Ok(())
 }
 
@@ -751,41 +1301,70 @@ mod tests {
     fn test_obfuscate_function_names() {
         let input = "public class Test { public void myFunction() {} }";
         let expected = "public class Test { public void func_1() {} }";
-        let result = super::obfuscate_function_names(input);
+        let result = super::obfuscate_function_names(input, &super::Naming::default());
         assert_eq!(result, expected);
     }
 
     #[test]
     fn test_obfuscate_code() {
         let input = "public class Test { public void myFunction(int param1) { int x = 0; x = x + param1; } }";
-        let func_name_obfuscated = super::obfuscate_function_names(input);
+        let func_name_obfuscated =
+            super::obfuscate_function_names(input, &super::Naming::default());
         let expected = "public class Test { public void func_1(int var_1) { int var_2 = 0; var_2 = var_2 + var_1; } }";
-        let result = super::obfuscate_code(&func_name_obfuscated);
+        let result = super::obfuscate_code(&func_name_obfuscated, &super::Naming::default());
         assert_eq!(result, expected);
     }
 
+    #[test]
+    fn seeded_names_are_consistent_and_seed_dependent() {
+        let input = "public class Test { public void m(int p) { int x = p; x = x + p; } }";
+        let rename = |seed| {
+            let naming = super::Naming {
+                seed,
+                ..super::Naming::default()
+            };
+            super::obfuscate_code(&super::obfuscate_function_names(input, &naming), &naming)
+        };
+        let seeded = rename(Some(7));
+        assert_eq!(seeded, rename(Some(7)));
+        assert_ne!(seeded, rename(Some(8)));
+        assert_ne!(seeded, rename(None));
+        // Renames stay consistent: `p` is used twice, `x` three times.
+        let names: Vec<&str> = seeded
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .filter(|w| w.starts_with("var_"))
+            .collect();
+        assert_eq!(names.len(), 6);
+        assert_eq!(names[0], names[2]);
+        assert_eq!(names[1], names[3]);
+        assert_ne!(names[0], names[1]);
+    }
+
     #[test]
     fn test_preserves_equals() {
         let input =
             r#"public class T { public void m() { Listener listener = new Listener("table"); } }"#;
-        let func_name_obfuscated = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&func_name_obfuscated);
+        let func_name_obfuscated =
+            super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&func_name_obfuscated, &super::Naming::default());
         assert!(result.contains("= new Listener(\"table\")"));
     }
 
     #[test]
     fn test_does_not_rename_method_name() {
         let input = r#"public class T { public void m() { int size = 1; foo.size(); } }"#;
-        let func_name_obfuscated = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&func_name_obfuscated);
+        let func_name_obfuscated =
+            super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&func_name_obfuscated, &super::Naming::default());
         assert!(result.contains(".size()"));
     }
 
     #[test]
     fn test_shadowing_scopes() {
         let input = r#"public class T { public void m() { int x = 1; { int x = 2; x = x + 1; } x = x + 1; } }"#;
-        let func_name_obfuscated = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&func_name_obfuscated);
+        let func_name_obfuscated =
+            super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&func_name_obfuscated, &super::Naming::default());
         assert!(!result.contains(" int x "));
     }
 
@@ -800,8 +1379,9 @@ mod tests {
                 }
             }
         "#;
-        let func_name_obfuscated = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&func_name_obfuscated);
+        let func_name_obfuscated =
+            super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&func_name_obfuscated, &super::Naming::default());
         assert!(result.contains("for (String"));
         assert!(result.contains("catch (Exception"));
     }
@@ -818,8 +1398,9 @@ mod tests {
                 }
             }
         "#;
-        let func_name_obfuscated = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&func_name_obfuscated);
+        let func_name_obfuscated =
+            super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&func_name_obfuscated, &super::Naming::default());
         assert!(
             !result.contains("counter"),
             "field 'counter' should be renamed"
@@ -847,8 +1428,8 @@ mod tests {
             }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains("testFiltreNoeudsSimples"),
@@ -894,8 +1475,8 @@ mod tests {
             }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains(" row"),
@@ -927,8 +1508,8 @@ mod tests {
             }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains(" i ") && !result.contains("(int i"),
@@ -950,8 +1531,8 @@ mod tests {
             }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains(" x "),
@@ -978,8 +1559,8 @@ mod tests {
             }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains(" value"),
@@ -999,8 +1580,8 @@ mod tests {
             }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains(" stream"),
@@ -1022,8 +1603,8 @@ mod tests {
             }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains(" item"),
@@ -1046,8 +1627,8 @@ mod tests {
             }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             result.contains("\"value is not a variable here\""),
@@ -1055,6 +1636,46 @@ mod tests {
         );
     }
 
+    #[test]
+    fn placeholder_lookalikes_in_comments_do_not_shift_literals() {
+        let input = concat!(
+            "public class T {\n",
+            "    // press 'X' to print \"STR_0\"\n",
+            "    public void m() {\n",
+            "        char key = 'q';\n",
+            "        String label = \"quit\";\n",
+            "        System.out.println(label + key);\n",
+            "    }\n",
+            "}\n",
+        );
+        let (result, fallback) = super::obfuscate_str_checked(input).unwrap();
+        assert!(!fallback);
+        assert!(
+            result.contains("// press 'X' to print \"STR_0\"\n"),
+            "{result}"
+        );
+        assert!(result.contains("char var_1 = 'q';"), "{result}");
+        assert!(result.contains("String var_2 = \"quit\";"), "{result}");
+    }
+
+    #[test]
+    fn text_blocks_take_the_clean_path() {
+        let input = concat!(
+            "public class T {\n",
+            "    public void m() {\n",
+            "        String value = \"\"\"\n",
+            "            {\"value\": \"it's\", \"q\": \\\"\"\"}\n",
+            "            \"\"\";\n",
+            "        System.out.println(value);\n",
+            "    }\n",
+            "}\n",
+        );
+        let (result, fallback) = super::obfuscate_str_checked(input).unwrap();
+        assert!(!fallback, "a text block must not need the fallback");
+        assert!(result.contains("{\"value\": \"it's\", \"q\": \\\"\"\"}"));
+        assert!(result.contains("println(var_1)"), "{result}");
+    }
+
     #[test]
     fn test_this_field_access_renamed() {
         let input = r#"
@@ -1065,8 +1686,8 @@ mod tests {
             }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains(" counter ="),
@@ -1086,8 +1707,8 @@ mod tests {
             public void mb() { x = 2; }
         }
     "#;
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains(" x "),
@@ -1115,8 +1736,8 @@ mod tests {
         }
     "#;
 
-        let step1 = super::obfuscate_function_names(input);
-        let result = super::obfuscate_code(&step1);
+        let step1 = super::obfuscate_function_names(input, &super::Naming::default());
+        let result = super::obfuscate_code(&step1, &super::Naming::default());
 
         assert!(
             !result.contains("SearchStringFindsTooManyMatches"),
@@ -1273,67 +1894,9 @@ mod tests {
     }
 
     fn count_string_literals(src: &str) -> usize {
-        fn suspicious(b: u8) -> bool {
-            matches!(
-                b,
-                b'a'..=b'z'
-                    | b'A'..=b'Z'
-                    | b'0'..=b'9'
-                    | b'{'
-                    | b'}'
-                    | b':'
-                    | b','
-                    | b'['
-                    | b']'
-                    | b'_'
-                    | b'$'
-                    | b'\\'
-            )
-        }
-        let bytes = src.as_bytes();
-        let mut count = 0;
-        let mut i = 0;
-        while i < bytes.len() {
-            if bytes[i] == b'"' {
-                count += 1;
-                i += 1;
-                loop {
-                    if i >= bytes.len() {
-                        break;
-                    }
-                    match bytes[i] {
-                        b'\\' => {
-                            let rs = i;
-                            while i < bytes.len() && bytes[i] == b'\\' {
-                                i += 1;
-                            }
-                            let rl = i - rs;
-                            if i < bytes.len() && bytes[i] == b'"' {
-                                let next = bytes.get(i + 1).copied().unwrap_or(b' ');
-                                if rl % 2 == 0 && suspicious(next) {
-                                    i += 1;
-                                } else if rl % 2 == 0 {
-                                    i += 1;
-                                    break;
-                                } else {
-                                    i += 1;
-                                }
-                            }
-                        }
-                        b'"' => {
-                            i += 1;
-                            break;
-                        }
-                        _ => {
-                            i += 1;
-                        }
-                    }
-                }
-            } else {
-                i += 1;
-            }
-        }
-        count
+        crate::lexer::Lexer::new(src)
+            .filter(crate::lexer::Token::is_string)
+            .count()
     }
 
     fn assert_literal_count_preserved(label: &str, src: &str) {
@@ -1464,10 +2027,249 @@ mod tests {
         );
         // Fallback path: the `\\n ` (backslash-n + space) pattern causes the
         // heuristic to close the string early because space is NOT suspicious.
-        // The fallback uses permanent blanking, so dummy "_" literals are expected.
+        // Only that literal is collapsed; the others keep their content.
+        assert!(
+            result.contains(" \"{\\\\n \\\"name\\\" : \\\"test1\\\"\\\\n}\""),
+            "the corrupt literal must be collapsed: {result}"
+        );
+        assert!(result.contains("\"/api/etag/test1\"") && !result.contains("\"_\""));
+    }
+
+    #[test]
+    fn fallback_repairs_only_the_literals_in_error() {
+        use super::{LiteralRepair, LiteralStrategy::*};
+
+        let src = concat!(
+            "public class T {\n",
+            "    void m() {\n",
+            "        String ok = \"keep me\";\n",
+            "        check(ok, \"/x\", \"{\\\\n \\\\\"name\\\\\" : 1}\");\n",
+            "        int x = 1 +;\n",
+            "        call(\"a\" \"b\", x);\n",
+            "    }\n",
+            "}\n",
+        );
+        let obfuscation =
+            super::obfuscate_source(src, &super::Naming::default(), &super::Limits::default())
+                .unwrap();
+        assert!(obfuscation.fallback);
+        let strategies: Vec<_> = obfuscation
+            .literals
+            .iter()
+            .map(|&LiteralRepair { line, strategy }| (line, strategy))
+            .collect();
+        assert_eq!(
+            strategies,
+            [
+                (3, Restored),
+                (4, Restored),
+                (4, Collapsed),
+                (6, Blanked),
+                (6, Restored)
+            ]
+        );
+        let (prompt, response) = obfuscation.blanked.as_ref().unwrap();
+        assert_eq!(prompt, &obfuscation.source);
+        assert!(prompt.contains("String var_1 = \"keep me\";"));
+        assert!(prompt.contains("check(var_1, \"/x\", \"{\\\\n \\\"name\\\" : 1}\");"));
+        assert!(prompt.contains("int var_2 = 1 +;\n        call(\"_\" \"b\", var_2);"));
+        assert!(response.contains("call(\"_\" \"b\", x);"));
+    }
+
+    #[test]
+    fn fallback_leaves_literals_alone_when_no_error_overlaps_them() {
+        let src = "class T { void m() { String s = \"a\\\\b\"; int x = 1 +; } }";
+        let obfuscation =
+            super::obfuscate_source(src, &super::Naming::default(), &super::Limits::default())
+                .unwrap();
+        assert!(obfuscation.fallback);
+        assert!(
+            obfuscation
+                .literals
+                .iter()
+                .all(|l| l.strategy == super::LiteralStrategy::Restored)
+        );
+        assert!(obfuscation.source.contains("\"a\\\\b\""));
+    }
+
+    #[test]
+    fn error_literals_reject_a_stale_blanked_form() {
+        let src = "class T { void m() { String s = \"a\"; int x = 1 +; } }";
+        let (blanked, store) = super::blank_literals(src);
+        let tree = super::parse(&blanked).unwrap();
+        let stale = blanked.replacen("STR_", "XTR_", 1);
+        assert!(matches!(
+            super::error_literals(tree.root_node(), &stale, &store, src),
+            Err(super::Error::LiteralStoreMismatch { .. })
+        ));
+    }
+
+    const SINGLE_PARSE_CASES: &[&str] = &[
+        "public class T { private int n; public void m(int p) { for (int i = 0; i < p; i++) { n += i; } } }",
+        "public class T { void m(java.util.List<String> xs) { xs.forEach(x -> System.out.println(x)); } }",
+        // A method of an anonymous class named like a local in scope.
+        "public class T { void m() { int run = 1; new Runnable() { public void run() { System.out.println(run); } }.run(); } }",
+        "public class T { void m() throws Exception { try (java.io.InputStream in = open()) { in.read(); } catch (Exception e) { throw e; } } }",
+        // TestClass10179: needs the fallback.
+        "public class T {\n@Test public void t() { HttpRequest r = get(\"/x\"); check(r, \"{\\\\n \\\\\"name\\\\\" : 1}\"); }\n}",
+    ];
+
+    #[test]
+    fn single_parse_matches_two_pass_obfuscation() {
+        for seed in [None, Some(11)] {
+            let naming = super::Naming {
+                seed,
+                ..super::Naming::default()
+            };
+            for src in SINGLE_PARSE_CASES {
+                let obfuscation =
+                    super::obfuscate_source(src, &naming, &super::Limits::default()).unwrap();
+                // The fallback keeps literals that two passes blanked.
+                let source = if obfuscation.fallback {
+                    super::blank_source(&obfuscation.source, &super::Limits::default()).unwrap()
+                } else {
+                    obfuscation.source.clone()
+                };
+                assert_eq!(
+                    Some((source, obfuscation.fallback)),
+                    super::obfuscate_two_pass(src, &naming),
+                    "{src}"
+                );
+                if let Some((prompt, response)) = &obfuscation.blanked {
+                    assert_eq!(prompt, &obfuscation.source);
+                    let blank = |s| super::blank_source(s, &super::Limits::default()).unwrap();
+                    assert_eq!(blank(response), blank(src));
+                }
+            }
+        }
+        assert!(SINGLE_PARSE_CASES.iter().any(|src| {
+            super::obfuscate_source(src, &super::Naming::default(), &super::Limits::default())
+                .unwrap()
+                .fallback
+        }));
+    }
+
+    #[test]
+    fn deep_nesting_does_not_overflow_the_stack() {
+        // Thousands of nested `else if` blocks used to recurse once per level.
+        let depth = 3000;
+        let chain: String = (0..depth)
+            .map(|i| format!("if (x == {i}) {{ int v = x; }} else "))
+            .collect();
+        let src = format!("class T {{ void m(int x) {{ {chain} {{ x++; }} }} }}");
+        let obfuscated = std::thread::Builder::new()
+            .stack_size(256 * 1024)
+            .spawn(move || {
+                super::obfuscate_source(&src, &super::Naming::default(), &super::Limits::default())
+            })
+            .unwrap()
+            .join()
+            .expect("obfuscation must not overflow the stack")
+            .unwrap();
+        assert!(!obfuscated.fallback);
+        assert!(obfuscated.source.contains("void func_1(int var_1)"));
+        assert!(!obfuscated.source.contains("int v ="));
+    }
+
+    #[test]
+    fn limits_turn_oversized_sources_into_errors() {
+        use super::{LimitExceeded, Limits};
+        use crate::error::Error;
+
+        let methods: String = (0..2000)
+            .map(|i| format!("void m{i}() {{ int a = {i}; a++; }}\n"))
+            .collect();
+        let src = format!("class T {{\n{methods}}}");
+        let exceeded = |limits: Limits| match super::obfuscate_source(
+            &src,
+            &super::Naming::default(),
+            &limits,
+        ) {
+            Err(Error::LimitExceeded { limit, .. }) => Some(limit),
+            Err(e) => panic!("unexpected error {e}"),
+            Ok(_) => None,
+        };
+
+        assert_eq!(exceeded(Limits::default()), None);
+        assert_eq!(
+            exceeded(Limits {
+                max_bytes: Some(100),
+                ..Limits::default()
+            }),
+            Some(LimitExceeded::Bytes {
+                size: src.len(),
+                max: 100
+            })
+        );
+        assert!(matches!(
+            exceeded(Limits {
+                max_nodes: Some(1000),
+                ..Limits::default()
+            }),
+            Some(LimitExceeded::Nodes { max: 1000, .. })
+        ));
+        assert_eq!(
+            exceeded(Limits {
+                parse_timeout_ms: Some(0),
+                ..Limits::default()
+            }),
+            Some(LimitExceeded::ParseTimeout { max_ms: 0 })
+        );
+        // The cancelled parse does not leak into the next one.
+        let small = "class T { void m() { int a = 1; } }";
+        let generous = Limits {
+            parse_timeout_ms: Some(60_000),
+            ..Limits::default()
+        };
+        assert_eq!(
+            super::obfuscate_source(small, &super::Naming::default(), &generous)
+                .unwrap()
+                .source,
+            "class T { void func_1() { int var_1 = 1; } }"
+        );
+    }
+
+    #[test]
+    fn first_parse_error_reports_line_and_column() {
+        assert_eq!(
+            super::first_parse_error("public class T { void m() { int x = 1; } }"),
+            None
+        );
+
+        let broken = "public class T {\n  void m() { int x = ; }\n}";
+        let pos = super::first_parse_error(broken).expect("must report an error");
+        assert_eq!(pos.line, 2, "error is on the second line: {pos:?}");
+        assert!(pos.byte >= broken.find("void").unwrap(), "{pos:?}");
+    }
+
+    #[test]
+    fn error_locating_and_blanking_parse_within_the_limits() {
+        let limits = super::Limits {
+            max_nodes: Some(5),
+            ..super::Limits::default()
+        };
+        let broken = "class T { void m() { int x = ; String s = \"a\"; } }";
+        assert!(matches!(
+            super::parse_error_locations(broken, &limits),
+            Err(super::LimitExceeded::Nodes { max: 5, .. })
+        ));
+        assert!(super::blank_source(broken, &limits).is_err());
+    }
+
+    #[test]
+    fn parse_error_locations_reports_each_error_in_order() {
         assert!(
-            result.contains("\"_\""),
-            "fallback path must produce dummy '_' literals (corrupt source permanently blanked)"
+            super::parse_error_locations("class T { }", &super::Limits::default())
+                .unwrap()
+                .is_empty()
         );
+
+        let broken = "class T {\n  void a() { int x = ; }\n  void b() { int y = ; }\n}";
+        let lines: Vec<usize> = super::parse_error_locations(broken, &super::Limits::default())
+            .unwrap()
+            .iter()
+            .map(|p| p.line)
+            .collect();
+        assert_eq!(lines, vec![2, 3]);
     }
 }
@@ -0,0 +1,1384 @@
+use std::borrow::Cow;
+use std::cell::Cell;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rayon::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::{Encoding, decode};
+use crate::error::{Error, Result};
+use crate::error_log::{ErrorCategory, ErrorLog, ErrorRecord};
+use crate::helper::{get_files, glob_regex, panic_message, stable_hash, write_atomic};
+use crate::manifest::{
+    Manifest, ManifestEntry, Outcome, Selection, config_fingerprint, content_hash, input_key,
+};
+use crate::obfuscator::{Limits, Naming, SourcePosition, obfuscate_source};
+use crate::processor::{OutputSchema, PromptResponse, pair_of, tokens};
+use crate::quarantine::{QuarantineReason, quarantine, quarantine_dir_of};
+use crate::sanitizer::{
+    Sanitized, SanitizerSteps, sanitize_structural, sanitize_structural_counted,
+};
+use crate::split::{Split, SplitGroup, SplitRatios, assign_split, group_key};
+use crate::summary::RunStats;
+
+/// Reason recorded for sources quarantined because they need the fallback.
+const FALLBACK_DISABLED: &str = "corrupt source, --blanked-subdir not set";
+
+/// Sibling directory that receives pairs written through the literal-blanker
+/// fallback: `<parent>/<name>_blanked`.
+pub fn blanked_subdir_of(jsonl_output_dir: &Path) -> PathBuf {
+    let parent = jsonl_output_dir.parent().unwrap_or(Path::new("."));
+    let dir_name = jsonl_output_dir
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("jsonl");
+    parent.join(format!("{}_blanked", dir_name))
+}
+
+/// Every sanitizer step applied to a source before obfuscation.
+pub fn full_sanitize(raw: &str) -> String {
+    sanitize_structural(raw)
+}
+
+// ── Configuration ───────────────────────────────────────────────────────────
+
+/// Train / val / test assignment settings.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SplitConfig {
+    pub ratios: SplitRatios,
+    pub group: SplitGroup,
+    pub seed: u64,
+}
+
+/// Inputs excluded from the dataset before obfuscation.  Unset limits do not
+/// filter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Filters {
+    /// Largest accepted input, in bytes.
+    pub max_bytes: Option<u64>,
+    /// Fewest structural tokens a sanitized source must have.
+    pub min_tokens: Option<usize>,
+    /// Most structural tokens a sanitized source may have.
+    pub max_tokens: Option<usize>,
+}
+
+impl Filters {
+    /// Why an input of `bytes` bytes is filtered out, if it is.
+    fn reject_size(&self, bytes: usize) -> Option<String> {
+        let max = self.max_bytes?;
+        (bytes as u64 > max).then(|| format!("{} bytes exceeds max_bytes {}", bytes, max))
+    }
+
+    /// Why a sanitized source is filtered out by its token count, if it is.
+    fn reject_tokens(&self, sanitized: &str) -> Option<String> {
+        if self.min_tokens.is_none() && self.max_tokens.is_none() {
+            return None;
+        }
+        let count = tokens(sanitized).count();
+        if let Some(min) = self.min_tokens
+            && count < min
+        {
+            return Some(format!("{} tokens is below min_tokens {}", count, min));
+        }
+        if let Some(max) = self.max_tokens
+            && count > max
+        {
+            return Some(format!("{} tokens exceeds max_tokens {}", count, max));
+        }
+        None
+    }
+}
+
+/// Process only the inputs whose key hashes to `index` modulo `count`, so
+/// `count` machines can convert disjoint parts of one tree.  Built through
+/// [`Shard::new`], which a config file goes through too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "ShardFields")]
+pub struct Shard {
+    index: u64,
+    count: u64,
+}
+
+/// The serialized form of a [`Shard`], checked on the way in.
+#[derive(Deserialize)]
+struct ShardFields {
+    index: u64,
+    count: u64,
+}
+
+impl TryFrom<ShardFields> for Shard {
+    type Error = String;
+
+    fn try_from(fields: ShardFields) -> std::result::Result<Self, String> {
+        Shard::new(fields.index, fields.count)
+    }
+}
+
+impl Shard {
+    pub fn new(index: u64, count: u64) -> std::result::Result<Self, String> {
+        if index >= count {
+            return Err(format!(
+                "shard index {} must be below the shard count {}",
+                index, count
+            ));
+        }
+        Ok(Shard { index, count })
+    }
+
+    pub fn index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Parse `"index/count"`, e.g. `0/4`.
+    pub fn parse(s: &str) -> std::result::Result<Self, String> {
+        let (index, count) = s
+            .split_once('/')
+            .ok_or_else(|| format!("expected INDEX/COUNT, got {:?}", s))?;
+        let parse = |p: &str| {
+            p.trim()
+                .parse::<u64>()
+                .map_err(|e| format!("invalid shard number {:?}: {}", p.trim(), e))
+        };
+        Shard::new(parse(index)?, parse(count)?)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        stable_hash(key.as_bytes()) % self.count == self.index
+    }
+}
+
+/// Settings of a [`Pipeline`], built with chained setters:
+///
+/// ```no_run
+/// # use java_dataset_converter_llm::pipeline::{Pipeline, PipelineConfig, FsSink, Source};
+/// let config = PipelineConfig::new()
+///     .blanked_fallback(true)
+///     .manifest("out/manifest.jsonl");
+/// let pipeline = Pipeline::new(config, FsSink::new("out")).unwrap();
+/// let reports = pipeline.run(Source::Dir("in".into())).unwrap();
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct PipelineConfig {
+    /// Write pairs that need the literal-blanker fallback (to the blanked
+    /// destination) instead of quarantining their source.
+    pub blanked_fallback: bool,
+    pub split: Option<SplitConfig>,
+    pub selection: Selection,
+    /// Run manifest used to skip up-to-date inputs; none disables resuming.
+    pub manifest: Option<PathBuf>,
+    /// Structured error log; none disables error logging.
+    pub error_log: Option<PathBuf>,
+    /// Globs over input keys; an empty list includes every input.
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub shard: Option<Shard>,
+    pub filters: Filters,
+    /// Per-file resource limits; a violation fails only that file.
+    pub limits: Limits,
+    pub naming: Naming,
+    pub sanitizer: SanitizerSteps,
+    pub schema: OutputSchema,
+    /// Stop the run at the first failed file instead of logging it and
+    /// carrying on.
+    pub strict: bool,
+    /// Encoding of inputs without a byte order mark; none detects it.
+    pub encoding: Option<Encoding>,
+}
+
+/// Settings that change what is written for a given input.  Any change here
+/// alters the manifest fingerprint and forces affected files to be redone.
+#[derive(Serialize)]
+struct OutputSettings<'a> {
+    blanked_subdir: bool,
+    split: Option<(SplitRatios, SplitGroup, u64)>,
+    filters: Filters,
+    naming: &'a Naming,
+    sanitizer: SanitizerSteps,
+    schema: &'a OutputSchema,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encoding: Option<Encoding>,
+}
+
+impl PipelineConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn blanked_fallback(mut self, enabled: bool) -> Self {
+        self.blanked_fallback = enabled;
+        self
+    }
+
+    pub fn split(mut self, split: Option<SplitConfig>) -> Self {
+        self.split = split;
+        self
+    }
+
+    pub fn selection(mut self, selection: Selection) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    pub fn manifest(mut self, path: impl Into<PathBuf>) -> Self {
+        self.manifest = Some(path.into());
+        self
+    }
+
+    pub fn error_log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.error_log = Some(path.into());
+        self
+    }
+
+    pub fn include(mut self, globs: Vec<String>) -> Self {
+        self.include = globs;
+        self
+    }
+
+    pub fn exclude(mut self, globs: Vec<String>) -> Self {
+        self.exclude = globs;
+        self
+    }
+
+    pub fn shard(mut self, shard: Option<Shard>) -> Self {
+        self.shard = shard;
+        self
+    }
+
+    pub fn filters(mut self, filters: Filters) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn strict(mut self, enabled: bool) -> Self {
+        self.strict = enabled;
+        self
+    }
+
+    pub fn limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    pub fn naming(mut self, naming: Naming) -> Self {
+        self.naming = naming;
+        self
+    }
+
+    pub fn sanitizer(mut self, steps: SanitizerSteps) -> Self {
+        self.sanitizer = steps;
+        self
+    }
+
+    pub fn schema(mut self, schema: OutputSchema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    pub fn encoding(mut self, encoding: Option<Encoding>) -> Self {
+        self.encoding = encoding;
+        self
+    }
+
+    /// Fingerprint of the output-affecting settings, stored in the manifest.
+    pub fn fingerprint(&self) -> String {
+        config_fingerprint(&OutputSettings {
+            blanked_subdir: self.blanked_fallback,
+            split: self.split.map(|s| (s.ratios, s.group, s.seed)),
+            filters: self.filters,
+            naming: &self.naming,
+            sanitizer: self.sanitizer,
+            schema: &self.schema,
+            encoding: self.encoding,
+        })
+    }
+}
+
+// ── Sources ─────────────────────────────────────────────────────────────────
+
+/// Where a pipeline run reads its inputs from.
+#[derive(Debug, Clone)]
+pub enum Source {
+    /// Every `.java` file under a directory, recursively.
+    Dir(PathBuf),
+    /// An explicit list of files, keyed relative to `root`.
+    Files { root: PathBuf, files: Vec<PathBuf> },
+    /// In-memory sources keyed by a relative path such as `a/T.java`.
+    Memory(Vec<(String, Vec<u8>)>),
+}
+
+/// One input of a run.
+#[derive(Debug, Clone)]
+pub struct Input {
+    /// Path relative to the input root, `/`-separated.
+    pub key: String,
+    /// Path on disk, or the key for in-memory inputs.
+    pub path: PathBuf,
+    contents: Option<Vec<u8>>,
+}
+
+impl Input {
+    pub fn file(root: &Path, path: PathBuf) -> Self {
+        Input {
+            key: input_key(root, &path),
+            path,
+            contents: None,
+        }
+    }
+
+    pub fn memory(key: impl Into<String>, contents: impl Into<Vec<u8>>) -> Self {
+        let key = key.into();
+        Input {
+            path: PathBuf::from(&key),
+            key,
+            contents: Some(contents.into()),
+        }
+    }
+
+    fn read(&self) -> io::Result<Cow<'_, [u8]>> {
+        match &self.contents {
+            Some(bytes) => Ok(Cow::Borrowed(bytes)),
+            None => fs::read(&self.path).map(Cow::Owned),
+        }
+    }
+}
+
+impl Source {
+    /// List the inputs, in a stable order for directory sources.
+    pub fn inputs(self) -> io::Result<Vec<Input>> {
+        match self {
+            Source::Dir(root) => {
+                let files = get_files(&root.to_string_lossy(), "java")?;
+                Ok(files.into_iter().map(|f| Input::file(&root, f)).collect())
+            }
+            Source::Files { root, files } => {
+                Ok(files.into_iter().map(|f| Input::file(&root, f)).collect())
+            }
+            Source::Memory(sources) => Ok(sources
+                .into_iter()
+                .map(|(key, contents)| Input::memory(key, contents))
+                .collect()),
+        }
+    }
+}
+
+// ── Sinks ───────────────────────────────────────────────────────────────────
+
+/// Where a pair is routed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PairTarget<'a> {
+    pub key: &'a str,
+    pub split: Option<Split>,
+    /// Written through the literal-blanker fallback.
+    pub blanked: bool,
+}
+
+/// Destination of pipeline results.
+pub trait Sink: Sync {
+    /// Store `pair`, serialized under `schema` if the sink writes JSONL;
+    /// returns the file written, if the sink writes files.
+    fn write_pair(
+        &self,
+        target: PairTarget,
+        pair: &PromptResponse,
+        schema: &OutputSchema,
+    ) -> io::Result<Option<PathBuf>>;
+
+    /// Set aside a source that could not be converted.
+    fn quarantine(
+        &self,
+        key: &str,
+        original: &[u8],
+        reason: &QuarantineReason,
+    ) -> io::Result<Option<PathBuf>>;
+}
+
+/// Writes one `<key>.jsonl` per pair under `root`, mirroring the input tree,
+/// in a `train` / `val` / `test` sub-directory when splitting and in the
+/// `_blanked` sibling for fallback pairs.  Quarantined sources go to
+/// `<root>_quarantine`.
+#[derive(Debug, Clone)]
+pub struct FsSink {
+    root: PathBuf,
+    quarantine_dir: PathBuf,
+}
+
+impl FsSink {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        FsSink {
+            quarantine_dir: quarantine_dir_of(&root),
+            root,
+        }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Directory a pair for `target` is written to.
+    pub fn dir_of(&self, target: PairTarget) -> PathBuf {
+        let dir = match target.split {
+            Some(split) => self.root.join(split.as_str()),
+            None => self.root.clone(),
+        };
+        if target.blanked {
+            blanked_subdir_of(&dir)
+        } else {
+            dir
+        }
+    }
+}
+
+impl Sink for FsSink {
+    fn write_pair(
+        &self,
+        target: PairTarget,
+        pair: &PromptResponse,
+        schema: &OutputSchema,
+    ) -> io::Result<Option<PathBuf>> {
+        // The whole key, not just the file name: `a/T.java` and `b/T.java`
+        // must not overwrite each other.
+        let path = self.dir_of(target).join(format!("{}.jsonl", target.key));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let line = schema.to_line(pair);
+        write_atomic(&path, |w| writeln!(w, "{}", line))?;
+        Ok(Some(path))
+    }
+
+    fn quarantine(
+        &self,
+        key: &str,
+        original: &[u8],
+        reason: &QuarantineReason,
+    ) -> io::Result<Option<PathBuf>> {
+        quarantine(&self.quarantine_dir, key, original, reason).map(Some)
+    }
+}
+
+/// A pair collected by [`MemorySink`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryPair {
+    pub key: String,
+    pub split: Option<Split>,
+    pub blanked: bool,
+    pub pair: PromptResponse,
+}
+
+/// Collects results in memory, for callers that post-process pairs
+/// themselves.
+#[derive(Debug, Default)]
+pub struct MemorySink {
+    pairs: Mutex<Vec<MemoryPair>>,
+    quarantined: Mutex<Vec<(String, QuarantineReason)>>,
+}
+
+impl MemorySink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Collected pairs, sorted by key.
+    pub fn pairs(&self) -> Vec<MemoryPair> {
+        let mut pairs = self.pairs.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        pairs.sort_by(|a, b| a.key.cmp(&b.key));
+        pairs
+    }
+
+    /// Quarantined sources with their reasons, sorted by key.
+    pub fn quarantined(&self) -> Vec<(String, QuarantineReason)> {
+        let mut q = self
+            .quarantined
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        q.sort_by(|a, b| a.0.cmp(&b.0));
+        q
+    }
+}
+
+impl Sink for MemorySink {
+    fn write_pair(
+        &self,
+        target: PairTarget,
+        pair: &PromptResponse,
+        _schema: &OutputSchema,
+    ) -> io::Result<Option<PathBuf>> {
+        self.pairs
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(MemoryPair {
+                key: target.key.to_string(),
+                split: target.split,
+                blanked: target.blanked,
+                pair: pair.clone(),
+            });
+        Ok(None)
+    }
+
+    fn quarantine(
+        &self,
+        key: &str,
+        _original: &[u8],
+        reason: &QuarantineReason,
+    ) -> io::Result<Option<PathBuf>> {
+        self.quarantined
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push((key.to_string(), reason.clone()));
+        Ok(None)
+    }
+}
+
+// ── Outcomes ────────────────────────────────────────────────────────────────
+
+/// Pipeline stage a file failed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Read,
+    Sanitize,
+    Obfuscate,
+    Write,
+    WriteBlanked,
+    Quarantine,
+}
+
+impl Stage {
+    /// Name used in the manifest, the error log and the summary.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Stage::Read => "read",
+            Stage::Sanitize => "sanitize",
+            Stage::Obfuscate => "obfuscate",
+            Stage::Write => "generate_jsonl",
+            Stage::WriteBlanked => "generate_jsonl_blanked",
+            Stage::Quarantine => "quarantine",
+        }
+    }
+}
+
+/// What happened to one input.  `output` is the file written, when the sink
+/// writes files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FileOutcome {
+    /// Parsed cleanly; the pair keeps real literal content.
+    Clean { output: Option<PathBuf> },
+    /// Needed the literal-blanker fallback and was written blanked.
+    Fallback { output: Option<PathBuf> },
+    /// Needed the fallback, which is disabled; the source was quarantined.
+    Quarantined { output: Option<PathBuf> },
+    /// Excluded by [`Filters`]; nothing was written.
+    Filtered { reason: String },
+    /// Skipped because the manifest says it is up to date.
+    UpToDate,
+    Failed {
+        stage: Stage,
+        category: ErrorCategory,
+        message: String,
+    },
+}
+
+impl FileOutcome {
+    /// Manifest outcome and output, or `None` when nothing is recorded.
+    fn to_manifest(&self) -> Option<(Outcome, Option<PathBuf>)> {
+        match self {
+            FileOutcome::Clean { output } => Some((Outcome::Clean, output.clone())),
+            FileOutcome::Fallback { output } => Some((Outcome::Fallback, output.clone())),
+            FileOutcome::Quarantined { output } => Some((Outcome::Skipped, output.clone())),
+            FileOutcome::Filtered { reason } => Some((
+                Outcome::Filtered {
+                    reason: reason.clone(),
+                },
+                None,
+            )),
+            FileOutcome::UpToDate => None,
+            FileOutcome::Failed { stage, .. } => Some((
+                Outcome::Failed {
+                    stage: stage.as_str().to_string(),
+                },
+                None,
+            )),
+        }
+    }
+}
+
+/// Outcome of one input of a run.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileReport {
+    pub key: String,
+    pub path: PathBuf,
+    pub outcome: FileOutcome,
+}
+
+// ── Pipeline ────────────────────────────────────────────────────────────────
+
+type ProgressFn = Box<dyn Fn(&FileReport) + Send + Sync>;
+
+/// Sanitize → obfuscate → route → write, with resume, error logging and
+/// blanked routing, over any [`Source`] into any [`Sink`].
+pub struct Pipeline<S: Sink> {
+    config: PipelineConfig,
+    fingerprint: String,
+    include: Vec<Regex>,
+    exclude: Vec<Regex>,
+    sink: S,
+    manifest: Option<Mutex<Manifest>>,
+    error_log: Option<ErrorLog>,
+    stats: RunStats,
+    progress: Option<ProgressFn>,
+}
+
+impl<S: Sink> Pipeline<S> {
+    /// Open the manifest and error log named in `config`.
+    pub fn new(config: PipelineConfig, sink: S) -> Result<Self> {
+        let manifest = match &config.manifest {
+            Some(path) => Some(Mutex::new(Manifest::open(path)?)),
+            None => None,
+        };
+        let error_log = match &config.error_log {
+            Some(path) => Some(ErrorLog::open(path)?),
+            None => None,
+        };
+        let globs = |patterns: &[String]| patterns.iter().map(|p| glob_regex(p)).collect();
+        Ok(Pipeline {
+            fingerprint: config.fingerprint(),
+            include: globs(&config.include),
+            exclude: globs(&config.exclude),
+            config,
+            sink,
+            manifest,
+            error_log,
+            stats: RunStats::default(),
+            progress: None,
+        })
+    }
+
+    /// Call `f` after each input, from the worker thread that processed it.
+    pub fn on_progress(mut self, f: impl Fn(&FileReport) + Send + Sync + 'static) -> Self {
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    pub fn stats(&self) -> &RunStats {
+        &self.stats
+    }
+
+    /// Process every selected input of `source` in parallel.  Reports are
+    /// returned in input order.
+    pub fn run(&self, source: Source) -> Result<Vec<FileReport>> {
+        let inputs = self.discover(source)?;
+        self.run_inputs(&inputs)
+    }
+
+    /// List the inputs of `source` that pass the include / exclude globs and
+    /// belong to the configured shard.
+    pub fn discover(&self, source: Source) -> Result<Vec<Input>> {
+        let mut inputs = self.stats.time("discover", || source.inputs())?;
+        inputs.retain(|input| self.selects(&input.key));
+        Ok(inputs)
+    }
+
+    fn selects(&self, key: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|g| g.is_match(key)))
+            && !self.exclude.iter().any(|g| g.is_match(key))
+            && self.config.shard.is_none_or(|s| s.contains(key))
+    }
+
+    /// Process already listed inputs in parallel, in input order.
+    ///
+    /// In strict mode the first failed file ends the run with
+    /// [`Error::Aborted`]; inputs not yet started are left unprocessed.
+    pub fn run_inputs(&self, inputs: &[Input]) -> Result<Vec<FileReport>> {
+        inputs
+            .par_iter()
+            .map(|input| {
+                let report = self.process(input);
+                match report.outcome {
+                    FileOutcome::Failed {
+                        stage,
+                        category,
+                        ref message,
+                    } if self.config.strict => Err(Error::Aborted {
+                        label: report.key,
+                        stage: stage.as_str(),
+                        category,
+                        message: message.clone(),
+                    }),
+                    _ => Ok(report),
+                }
+            })
+            .collect()
+    }
+
+    /// Process one input, recording it in the manifest and the statistics.
+    pub fn process(&self, input: &Input) -> FileReport {
+        self.stats.file_seen();
+        let outcome = self.convert(input);
+        let report = FileReport {
+            key: input.key.clone(),
+            path: input.path.clone(),
+            outcome,
+        };
+        if let Some(progress) = &self.progress {
+            progress(&report);
+        }
+        report
+    }
+
+    /// Compact the manifest and hand back the sink and the run statistics.
+    pub fn finish(self) -> Result<(S, RunStats)> {
+        if let (Some(manifest), Some(path)) = (self.manifest, &self.config.manifest) {
+            manifest
+                .into_inner()
+                .unwrap_or_else(|e| e.into_inner())
+                .compact(path)?;
+        }
+        Ok((self.sink, self.stats))
+    }
+
+    fn convert(&self, input: &Input) -> FileOutcome {
+        let stats = &self.stats;
+        let bytes = match stats.time("read", || input.read()) {
+            Ok(b) => b,
+            Err(e) => {
+                // Unreadable inputs have no hash to key a manifest entry on.
+                stats.error(Stage::Read.as_str());
+                return self.fail(input, None, Stage::Read, ErrorCategory::Read, &e);
+            }
+        };
+        let hash = content_hash(&bytes);
+
+        if let Some(manifest) = &self.manifest {
+            let needs_processing = manifest
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .needs_processing(&input.key, &hash, &self.fingerprint, self.config.selection);
+            if !needs_processing {
+                stats.already_processed();
+                return FileOutcome::UpToDate;
+            }
+        }
+
+        let (outcome, encoding) = match decode(&bytes, self.config.encoding) {
+            Ok(decoded) => {
+                stats.encoding(decoded.encoding);
+                let outcome = match self.config.filters.reject_size(bytes.len()) {
+                    Some(reason) => FileOutcome::Filtered { reason },
+                    None => self.convert_guarded(input, &bytes, &decoded.text, &hash),
+                };
+                (outcome, Some(decoded.encoding))
+            }
+            Err(e) => (
+                self.fail(input, Some(&hash), Stage::Read, ErrorCategory::Utf8, &e),
+                None,
+            ),
+        };
+        self.record(input, hash, &outcome, encoding);
+        outcome
+    }
+
+    /// [`Self::convert_source`], turning a panic into a failure of the stage
+    /// it happened in so one bad file cannot take down the run.
+    fn convert_guarded(&self, input: &Input, bytes: &[u8], raw: &str, hash: &str) -> FileOutcome {
+        let stage = Cell::new(Stage::Sanitize);
+        let converted = panic::catch_unwind(AssertUnwindSafe(|| {
+            self.convert_source(input, bytes, raw, hash, &stage)
+        }));
+        converted.unwrap_or_else(|payload| {
+            let message = format!("panicked: {}", panic_message(payload.as_ref()));
+            self.fail(
+                input,
+                Some(hash),
+                stage.get(),
+                ErrorCategory::Panic,
+                &message,
+            )
+        })
+    }
+
+    /// Sanitize, obfuscate and route one decoded source.  `stage` tracks the
+    /// stage in progress for [`Self::convert_guarded`].
+    fn convert_source(
+        &self,
+        input: &Input,
+        bytes: &[u8],
+        raw: &str,
+        hash: &str,
+        stage: &Cell<Stage>,
+    ) -> FileOutcome {
+        let stats = &self.stats;
+        let Sanitized {
+            text: sanitized,
+            mojibake_repairs,
+        } = stats.time("sanitize", || {
+            sanitize_structural_counted(raw, &self.config.sanitizer)
+        });
+        stats.mojibake_repairs(mojibake_repairs);
+        if let Some(reason) = self.config.filters.reject_tokens(&sanitized) {
+            return FileOutcome::Filtered { reason };
+        }
+
+        stage.set(Stage::Obfuscate);
+        let checked = stats.time("obfuscate", || {
+            obfuscate_source(&sanitized, &self.config.naming, &self.config.limits)
+        });
+        let obfuscation = match checked {
+            Ok(obfuscation) => obfuscation,
+            Err(e) => return self.fail_on(input, Some(hash), Stage::Obfuscate, &e),
+        };
+
+        let needed_fallback = obfuscation.fallback;
+        let split = self.config.split.map(|s| {
+            let key = group_key(Path::new(""), Path::new(&input.key), Some(raw), s.group);
+            assign_split(&key, &s.ratios, s.seed)
+        });
+
+        // ── Route & write ───────────────────────────────────────────────────
+        if needed_fallback && !self.config.blanked_fallback {
+            // Corrupt source + fallback disabled: quarantine it, so it is only
+            // retried when the input or the settings change.
+            stage.set(Stage::Quarantine);
+            let reason = match QuarantineReason::new(
+                &input.path,
+                hash,
+                FALLBACK_DISABLED,
+                &sanitized,
+                &self.config.limits,
+            ) {
+                Ok(reason) => reason,
+                Err(limit) => {
+                    let e = Error::LimitExceeded {
+                        label: String::new(),
+                        limit,
+                    };
+                    return self.fail_on(input, Some(hash), Stage::Quarantine, &e);
+                }
+            };
+            self.append_error(ErrorRecord {
+                file: input.path.clone(),
+                input: Some(input.key.clone()),
+                stage: "parse".to_string(),
+                category: ErrorCategory::Parse,
+                message: FALLBACK_DISABLED.to_string(),
+                file_hash: Some(hash.to_string()),
+                parse_error: reason.parse_errors.first().copied(),
+            });
+            return match self.sink.quarantine(&input.key, bytes, &reason) {
+                Ok(output) => FileOutcome::Quarantined { output },
+                Err(e) => self.fail(
+                    input,
+                    Some(hash),
+                    Stage::Quarantine,
+                    ErrorCategory::Write,
+                    &e,
+                ),
+            };
+        }
+
+        stage.set(if needed_fallback {
+            Stage::WriteBlanked
+        } else {
+            Stage::Write
+        });
+        let target = PairTarget {
+            key: &input.key,
+            split,
+            blanked: needed_fallback,
+        };
+        let written = stats.time("write", || {
+            let pair = pair_of(&sanitized, &obfuscation, &input.key)?;
+            Ok::<_, Error>(self.sink.write_pair(target, &pair, &self.config.schema)?)
+        });
+        match written {
+            Ok(output) => {
+                stats.pair(&sanitized, &obfuscation.source);
+                if needed_fallback {
+                    FileOutcome::Fallback { output }
+                } else {
+                    FileOutcome::Clean { output }
+                }
+            }
+            Err(e) => self.fail_on(input, Some(hash), stage.get(), &e),
+        }
+    }
+
+    fn record(
+        &self,
+        input: &Input,
+        hash: String,
+        outcome: &FileOutcome,
+        encoding: Option<Encoding>,
+    ) {
+        let Some((outcome, output)) = outcome.to_manifest() else {
+            return;
+        };
+        self.stats.outcome(&outcome);
+        let Some(manifest) = &self.manifest else {
+            return;
+        };
+        let mut manifest = manifest.lock().unwrap_or_else(|e| e.into_inner());
+        manifest.remove_stale_output(&input.key, output.as_deref());
+        let entry = ManifestEntry {
+            input: input.key.clone(),
+            content_hash: hash,
+            config_fingerprint: self.fingerprint.clone(),
+            outcome,
+            output,
+            encoding,
+        };
+        // A lost manifest line only means the input is redone next run.
+        let _ = manifest.record(entry);
+    }
+
+    /// Log a failure of `input` and return it as its outcome.
+    fn fail(
+        &self,
+        input: &Input,
+        file_hash: Option<&str>,
+        stage: Stage,
+        category: ErrorCategory,
+        err: &dyn std::fmt::Display,
+    ) -> FileOutcome {
+        self.fail_at(input, file_hash, stage, category, err, None)
+    }
+
+    /// [`Self::fail`] with the category of `err` and, for a parse failure,
+    /// its position.
+    fn fail_on(
+        &self,
+        input: &Input,
+        file_hash: Option<&str>,
+        stage: Stage,
+        err: &Error,
+    ) -> FileOutcome {
+        self.fail_at(input, file_hash, stage, err.category(), err, err.position())
+    }
+
+    fn fail_at(
+        &self,
+        input: &Input,
+        file_hash: Option<&str>,
+        stage: Stage,
+        category: ErrorCategory,
+        err: &dyn std::fmt::Display,
+        parse_error: Option<SourcePosition>,
+    ) -> FileOutcome {
+        let message = err.to_string();
+        self.append_error(ErrorRecord {
+            file: input.path.clone(),
+            input: Some(input.key.clone()),
+            stage: stage.as_str().to_string(),
+            category,
+            message: message.clone(),
+            file_hash: file_hash.map(str::to_string),
+            parse_error,
+        });
+        FileOutcome::Failed {
+            stage,
+            category,
+            message,
+        }
+    }
+
+    fn append_error(&self, record: ErrorRecord) {
+        if let Some(error_log) = &self.error_log {
+            // The outcome is still reported; only the log line is lost.
+            let _ = error_log.append(&record);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const CLEAN: &str = "public class T {\n@Test public void testFoo() { int count = 1; assertEquals(1, count); }\n}";
+
+    // TestClass10179 pattern: needs the literal-blanker fallback.
+    const CORRUPT: &str = concat!(
+        "public class C {\n",
+        "@Test public void testCorrupt() throws Exception {",
+        " assertResponse(var_req, 200, \"{\\\\n \\\\\"name\\\\\" : \\\\\"val\\\\\"\\\\n}\");",
+        " }\n}",
+    );
+
+    fn memory_source() -> Source {
+        Source::Memory(vec![
+            ("a/T.java".to_string(), CLEAN.as_bytes().to_vec()),
+            ("b/C.java".to_string(), CORRUPT.as_bytes().to_vec()),
+            ("b/Bin.java".to_string(), vec![0xff, 0xfe, 0x41]),
+        ])
+    }
+
+    #[test]
+    fn full_sanitize_does_not_alter_valid_backslash_pairs() {
+        let raw = r#"assertThat(result).isEqualTo("\\\\");"#;
+        let result = full_sanitize(raw);
+        assert_eq!(
+            result, raw,
+            "full_sanitize must not alter a valid \\\\\\\\ string literal"
+        );
+    }
+
+    #[test]
+    fn full_sanitize_fixes_escaped_apostrophe() {
+        let raw = "Arrays.fill(buf, (byte) \\'Q\\');";
+        let result = full_sanitize(raw);
+        assert!(
+            result.contains("'Q'"),
+            "full_sanitize must convert \\' to '"
+        );
+        assert!(
+            !result.contains("\\'"),
+            "full_sanitize must remove all \\' sequences"
+        );
+    }
+
+    // ── blanked_subdir_of helper ─────────────────────────────────────────────
+
+    #[test]
+    fn blanked_subdir_of_appends_blanked_suffix() {
+        let dir = Path::new("train/jsonl");
+        let blanked = blanked_subdir_of(dir);
+        assert_eq!(
+            blanked,
+            PathBuf::from("train/jsonl_blanked"),
+            "sibling directory must be '<parent>/<name>_blanked'"
+        );
+    }
+
+    #[test]
+    fn blanked_subdir_of_nested_path() {
+        let dir = Path::new("data/splits/test/jsonl");
+        let blanked = blanked_subdir_of(dir);
+        assert_eq!(blanked, PathBuf::from("data/splits/test/jsonl_blanked"));
+    }
+
+    #[test]
+    fn blanked_subdir_of_top_level_name() {
+        // When the input path has no parent component (e.g. "jsonl"), the
+        // platform may return "" or "." for the parent, yielding either
+        // "jsonl_blanked" or "./jsonl_blanked".  Both are equivalent paths;
+        // assert on the file_name component only.
+        let dir = Path::new("jsonl");
+        let blanked = blanked_subdir_of(dir);
+        assert_eq!(
+            blanked.file_name().and_then(|n| n.to_str()),
+            Some("jsonl_blanked"),
+            "basename of the blanked sibling must be 'jsonl_blanked'"
+        );
+    }
+
+    // ── pipeline ─────────────────────────────────────────────────────────────
+
+    #[test]
+    fn memory_run_reports_typed_outcomes() {
+        let pipeline = Pipeline::new(PipelineConfig::new(), MemorySink::new()).unwrap();
+        let reports = pipeline.run(memory_source()).unwrap();
+
+        assert_eq!(reports[0].outcome, FileOutcome::Clean { output: None });
+        assert_eq!(
+            reports[1].outcome,
+            FileOutcome::Quarantined { output: None }
+        );
+        assert!(matches!(
+            reports[2].outcome,
+            FileOutcome::Failed {
+                stage: Stage::Read,
+                ..
+            }
+        ));
+
+        let pairs = pipeline.sink().pairs();
+        assert_eq!(pairs.len(), 1);
+        assert!(!pairs[0].pair.prompt.contains("testFoo"));
+        assert_eq!(pairs[0].pair.response, CLEAN);
+        let quarantined = pipeline.sink().quarantined();
+        assert_eq!(quarantined[0].0, "b/C.java");
+        assert!(!quarantined[0].1.parse_errors.is_empty());
+    }
+
+    /// Panics while writing the pair of `a/T.java`.
+    struct PanickingSink(MemorySink);
+
+    impl Sink for PanickingSink {
+        fn write_pair(
+            &self,
+            target: PairTarget,
+            pair: &PromptResponse,
+            schema: &OutputSchema,
+        ) -> io::Result<Option<PathBuf>> {
+            assert_ne!(target.key, "a/T.java", "sink exploded");
+            self.0.write_pair(target, pair, schema)
+        }
+
+        fn quarantine(
+            &self,
+            key: &str,
+            original: &[u8],
+            reason: &QuarantineReason,
+        ) -> io::Result<Option<PathBuf>> {
+            self.0.quarantine(key, original, reason)
+        }
+    }
+
+    #[test]
+    fn panics_fail_only_their_file() {
+        let dir = TempDir::new().unwrap();
+        let log = dir.path().join("errors.jsonl");
+        let config = PipelineConfig::new().error_log(&log);
+        let pipeline = Pipeline::new(config, PanickingSink(MemorySink::new())).unwrap();
+        let reports = pipeline.run(memory_source()).unwrap();
+
+        let FileOutcome::Failed {
+            stage,
+            category,
+            message,
+        } = &reports[0].outcome
+        else {
+            panic!("expected a failure, got {:?}", reports[0].outcome);
+        };
+        assert_eq!(*stage, Stage::Write);
+        assert_eq!(*category, ErrorCategory::Panic);
+        assert!(message.contains("sink exploded"), "{message}");
+        assert_eq!(
+            reports[1].outcome,
+            FileOutcome::Quarantined { output: None }
+        );
+
+        let records = crate::error_log::read_error_log(&log).unwrap();
+        let panicked: Vec<_> = records
+            .iter()
+            .filter(|r| r.category == ErrorCategory::Panic)
+            .collect();
+        assert_eq!(panicked.len(), 1);
+        assert_eq!(panicked[0].stage, "generate_jsonl");
+        assert!(panicked[0].file_hash.is_some());
+    }
+
+    #[test]
+    fn strict_mode_aborts_on_the_first_failure() {
+        let config = PipelineConfig::new().strict(true);
+        let pipeline = Pipeline::new(config, MemorySink::new()).unwrap();
+        let err = pipeline.run(memory_source()).unwrap_err();
+        assert!(matches!(
+            err,
+            Error::Aborted {
+                stage: "read",
+                category: ErrorCategory::Utf8,
+                ..
+            }
+        ));
+        assert!(err.to_string().contains("b/Bin.java"), "{err}");
+
+        // Quarantining is not a failure.
+        let clean = Source::Memory(vec![
+            ("a/T.java".to_string(), CLEAN.as_bytes().to_vec()),
+            ("b/C.java".to_string(), CORRUPT.as_bytes().to_vec()),
+        ]);
+        assert!(pipeline.run(clean).is_ok());
+    }
+
+    #[test]
+    fn blanked_fallback_routes_corrupt_source_to_blanked_pair() {
+        let config = PipelineConfig::new().blanked_fallback(true);
+        let pipeline = Pipeline::new(config, MemorySink::new()).unwrap();
+        let reports = pipeline.run(memory_source()).unwrap();
+
+        assert_eq!(reports[1].outcome, FileOutcome::Fallback { output: None });
+        let pairs = pipeline.sink().pairs();
+        let blanked: Vec<_> = pairs.iter().filter(|p| p.blanked).collect();
+        assert_eq!(blanked.len(), 1);
+        assert_eq!(blanked[0].key, "b/C.java");
+    }
+
+    #[test]
+    fn globs_filters_naming_and_schema_apply() {
+        let config = PipelineConfig::new()
+            .exclude(vec!["b/**".to_string()])
+            .filters(Filters {
+                min_tokens: Some(5),
+                ..Filters::default()
+            })
+            .naming(Naming {
+                function_prefix: "m".to_string(),
+                ..Naming::default()
+            });
+        let source = Source::Memory(vec![
+            ("a/T.java".to_string(), CLEAN.as_bytes().to_vec()),
+            ("a/S.java".to_string(), b"class S {}".to_vec()),
+            ("b/C.java".to_string(), CORRUPT.as_bytes().to_vec()),
+        ]);
+        let pipeline = Pipeline::new(config, MemorySink::new()).unwrap();
+        let reports = pipeline.run(source).unwrap();
+
+        assert_eq!(reports.len(), 2);
+        assert_eq!(reports[0].outcome, FileOutcome::Clean { output: None });
+        assert!(matches!(reports[1].outcome, FileOutcome::Filtered { .. }));
+        assert!(pipeline.sink().pairs()[0].pair.prompt.contains("m1("));
+
+        let schema = OutputSchema {
+            prompt_field: "input".to_string(),
+            response_field: "output".to_string(),
+        };
+        let dir = TempDir::new().unwrap();
+        let pipeline = Pipeline::new(
+            PipelineConfig::new().schema(schema.clone()),
+            FsSink::new(dir.path()),
+        )
+        .unwrap();
+        let reports = pipeline
+            .run(Source::Memory(vec![(
+                "T.java".to_string(),
+                CLEAN.as_bytes().to_vec(),
+            )]))
+            .unwrap();
+        let FileOutcome::Clean { output: Some(path) } = &reports[0].outcome else {
+            panic!("expected a clean pair, got {:?}", reports[0].outcome);
+        };
+        let line = fs::read_to_string(path).unwrap();
+        assert!(line.starts_with("{\"input\":"));
+        let pair = schema.parse_line(line.trim_end()).unwrap();
+        assert!(pair.prompt.contains("func_1"));
+    }
+
+    #[test]
+    fn shards_partition_the_inputs() {
+        let keys: Vec<String> = (0..20).map(|i| format!("T{}.java", i)).collect();
+        let shards: Vec<Shard> = (0..3).map(|i| Shard::new(i, 3).unwrap()).collect();
+        for key in &keys {
+            assert_eq!(shards.iter().filter(|s| s.contains(key)).count(), 1);
+        }
+        assert_eq!(Shard::parse("1/3"), Ok(shards[1]));
+        assert!(Shard::parse("3/3").is_err());
+        assert!(Shard::new(0, 0).is_err());
+        assert!(serde_json::from_str::<Shard>(r#"{"index": 0, "count": 0}"#).is_err());
+        let shard: Shard = serde_json::from_str(r#"{"index": 1, "count": 3}"#).unwrap();
+        assert_eq!((shard.index(), shard.count()), (1, 3));
+    }
+
+    #[test]
+    fn legacy_encodings_are_transcoded_and_recorded() {
+        let dir = TempDir::new().unwrap();
+        let manifest = dir.path().join("manifest.jsonl");
+        let source =
+            "public class T {\n@Test public void testFoo() { String s = \"caf\u{e9}\"; }\n}";
+        let latin1: Vec<u8> = source.chars().map(|c| c as u8).collect();
+        let utf16: Vec<u8> = "\u{feff}"
+            .encode_utf16()
+            .chain(source.encode_utf16())
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let inputs = Source::Memory(vec![
+            ("a/Latin1.java".to_string(), latin1),
+            ("a/Utf16.java".to_string(), utf16),
+        ]);
+
+        let config = PipelineConfig::new().manifest(&manifest);
+        let pipeline = Pipeline::new(config, MemorySink::new()).unwrap();
+        let reports = pipeline.run(inputs).unwrap();
+        assert!(
+            reports
+                .iter()
+                .all(|r| r.outcome == FileOutcome::Clean { output: None })
+        );
+        for pair in pipeline.sink().pairs() {
+            assert_eq!(pair.pair.response, source);
+        }
+        let (_, stats) = pipeline.finish().unwrap();
+        let summary = stats.finish(std::time::Duration::ZERO);
+        assert_eq!(summary.encodings["windows-1252"], 1);
+        assert_eq!(summary.encodings["utf-16le"], 1);
+
+        let manifest = Manifest::open(&manifest).unwrap();
+        let encoding = |key| manifest.get(key).unwrap().encoding;
+        assert_eq!(encoding("a/Latin1.java"), Some(Encoding::Windows1252));
+        assert_eq!(encoding("a/Utf16.java"), Some(Encoding::Utf16Le));
+    }
+
+    #[test]
+    fn fs_run_writes_split_dirs_and_resumes_from_manifest() {
+        let dir = TempDir::new().unwrap();
+        let input = dir.path().join("in");
+        fs::create_dir_all(input.join("p")).unwrap();
+        fs::write(input.join("p/T.java"), CLEAN).unwrap();
+        let out = dir.path().join("jsonl");
+
+        let config = || {
+            PipelineConfig::new()
+                .split(Some(SplitConfig {
+                    ratios: SplitRatios::new(1.0, 0.0, 0.0).unwrap(),
+                    group: SplitGroup::File,
+                    seed: 0,
+                }))
+                .manifest(out.join("manifest.jsonl"))
+        };
+        fs::create_dir_all(&out).unwrap();
+
+        let pipeline = Pipeline::new(config(), FsSink::new(&out)).unwrap();
+        let reports = pipeline.run(Source::Dir(input.clone())).unwrap();
+        let expected = out.join("train/p/T.java.jsonl");
+        assert_eq!(
+            reports[0].outcome,
+            FileOutcome::Clean {
+                output: Some(expected.clone())
+            }
+        );
+        assert!(expected.is_file());
+        pipeline.finish().unwrap();
+
+        let pipeline = Pipeline::new(config(), FsSink::new(&out)).unwrap();
+        let reports = pipeline.run(Source::Dir(input)).unwrap();
+        assert_eq!(reports[0].outcome, FileOutcome::UpToDate);
+    }
+
+    #[test]
+    fn fs_sink_keeps_same_named_files_of_different_directories_apart() {
+        let dir = TempDir::new().unwrap();
+        let out = dir.path().join("jsonl");
+        let other = CLEAN.replace("testFoo", "testBar");
+        let source = Source::Memory(vec![
+            ("a/T.java".to_string(), CLEAN.as_bytes().to_vec()),
+            ("b/T.java".to_string(), other.as_bytes().to_vec()),
+        ]);
+
+        let pipeline = Pipeline::new(PipelineConfig::new(), FsSink::new(&out)).unwrap();
+        let reports = pipeline.run(source).unwrap();
+        let outputs: Vec<_> = reports
+            .iter()
+            .map(|r| match &r.outcome {
+                FileOutcome::Clean { output: Some(path) } => path.clone(),
+                other => panic!("unexpected outcome {:?}", other),
+            })
+            .collect();
+        assert_eq!(
+            outputs,
+            vec![out.join("a/T.java.jsonl"), out.join("b/T.java.jsonl")]
+        );
+
+        let schema = OutputSchema::default();
+        let response = |path: &Path| {
+            let line = fs::read_to_string(path).unwrap();
+            schema.parse_line(line.trim_end()).unwrap().response
+        };
+        assert_eq!(response(&outputs[0]), CLEAN);
+        assert_eq!(response(&outputs[1]), other);
+    }
+}
@@ -1,132 +1,200 @@
-use crate::obfuscator::blank_source;
+use crate::error::{Error, Result};
+use crate::helper::write_atomic;
+use crate::obfuscator::{Limits, Obfuscation, blank_source};
 use crate::sanitizer::sanitize_structural;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::fs::File;
-use std::io;
-use std::io::{BufWriter, Write};
-
-#[derive(Serialize)]
-struct PromptResponse {
-    prompt: String,
-    response: String,
+use std::path::{Path, PathBuf};
+
+/// One JSONL training pair: the obfuscated source as the prompt and the
+/// original source as the response.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PromptResponse {
+    pub prompt: String,
+    pub response: String,
+}
+
+/// Field names under which a pair is written to JSONL.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OutputSchema {
+    pub prompt_field: String,
+    pub response_field: String,
+}
+
+impl Default for OutputSchema {
+    fn default() -> Self {
+        OutputSchema {
+            prompt_field: "prompt".to_string(),
+            response_field: "response".to_string(),
+        }
+    }
+}
+
+impl OutputSchema {
+    /// Serialize `pair` as one JSON object using this schema's field names.
+    pub fn to_line(&self, pair: &PromptResponse) -> String {
+        let mut object = serde_json::Map::new();
+        object.insert(self.prompt_field.clone(), pair.prompt.clone().into());
+        object.insert(self.response_field.clone(), pair.response.clone().into());
+        serde_json::Value::Object(object).to_string()
+    }
+
+    /// Read a pair written with this schema back from one JSONL line.
+    pub fn parse_line(&self, line: &str) -> Option<PromptResponse> {
+        let value: serde_json::Value = serde_json::from_str(line).ok()?;
+        Some(PromptResponse {
+            prompt: value.get(&self.prompt_field)?.as_str()?.to_string(),
+            response: value.get(&self.response_field)?.as_str()?.to_string(),
+        })
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Token-count integrity check
 // ---------------------------------------------------------------------------
 
-/// Count "structural tokens" in a Java source string.
+/// Iterator over the "structural tokens" of a Java source string.
 ///
 /// A token is one of:
 /// * an identifier / keyword: `[A-Za-z_$][A-Za-z0-9_$]*`
 /// * a numeric literal:       `[0-9]+`
+/// * a run of non-ASCII characters glued to any of the above
 /// * any single non-whitespace, non-alphanumeric character (punctuation /
 ///   operators / string delimiters / …)
 ///
 /// Whitespace is skipped entirely.
 ///
-/// This deliberately does NOT try to parse Java properly; its only purpose is
-/// to detect gross structural divergence between the `prompt` and `response`
-/// sides of a JSONL pair — for example when a UTF-8 multi-byte character is
-/// corrupted into two Latin-1 surrogates (`é` → `Ã©`), which splits what was
-/// one token into two.
-pub(crate) fn count_tokens(src: &str) -> usize {
-    let bytes = src.as_bytes();
-    let mut count = 0usize;
-    let mut i = 0;
-    while i < bytes.len() {
-        let b = bytes[i];
-        if b.is_ascii_whitespace() {
-            i += 1;
-        } else if b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || !b.is_ascii() {
+/// This deliberately does NOT try to parse Java properly; see [`count_tokens`].
+pub(crate) struct Tokens<'a> {
+    src: &'a str,
+    i: usize,
+}
+
+pub(crate) fn tokens(src: &str) -> Tokens<'_> {
+    Tokens { src, i: 0 }
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        let bytes = self.src.as_bytes();
+        while self.i < bytes.len() && bytes[self.i].is_ascii_whitespace() {
+            self.i += 1;
+        }
+        if self.i >= bytes.len() {
+            return None;
+        }
+        let start = self.i;
+        let b = bytes[self.i];
+        if b.is_ascii_alphanumeric() || b == b'_' || b == b'$' || !b.is_ascii() {
             // Consume the whole word-like token (identifier, number, or UTF-8 run).
-            while i < bytes.len() {
-                let c = bytes[i];
+            while self.i < bytes.len() {
+                let c = bytes[self.i];
                 if c.is_ascii_whitespace()
                     || (c.is_ascii() && !c.is_ascii_alphanumeric() && c != b'_' && c != b'$')
                 {
                     break;
                 }
-                i += 1;
+                self.i += 1;
             }
-            count += 1;
         } else {
             // Single punctuation / operator / delimiter character.
-            count += 1;
-            i += 1;
+            self.i += 1;
         }
+        Some(&self.src[start..self.i])
     }
-    count
 }
 
-/// Assert that `prompt` and `response` have the same token count.
+/// Count "structural tokens" in a Java source string (see [`Tokens`]).
+///
+/// This deliberately does NOT try to parse Java properly; its only purpose is
+/// to detect gross structural divergence between the `prompt` and `response`
+/// sides of a JSONL pair — for example when a UTF-8 multi-byte character is
+/// corrupted into two Latin-1 surrogates (`é` → `Ã©`), which splits what was
+/// one token into two.
+pub(crate) fn count_tokens(src: &str) -> usize {
+    tokens(src).count()
+}
+
+/// Check that `prompt` and `response` have the same token count.
 ///
 /// A mismatch almost always indicates a UTF-8 encoding bug (e.g. `é` → `Ã©`)
-/// or a literal-blanking asymmetry.  Panics in debug builds; in release builds
-/// it logs the discrepancy to stderr and returns an `Err` so the caller can
-/// route the pair to the error log rather than silently producing bad training
-/// data.
-pub(crate) fn assert_token_count_match(
-    prompt: &str,
-    response: &str,
-    label: &str,
-) -> io::Result<()> {
+/// or a literal-blanking asymmetry.  It is returned as
+/// [`Error::TokenMismatch`] in every build so the caller can route the pair to
+/// the error log rather than silently producing bad training data.  Sources
+/// that arrive mis-decoded can be repaired beforehand by the sanitizer's
+/// `mojibake` step.
+pub(crate) fn check_token_counts(prompt: &str, response: &str, label: &str) -> Result<()> {
     let p = count_tokens(prompt);
     let r = count_tokens(response);
     if p != r {
-        let msg = format!(
-            "token count mismatch in {label}: prompt={p} response={r}\n\
-             This usually means a UTF-8 multi-byte character was corrupted during \
-             literal blanking/restoring (e.g. é → Ã©).  \
-             Check `consume_string_literal` in literal_blanker.rs."
-        );
-        // Panic in test / debug builds to catch regressions early.
-        debug_assert!(false, "{msg}");
-        return Err(io::Error::new(io::ErrorKind::InvalidData, msg));
+        return Err(Error::TokenMismatch {
+            label: label.to_string(),
+            prompt: p,
+            response: r,
+        });
     }
     Ok(())
 }
 
-/// Write a JSONL training pair from in-memory source strings **without**
-/// blanking string literals.
+/// Serialize `pair` as one JSONL line and write it atomically to `output_file`.
+fn write_pair(output_file: &str, pair: &PromptResponse) -> Result<()> {
+    let line = serde_json::to_string(pair)?;
+    write_atomic(Path::new(output_file), |w| writeln!(w, "{}", line))?;
+    Ok(())
+}
+
+/// Reject output paths without a `.jsonl` extension.
+fn check_jsonl_extension(output_file: &str) -> Result<()> {
+    if output_file.ends_with(".jsonl") {
+        Ok(())
+    } else {
+        Err(Error::InvalidExtension {
+            path: PathBuf::from(output_file),
+            expected: "jsonl",
+        })
+    }
+}
+
+/// Build a training pair from in-memory source strings **without** blanking
+/// string literals.
 ///
 /// Use this for clean sources where `obfuscate_str_checked` returned
 /// `needed_fallback = false` — the obfuscated string already has real literal
-/// content restored, so no further transformation is needed.
-pub fn generate_jsonl_raw(
-    original_src: &str,
-    obfuscated_src: &str,
-    output_file: &str,
-) -> std::io::Result<()> {
-    if !output_file.ends_with(".jsonl") {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Output file must have a .jsonl extension",
-        ));
-    }
-
+/// content restored, so no further transformation is needed.  `label` names
+/// the pair in error messages.
+pub fn raw_pair(original_src: &str, obfuscated_src: &str, label: &str) -> Result<PromptResponse> {
     if obfuscated_src.trim().is_empty() {
-        return Err(std::io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Obfuscated source is empty",
-        ));
+        return Err(Error::EmptyOutput {
+            label: label.to_string(),
+        });
     }
 
     // Guard: prompt and response must have the same token count.
     // A mismatch means the literal round-trip corrupted multi-byte characters.
-    assert_token_count_match(obfuscated_src, original_src, output_file)?;
+    check_token_counts(obfuscated_src, original_src, label)?;
 
-    let mut writer = BufWriter::new(File::create(output_file)?);
-    let pair = PromptResponse {
+    Ok(PromptResponse {
         prompt: obfuscated_src.to_string(),
         response: original_src.to_string(),
-    };
-    writeln!(writer, "{}", serde_json::to_string(&pair)?)?;
-    Ok(())
+    })
+}
+
+/// Write a JSONL training pair built by [`raw_pair`] to `output_file`.
+pub fn generate_jsonl_raw(
+    original_src: &str,
+    obfuscated_src: &str,
+    output_file: &str,
+) -> Result<()> {
+    check_jsonl_extension(output_file)?;
+
+    let pair = raw_pair(original_src, obfuscated_src, output_file)?;
+    write_pair(output_file, &pair)
 }
 
-/// Write a JSONL training pair from in-memory source strings.
+/// Build a training pair from in-memory source strings with literals blanked.
 ///
 /// Both sides have string/char literals permanently replaced with `"_"` /
 /// `'X'` via `blank_source`, which mirrors `obfuscate_str`'s blanking logic:
@@ -138,44 +206,72 @@ pub fn generate_jsonl_raw(
 ///
 /// Use this only for the fallback (blanked) path — i.e. when
 /// `obfuscate_str_checked` returned `needed_fallback = true`.
-pub fn generate_jsonl_from_strings(
+pub fn blanked_pair(
     original_src: &str,
     obfuscated_src: &str,
-    output_file: &str,
-) -> std::io::Result<()> {
-    if !output_file.ends_with(".jsonl") {
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::InvalidInput,
-            "Output file must have a .jsonl extension",
-        ));
+    label: &str,
+) -> Result<PromptResponse> {
+    let blank = |src| {
+        blank_source(src, &Limits::default()).map_err(|limit| Error::LimitExceeded {
+            label: label.to_string(),
+            limit,
+        })
+    };
+    let prompt = blank(obfuscated_src)?;
+    let response = blank(original_src)?;
+
+    if prompt.trim().is_empty() {
+        return Err(Error::EmptyOutput {
+            label: label.to_string(),
+        });
     }
 
-    let prompt = blank_source(obfuscated_src);
-    let response = blank_source(original_src);
+    // Guard: both blanked sides must have the same token count.
+    check_token_counts(&prompt, &response, label)?;
 
+    Ok(PromptResponse { prompt, response })
+}
+
+/// The training pair of `original_src` and its [`Obfuscation`]: a
+/// [`raw_pair`] on the clean path, a blanked pair on the fallback path.
+///
+/// Unlike [`blanked_pair`], the fallback sides are taken from the
+/// obfuscation instead of being blanked and parsed again.
+pub fn pair_of(
+    original_src: &str,
+    obfuscation: &Obfuscation,
+    label: &str,
+) -> Result<PromptResponse> {
+    let Some((prompt, response)) = &obfuscation.blanked else {
+        return raw_pair(original_src, &obfuscation.source, label);
+    };
     if prompt.trim().is_empty() {
-        return Err(std::io::Error::new(
-            io::ErrorKind::InvalidData,
-            "Obfuscated source is empty",
-        ));
+        return Err(Error::EmptyOutput {
+            label: label.to_string(),
+        });
     }
+    check_token_counts(prompt, response, label)?;
+    Ok(PromptResponse {
+        prompt: prompt.clone(),
+        response: response.clone(),
+    })
+}
 
-    // Guard: both blanked sides must have the same token count.
-    assert_token_count_match(&prompt, &response, output_file)?;
+/// Write a JSONL training pair built by [`blanked_pair`] to `output_file`.
+pub fn generate_jsonl_from_strings(
+    original_src: &str,
+    obfuscated_src: &str,
+    output_file: &str,
+) -> Result<()> {
+    check_jsonl_extension(output_file)?;
 
-    let mut writer = BufWriter::new(File::create(output_file)?);
-    let pair = PromptResponse { prompt, response };
-    writeln!(writer, "{}", serde_json::to_string(&pair)?)?;
-    Ok(())
+    let pair = blanked_pair(original_src, obfuscated_src, output_file)?;
+    write_pair(output_file, &pair)
 }
 
 /// File-based wrapper: reads both files, applies `sanitize_structural`, then
 /// delegates to `generate_jsonl_from_strings`.
-pub fn generate_jsonl(
-    original_file: &str,
-    obfuscated_file: &str,
-    output_file: &str,
-) -> std::io::Result<()> {
+pub fn generate_jsonl(original_file: &str, obfuscated_file: &str, output_file: &str) -> Result<()> {
     let original_p1 = sanitize_structural(&fs::read_to_string(original_file)?);
     let obfuscated_p1 = sanitize_structural(&fs::read_to_string(obfuscated_file)?);
     generate_jsonl_from_strings(&original_p1, &obfuscated_p1, output_file)
@@ -184,10 +280,10 @@ pub fn generate_jsonl(
 #[cfg(test)]
 mod tests {
     use super::generate_jsonl_from_strings;
+    use crate::error::Error;
     use crate::processor::generate_jsonl_raw;
     use regex::Regex;
     use std::fs;
-    use std::io::ErrorKind;
     use tempfile::NamedTempFile;
 
     // ── helpers ──────────────────────────────────────────────────────────────
@@ -266,7 +362,7 @@ mod tests {
             result.is_err(),
             "must fail when the obfuscated file is empty"
         );
-        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(matches!(result.unwrap_err(), Error::EmptyOutput { .. }));
 
         let written = fs::read_to_string(&out_path).unwrap_or_default();
         assert!(
@@ -393,7 +489,7 @@ mod tests {
         );
 
         assert!(result.is_err(), "must reject an empty obfuscated file");
-        assert_eq!(result.unwrap_err().kind(), ErrorKind::InvalidData);
+        assert!(matches!(result.unwrap_err(), Error::EmptyOutput { .. }));
 
         let written = fs::read_to_string(&out_path).unwrap_or_default();
         assert!(
@@ -971,14 +1067,24 @@ mod tests {
         let response_correct =
             "assertThat(text, is(\"Bonjour John Doe, le test unitaire est passé\"));";
 
-        let result = super::assert_token_count_match(prompt_mojibake, response_correct, "test");
+        let result = super::check_token_counts(prompt_mojibake, response_correct, "test");
         // Mojibake splits 1 multi-byte char into 2 tokens, so counts will differ.
         // This checks the guard actually fires on broken data.
         // (The counts may or may not differ depending on our tokenizer treating
         //  non-ASCII runs as single tokens — but the round-trip fix means this
         //  scenario should never arise in production.)
         // We primarily test that the function does not panic and returns a result.
-        let _ = result; // accepted either Ok or Err — the real guard is in assert_token_count_match
+        let _ = result; // accepted either Ok or Err — the real guard is in check_token_counts
+    }
+
+    #[test]
+    fn token_count_mismatch_is_an_error_not_a_panic() {
+        let err = super::check_token_counts("a ( b )", "a ( b", "T.java").unwrap_err();
+        assert_eq!(
+            err.category(),
+            crate::error_log::ErrorCategory::TokenMismatch
+        );
+        assert!(err.to_string().contains("in T.java"));
     }
 
     /// Verify count_tokens treats a UTF-8 accented word as ONE token, not two.
@@ -1003,4 +1109,34 @@ mod tests {
             "count_tokens must treat UTF-8 and mojibake runs equivalently (both non-ASCII)"
         );
     }
+
+    #[test]
+    fn pair_of_matches_raw_and_blanked_pairs() {
+        use crate::obfuscator::{Limits, Naming, blank_source, obfuscate_source};
+
+        let clean = "public class T { @Test public void t() { String s = \"a\"; check(s); } }";
+        let corrupt = "public class T {\n@Test public void t() { HttpRequest r = get(\"/x\"); check(r, \"{\\\\n \\\\\"name\\\\\" : 1}\"); }\n}";
+        let pair_of = |src| {
+            let obfuscation =
+                obfuscate_source(src, &Naming::default(), &Limits::default()).unwrap();
+            (super::pair_of(src, &obfuscation, "t").unwrap(), obfuscation)
+        };
+
+        let (pair, obfuscation) = pair_of(clean);
+        assert_eq!(
+            pair,
+            super::raw_pair(clean, &obfuscation.source, "t").unwrap()
+        );
+
+        // The fallback pair keeps the literals that `blanked_pair` blanks.
+        let (pair, obfuscation) = pair_of(corrupt);
+        assert!(pair.prompt.contains("\"/x\"") && pair.response.contains("\"/x\""));
+        assert_eq!(
+            super::blanked_pair(corrupt, &obfuscation.source, "t").unwrap(),
+            super::PromptResponse {
+                prompt: blank_source(&pair.prompt, &Limits::default()).unwrap(),
+                response: blank_source(&pair.response, &Limits::default()).unwrap(),
+            }
+        );
+    }
 }
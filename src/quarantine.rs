@@ -0,0 +1,132 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::helper::write_atomic;
+use crate::obfuscator::{LimitExceeded, Limits, SourcePosition, parse_error_locations};
+
+/// Suffix of the reason file written next to each quarantined source.
+pub const REASON_SUFFIX: &str = ".reason.json";
+
+/// Why a source was quarantined, written as `<file>.reason.json`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuarantineReason {
+    /// Original input path.
+    pub file: PathBuf,
+    pub file_hash: String,
+    pub reason: String,
+    /// Outermost tree-sitter ERROR / MISSING nodes in `sanitized_source`.
+    pub parse_errors: Vec<SourcePosition>,
+    /// The source as seen by the obfuscator, after sanitizing.
+    pub sanitized_source: String,
+}
+
+impl QuarantineReason {
+    /// The reason for `sanitized_source`, whose parse errors are located
+    /// with a parse within `limits`.
+    pub fn new(
+        file: &Path,
+        file_hash: &str,
+        reason: &str,
+        sanitized_source: &str,
+        limits: &Limits,
+    ) -> Result<Self, LimitExceeded> {
+        Ok(QuarantineReason {
+            file: file.to_path_buf(),
+            file_hash: file_hash.to_string(),
+            reason: reason.to_string(),
+            parse_errors: parse_error_locations(sanitized_source, limits)?,
+            sanitized_source: sanitized_source.to_string(),
+        })
+    }
+}
+
+/// Sibling quarantine directory of a JSONL output directory:
+/// `<parent>/<name>_quarantine`.
+pub fn quarantine_dir_of(jsonl_output_dir: &Path) -> PathBuf {
+    let parent = jsonl_output_dir.parent().unwrap_or(Path::new("."));
+    let dir_name = jsonl_output_dir
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("jsonl");
+    parent.join(format!("{}_quarantine", dir_name))
+}
+
+/// Reason file belonging to the quarantined copy at `copy`.
+pub fn reason_file_of(copy: &Path) -> PathBuf {
+    let mut name = copy.file_name().unwrap_or_default().to_os_string();
+    name.push(REASON_SUFFIX);
+    copy.with_file_name(name)
+}
+
+/// Write `original` to `quarantine_dir/<key>` and `reason` next to it.
+/// `key` is the input path relative to the input root, so the directory
+/// mirrors the input tree.  Returns the path of the copy.
+pub fn quarantine(
+    quarantine_dir: &Path,
+    key: &str,
+    original: &[u8],
+    reason: &QuarantineReason,
+) -> io::Result<PathBuf> {
+    let copy = quarantine_dir.join(key);
+    if let Some(parent) = copy.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    write_atomic(&copy, |w| w.write_all(original))?;
+
+    let json = serde_json::to_string_pretty(reason)?;
+    write_atomic(&reason_file_of(&copy), |w| w.write_all(json.as_bytes()))?;
+    Ok(copy)
+}
+
+/// Remove a quarantined copy and its reason file, e.g. once the input has
+/// been repaired.  Missing files are ignored.
+pub fn release(copy: &Path) {
+    let _ = fs::remove_file(copy);
+    let _ = fs::remove_file(reason_file_of(copy));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn quarantine_dir_of_appends_suffix() {
+        assert_eq!(
+            quarantine_dir_of(Path::new("out/jsonl")),
+            PathBuf::from("out/jsonl_quarantine")
+        );
+    }
+
+    #[test]
+    fn quarantine_copies_source_and_writes_reason() {
+        let dir = TempDir::new().unwrap();
+        let source = "class T {\n  void m() { int x = ; }\n}";
+        let qdir = dir.path().join("jsonl_quarantine");
+
+        let reason = QuarantineReason::new(
+            Path::new("in/a/Bad.java"),
+            "h",
+            "corrupt",
+            source,
+            &Limits::default(),
+        )
+        .unwrap();
+        let copy = quarantine(&qdir, "a/Bad.java", source.as_bytes(), &reason).unwrap();
+        assert_eq!(copy, qdir.join("a/Bad.java"));
+        assert_eq!(fs::read_to_string(&copy).unwrap(), source);
+
+        let reason_path = reason_file_of(&copy);
+        assert!(reason_path.ends_with("a/Bad.java.reason.json"));
+        let reason: QuarantineReason =
+            serde_json::from_str(&fs::read_to_string(&reason_path).unwrap()).unwrap();
+        assert_eq!(reason.sanitized_source, source);
+        assert_eq!(reason.parse_errors[0].line, 2);
+
+        release(&copy);
+        assert!(!copy.exists() && !reason_path.exists());
+    }
+}
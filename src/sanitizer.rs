@@ -1,5 +1,10 @@
 // AI generated code
 
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::single_byte_of;
+use crate::lexer::{Lexer, Token, TokenKind};
+
 /// Full sanitisation pipeline (steps 1–5).
 ///
 /// Prefer calling [`sanitize_structural`] + [`fix_string_literals`] +
@@ -21,14 +26,102 @@ pub fn sanitize(src: &str) -> String {
 /// Step 3 (backslash normalisation before `"`) is intentionally left out so
 /// that [`fix_string_literals`] can compare literal contents before those
 /// backslash runs are mutated.
-
 pub fn sanitize_structural(src: &str) -> String {
+    sanitize_structural_with(src, &SanitizerSteps::default())
+}
+
+/// Which steps of [`sanitize_structural`] run.  All but `mojibake` are
+/// enabled by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SanitizerSteps {
+    /// Strip null bytes and collapse CRLF → LF.
+    pub line_endings: bool,
+    /// Decode JSON unicode escapes (`\u0022` → `"`).
+    pub unicode_escapes: bool,
+    /// `\'` → `'`.
+    pub escaped_single_quotes: bool,
+    /// Escape raw newlines inside string literals.
+    pub raw_newlines_in_strings: bool,
+    /// Reverse UTF-8 text mis-decoded as Latin-1 (`Ã©` → `é`) in literals
+    /// and comments.  Off by default: it also rewrites such text on purpose.
+    pub mojibake: bool,
+}
+
+impl Default for SanitizerSteps {
+    fn default() -> Self {
+        SanitizerSteps {
+            line_endings: true,
+            unicode_escapes: true,
+            escaped_single_quotes: true,
+            raw_newlines_in_strings: true,
+            mojibake: false,
+        }
+    }
+}
+
+/// Result of [`sanitize_structural_counted`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sanitized {
+    pub text: String,
+    /// Characters repaired by the `mojibake` step.
+    pub mojibake_repairs: usize,
+}
+
+/// [`sanitize_structural`] running only the enabled `steps`.
+pub fn sanitize_structural_with(src: &str, steps: &SanitizerSteps) -> String {
+    sanitize_structural_counted(src, steps).text
+}
+
+/// [`sanitize_structural_with`], also reporting what the steps repaired.
+pub fn sanitize_structural_counted(src: &str, steps: &SanitizerSteps) -> Sanitized {
+    let mut out = if steps.line_endings {
+        fix_line_endings_and_nulls(src)
+    } else {
+        src.to_string()
+    };
+
+    // ── 1. JSON unicode escapes that leaked into the source ──────────────────
+    if steps.unicode_escapes {
+        out = fix_json_unicode_escapes(&out);
+    }
+
+    // ── 2. Escaped single-quotes  \'  →  '  ─────────────────────────────────
+    if steps.escaped_single_quotes {
+        out = fix_escaped_single_quotes(&out);
+    }
+
+    // ── 3. Raw newlines / CRs inside string literals  →  \n / \r  ───────────
+    // A bare 0x0A inside a Java string literal is invalid Java; it prevents
+    // tree-sitter from building a clean AST and causes obfuscate_str to
+    // silently produce zero identifier renames (prompt == response in JSONL).
+    // This step must run AFTER the CRLF pass (so lone \r is already gone from
+    // inter-token whitespace) but BEFORE sanitize_backslashes, because the
+    // two new backslashes it emits are valid Java escape characters that the
+    // backslash normaliser must not collapse.
+    if steps.raw_newlines_in_strings {
+        out = fix_raw_newlines_in_string_literals(&out);
+    }
+
+    // ── 4. Mojibake in literals and comments  (`Ã©` → `é`)  ─────────────────
+    let mut mojibake_repairs = 0;
+    if steps.mojibake {
+        (out, mojibake_repairs) = repair_mojibake(&out);
+    }
+    Sanitized {
+        text: out,
+        mojibake_repairs,
+    }
+}
+
+/// Strip null bytes and collapse CRLF → LF.
+fn fix_line_endings_and_nulls(src: &str) -> String {
     // ── Single pass: strip null bytes and collapse CRLF → LF ────────────────
     // This avoids two separate `.replace()` calls (each of which clones the
     // whole string). We write into a pre-allocated buffer and only allocate a
     // new String when the source actually contains one of these sequences.
     let needs_fixup = src.contains('\0') || src.contains("\r\n");
-    let after_cr_null = if needs_fixup {
+    if needs_fixup {
         let mut out = String::with_capacity(src.len());
         let mut bytes = src.as_bytes();
         while !bytes.is_empty() {
@@ -54,23 +147,7 @@ pub fn sanitize_structural(src: &str) -> String {
         out
     } else {
         src.to_string()
-    };
-
-    // ── 1. JSON unicode escapes that leaked into the source ──────────────────
-    let after_unicode = fix_json_unicode_escapes(&after_cr_null);
-
-    // ── 2. Escaped single-quotes  \'  →  '  ─────────────────────────────────
-    let after_sq = fix_escaped_single_quotes(&after_unicode);
-
-    // ── 3. Raw newlines / CRs inside string literals  →  \n / \r  ───────────
-    // A bare 0x0A inside a Java string literal is invalid Java; it prevents
-    // tree-sitter from building a clean AST and causes obfuscate_str to
-    // silently produce zero identifier renames (prompt == response in JSONL).
-    // This step must run AFTER the CRLF pass (so lone \r is already gone from
-    // inter-token whitespace) but BEFORE sanitize_backslashes, because the
-    // two new backslashes it emits are valid Java escape characters that the
-    // backslash normaliser must not collapse.
-    fix_raw_newlines_in_string_literals(&after_sq)
+    }
 }
 
 /// Phase 2 — over-escaped backslashes before double-quotes.
@@ -86,7 +163,6 @@ pub fn sanitize_structural(src: &str) -> String {
 /// We therefore repeat the replacement until the output stabilises so
 /// that every `\"` in the result has exactly one preceding backslash,
 /// regardless of how many encoding passes the source went through.
-
 pub fn sanitize_backslashes(src: &str) -> String {
     let mut out = src.to_string();
     loop {
@@ -107,90 +183,20 @@ pub fn sanitize_backslashes(src: &str) -> String {
 // String literal repair
 // ---------------------------------------------------------------------------
 
-/// Extract all double-quoted string literal spans from Java source.
-/// Returns a list of `(start, end)` byte offsets where `src[start..end]`
-/// is the full literal including the surrounding `"` characters.
+/// Every string literal and text block of a Java source, as lexed by
+/// [`Lexer`], in source order.  Unterminated literals are included.
 ///
-/// Uses the same corruption-recovery heuristic as `blank_literals` /
-/// `consume_string_literal`: when an even backslash run precedes a `"` and
-/// the following byte looks like it cannot start a valid Java token after a
-/// string close (a letter, digit, `{`, `}`, `:`, `_`, `$`, or `\`), the `"`
-/// is treated as an embedded escaped quote and scanning continues.
-///
-/// This must stay in sync with `literal_blanker::consume_string_literal` so
-/// that `fix_string_literals` always counts the same number of literals as
-/// `blank_literals`, preventing "String literal count mismatch" errors.
-pub(crate) fn extract_string_literal_spans(src: &str) -> Vec<(usize, usize)> {
-    fn is_suspicious_after_even_backslash_close(b: u8) -> bool {
-        matches!(
-            b,
-            b'a'..=b'z'
-                | b'A'..=b'Z'
-                | b'0'..=b'9'
-                | b'{'
-                | b'}'
-                | b':'
-                | b','
-                | b'['
-                | b']'
-                | b'\''  // apostrophe — never directly follows a Java string close
-                | b'_'
-                | b'$'
-                | b'\\'
-        )
-    }
-
-    let bytes = src.as_bytes();
-    let mut spans = Vec::new();
-    let mut i = 0;
-
-    while i < bytes.len() {
-        if bytes[i] == b'"' {
-            let start = i;
-            i += 1;
-            loop {
-                if i >= bytes.len() {
-                    break;
-                }
-                if bytes[i] == b'\\' {
-                    // Count the full backslash run.
-                    let run_start = i;
-                    while i < bytes.len() && bytes[i] == b'\\' {
-                        i += 1;
-                    }
-                    let run_len = i - run_start;
-                    if i < bytes.len() && bytes[i] == b'"' {
-                        let next = bytes.get(i + 1).copied().unwrap_or(b' ');
-                        if run_len % 2 == 0 && is_suspicious_after_even_backslash_close(next) {
-                            // Corruption heuristic: treat as embedded quote, keep scanning.
-                            i += 1;
-                        } else if run_len % 2 == 0 {
-                            // Even run + non-suspicious next byte → real closing quote.
-                            spans.push((start, i + 1));
-                            i += 1;
-                            break;
-                        } else {
-                            // Odd run → the last `\` escapes the `"`, keep scanning.
-                            i += 1;
-                        }
-                    }
-                } else if bytes[i] == b'"' {
-                    // Bare unescaped closing quote.
-                    spans.push((start, i + 1));
-                    i += 1;
-                    break;
-                } else {
-                    i += 1;
-                }
-            }
-        } else {
-            i += 1;
-        }
-    }
-
-    spans
+/// Because [`blank_literals`](crate::literal_blanker::blank_literals) lexes
+/// through the same [`Lexer`], `fix_string_literals` always sees the same
+/// literals as the blanker, preventing "String literal count mismatch"
+/// errors.
+pub(crate) fn extract_string_literal_spans(src: &str) -> Vec<Token> {
+    Lexer::new(src).filter(Token::is_string).collect()
 }
 
+/// Replace the content of every string literal of `response` with the
+/// content of the corresponding literal of `prompt`, or `None` when the two
+/// have a different number of literals.
 pub fn fix_string_literals(prompt: &str, response: &str) -> Option<String> {
     let p_spans = extract_string_literal_spans(prompt);
     let r_spans = extract_string_literal_spans(response);
@@ -204,13 +210,15 @@ pub fn fix_string_literals(prompt: &str, response: &str) -> Option<String> {
 
     // Iterate in reverse so that replacing a span does not shift the byte
     // offsets of spans that come earlier in the string.
-    for ((r_start, r_end), (p_start, p_end)) in
-        r_spans.iter().copied().zip(p_spans.iter().copied()).rev()
-    {
-        let r_inner = &response[r_start + 1..r_end - 1];
-        let p_inner = &prompt[p_start + 1..p_end - 1];
-        if r_inner != p_inner {
-            result.replace_range(r_start + 1..r_end - 1, p_inner);
+    for (r, p) in r_spans.iter().zip(&p_spans).rev() {
+        if r.kind != p.kind {
+            // A text block paired with a plain string: not a corruption this
+            // repair understands.
+            continue;
+        }
+        let p_inner = &prompt[p.content()];
+        if response[r.content()] != *p_inner {
+            result.replace_range(r.content(), p_inner);
         }
     }
 
@@ -234,144 +242,103 @@ pub fn fix_string_literals(prompt: &str, response: &str) -> Option<String> {
 ///
 /// Fix: replace each bare `\n` with the two-character escape `\n` and each
 /// bare `\r` with `\r` *inside string literals only*.  Outside string literals
-/// newlines are meaningful Java whitespace and must be left alone.
-///
-/// The scanner uses the same backslash-run logic as `consume_string_literal`
-/// in `literal_blanker`: an odd run of backslashes before `"` means an
-/// embedded escaped quote (keep scanning); an even run or a bare `"` closes
-/// the string.
-/// Returns `true` for bytes that cannot validly appear immediately after a
-/// closing `"` in well-formed Java source (same heuristic as
-/// `literal_blanker::is_suspicious_after_even_backslash_close`).
+/// newlines are meaningful Java whitespace and must be left alone, and text
+/// blocks may contain raw newlines.
 ///
-/// Used by `fix_raw_newlines_in_string_literals` so that its string-boundary
-/// detection stays in sync with `blank_literals` / `consume_string_literal`.
-#[inline]
-fn is_suspicious_close(b: u8) -> bool {
-    matches!(
-        b,
-        b'a'..=b'z'
-        | b'A'..=b'Z'
-        | b'0'..=b'9'
-        | b'{'
-        | b'}'
-        | b':'
-        | b','
-        | b'['
-        | b']'
-        | b'\''   // apostrophe — never directly follows a Java string close
-        | b'_'
-        | b'$'
-        | b'\\'
-    )
-}
-
-fn fix_raw_newlines_in_string_literals(src: &str) -> String {
-    // Fast path: if there is no double-quote in the source there are no string
-    // literals to inspect, and if there are no raw newlines inside a quoted
-    // region there is nothing to do.
+/// String boundaries come from [`Lexer`], so they match `blank_literals`
+/// exactly, including its corruption-recovery heuristic.
+pub(crate) fn fix_raw_newlines_in_string_literals(src: &str) -> String {
+    // Fast path: no double-quote means no string literals to inspect.
     if !src.contains('"') {
         return src.to_string();
     }
 
-    let bytes = src.as_bytes();
-    let n = bytes.len();
     let mut out = String::with_capacity(src.len());
-    let mut i = 0;
-
-    while i < n {
-        if bytes[i] == b'"' {
-            // ── Enter a string literal ──────────────────────────────────────
-            out.push('"');
-            i += 1;
+    for token in Lexer::new(src) {
+        let text = token.text(src);
+        if token.kind == TokenKind::String && text.contains(['\n', '\r']) {
+            out.push_str(&text.replace('\n', "\\n").replace('\r', "\\r"));
+        } else {
+            out.push_str(text);
+        }
+    }
+    out
+}
 
-            loop {
-                if i >= n {
-                    break;
-                }
-                match bytes[i] {
-                    b'\\' => {
-                        // Count the full backslash run so we can determine
-                        // whether the following `"` (if any) is escaped.
-                        let run_start = i;
-                        while i < n && bytes[i] == b'\\' {
-                            i += 1;
-                        }
-                        let run_len = i - run_start;
+/// Reverse UTF-8 text that was decoded as Latin-1 or Windows-1252 (`é` →
+/// `Ã©`) inside string, text-block and char literals and comments.
+///
+/// A run of characters is replaced only when the bytes they decode from form
+/// one valid UTF-8 character, so the repair round-trips losslessly; text that
+/// was mis-decoded twice is repaired until it is stable.  Returns the source
+/// and the number of characters of the result that differ from the input,
+/// so a character repaired over several passes counts once.
+pub fn repair_mojibake(src: &str) -> (String, usize) {
+    if src.is_ascii() {
+        return (src.to_string(), 0);
+    }
 
-                        // Emit all backslashes verbatim.
-                        for _ in 0..run_len {
-                            out.push('\\');
-                        }
+    let mut out = String::with_capacity(src.len());
+    let mut repairs = 0;
+    for token in Lexer::new(src) {
+        let text = token.text(src);
+        let prose = token.is_literal()
+            || matches!(token.kind, TokenKind::LineComment | TokenKind::BlockComment);
+        if !prose || text.is_ascii() {
+            out.push_str(text);
+            continue;
+        }
+        let mut chars: Vec<char> = text.chars().collect();
+        let mut repaired = vec![false; chars.len()];
+        while let Some((next, next_repaired)) = repair_mojibake_once(&chars, &repaired) {
+            chars = next;
+            repaired = next_repaired;
+        }
+        repairs += repaired.iter().filter(|&&r| r).count();
+        out.extend(chars);
+    }
+    (out, repairs)
+}
 
-                        if i < n && bytes[i] == b'"' {
-                            if run_len % 2 == 1 {
-                                // Odd run → the last `\` escapes the `"`;
-                                // it is an embedded character, keep scanning.
-                                out.push('"');
-                                i += 1;
-                            } else {
-                                // Even run + `"`: apply the same
-                                // corruption-recovery heuristic as
-                                // `consume_string_literal`: if the byte
-                                // immediately after the `"` looks like it
-                                // cannot start a valid Java token after a
-                                // string close, treat the `"` as an embedded
-                                // escaped quote and keep scanning.
-                                let next = bytes.get(i + 1).copied().unwrap_or(b' ');
-                                if is_suspicious_close(next) {
-                                    // Spurious close — treat as embedded, continue.
-                                    out.push('"');
-                                    i += 1;
-                                } else {
-                                    // Real closing quote.
-                                    out.push('"');
-                                    i += 1;
-                                    break;
-                                }
-                            }
-                        }
-                        // If not followed by `"` the run is part of the
-                        // string content (e.g. `\\n`, `\\t`); continue.
-                    }
-                    b'"' => {
-                        // Bare closing quote — end of literal.
-                        out.push('"');
-                        i += 1;
-                        break;
-                    }
-                    b'\n' => {
-                        // Raw newline inside a string literal → escape it.
-                        out.push('\\');
-                        out.push('n');
-                        i += 1;
-                    }
-                    b'\r' => {
-                        // Raw carriage-return inside a string literal.
-                        // If followed by \n the pair was already collapsed to
-                        // \n by the CRLF pass, so we only see a lone \r here.
-                        out.push('\\');
-                        out.push('r');
-                        i += 1;
-                    }
-                    _ => {
-                        // Copy a full UTF-8 char at once.
-                        let ch = src[i..].chars().next().unwrap_or('\0');
-                        out.push(ch);
-                        i += ch.len_utf8();
-                    }
-                }
+/// One pass of [`repair_mojibake`] over `chars`, where `repaired` marks the
+/// characters that earlier passes produced.  `None` when nothing changed.
+fn repair_mojibake_once(chars: &[char], repaired: &[bool]) -> Option<(Vec<char>, Vec<bool>)> {
+    let mut out = Vec::with_capacity(chars.len());
+    let mut out_repaired = Vec::with_capacity(chars.len());
+    let mut i = 0;
+    while i < chars.len() {
+        match misdecoded_char(&chars[i..]) {
+            Some((c, len)) => {
+                out.push(c);
+                out_repaired.push(true);
+                i += len;
+            }
+            None => {
+                out.push(chars[i]);
+                out_repaired.push(repaired[i]);
+                i += 1;
             }
-        } else {
-            // Outside a string literal — copy verbatim (including newlines,
-            // which are meaningful Java whitespace here).
-            let ch = src[i..].chars().next().unwrap_or('\0');
-            out.push(ch);
-            i += ch.len_utf8();
         }
     }
+    (out.len() != chars.len()).then_some((out, out_repaired))
+}
 
-    out
+/// The character whose UTF-8 bytes, each decoded as one Latin-1 or
+/// Windows-1252 character, start `chars`, with the number of `chars` used.
+fn misdecoded_char(chars: &[char]) -> Option<(char, usize)> {
+    let len = match single_byte_of(*chars.first()?)? {
+        0xC2..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF4 => 4,
+        _ => return None,
+    };
+    let bytes = chars
+        .get(..len)?
+        .iter()
+        .map(|&c| single_byte_of(c))
+        .collect::<Option<Vec<u8>>>()?;
+    let c = std::str::from_utf8(&bytes).ok()?.chars().next()?;
+    Some((c, len))
 }
 
 fn fix_escaped_single_quotes(src: &str) -> String {
@@ -434,12 +401,12 @@ fn fix_json_unicode_escapes(src: &str) -> String {
             && is_hex(bytes[i + 5])
         {
             let hex = &src[i + 2..i + 6];
-            if let Ok(cp) = u32::from_str_radix(hex, 16) {
-                if (0x20..=0x7e).contains(&cp) {
-                    out.push(cp as u8 as char);
-                    i += 6;
-                    continue;
-                }
+            if let Ok(cp) = u32::from_str_radix(hex, 16)
+                && (0x20..=0x7e).contains(&cp)
+            {
+                out.push(cp as u8 as char);
+                i += 6;
+                continue;
             }
         }
 
@@ -452,12 +419,15 @@ fn fix_json_unicode_escapes(src: &str) -> String {
 }
 
 fn is_hex(b: u8) -> bool {
-    matches!(b, b'0'..=b'9' | b'a'..=b'f' | b'A'..=b'F')
+    b.is_ascii_hexdigit()
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{fix_string_literals, sanitize};
+    use super::{
+        SanitizerSteps, fix_string_literals, repair_mojibake, sanitize, sanitize_structural,
+        sanitize_structural_counted,
+    };
 
     #[test]
     fn test_escaped_single_quote_char_literal() {
@@ -804,6 +774,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn text_blocks_are_one_span_and_keep_their_newlines() {
+        let src = "String a = \"\"\"\n  x \"y\"\n  \"\"\"; String b = \"z\";";
+        let spans = super::extract_string_literal_spans(src);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].text(src), "\"\"\"\n  x \"y\"\n  \"\"\"");
+        assert_eq!(super::fix_raw_newlines_in_string_literals(src), src);
+
+        let corrupt = "String a = \"\"\"\n  x \"Ã©\"\n  \"\"\"; String b = \"z\";";
+        let prompt = src.replace('b', "var_1");
+        let fixed = fix_string_literals(&prompt, corrupt).unwrap();
+        assert_eq!(fixed, src);
+    }
+
     #[test]
     fn extract_spans_matches_blank_literals_for_valid_double_backslash() {
         let src = "assertThat(result).isEqualTo(\"\\\\\\\\\");";
@@ -934,4 +918,40 @@ mod tests {
             "a valid \\\\n escape sequence must be left unchanged"
         );
     }
+
+    #[test]
+    fn mojibake_is_repaired_in_literals_and_comments_only() {
+        let src = concat!(
+            "// cafÃ© costs 5 â‚¬\n",
+            "String s = \"cafÃ©\", twice = \"ÃƒÂ©\", kept = \"é Ã \";\n",
+            "/* naÃ¯ve */ int cafÃ© = 1;",
+        );
+        let (repaired, repairs) = repair_mojibake(src);
+        assert_eq!(
+            repaired,
+            concat!(
+                "// café costs 5 €\n",
+                "String s = \"café\", twice = \"é\", kept = \"é Ã \";\n",
+                "/* naïve */ int cafÃ© = 1;",
+            )
+        );
+        // `ÃƒÂ©` takes two passes, to `Ã©` and then to `é`, but is one
+        // repaired character.
+        assert_eq!(repairs, 5);
+        assert_eq!(repair_mojibake(&repaired), (repaired.clone(), 0));
+    }
+
+    #[test]
+    fn mojibake_step_is_off_by_default_and_counted() {
+        let src = "String s = \"passÃ©\";";
+        assert_eq!(sanitize_structural(src), src);
+
+        let steps = SanitizerSteps {
+            mojibake: true,
+            ..SanitizerSteps::default()
+        };
+        let sanitized = sanitize_structural_counted(src, &steps);
+        assert_eq!(sanitized.text, "String s = \"passé\";");
+        assert_eq!(sanitized.mojibake_repairs, 1);
+    }
 }
@@ -0,0 +1,740 @@
+//! Long-running obfuscation server for data loaders that want a fresh
+//! renaming per epoch instead of a frozen dataset.
+//!
+//! Requests are JSON-RPC 2.0 objects, one per line, over a loopback TCP
+//! socket or a Unix socket.  The same objects can be sent as the body of an
+//! HTTP `POST` on the same socket.  Each connection gets a thread; requests
+//! run on a shared rayon pool so the thread-local parsers are reused.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::helper::panic_message;
+use crate::obfuscator::{Limits, Naming, obfuscate_source};
+use crate::sanitizer::{SanitizerSteps, sanitize_structural_with};
+use crate::summary::rename_mapping;
+
+// JSON-RPC 2.0 error codes.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+/// Implementation-defined: the source could not be obfuscated.
+const OBFUSCATION_FAILED: i64 = -32000;
+
+/// Longest request line, HTTP header line or HTTP body the server reads, in
+/// bytes.  A longer one gets an error reply and closes the connection.
+pub const MAX_REQUEST_BYTES: usize = 16 * 1024 * 1024;
+
+/// Where the server listens.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// A loopback address; port 0 picks a free port.
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl Endpoint {
+    /// Parse `127.0.0.1:7878`, `[::1]:7878` or `unix:/path/to/socket`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(Endpoint::Unix(PathBuf::from(path)));
+            #[cfg(not(unix))]
+            return Err(format!("unix sockets are not supported here: {}", path));
+        }
+        let addr: SocketAddr = s
+            .parse()
+            .map_err(|e| format!("invalid listen address {:?}: {}", s, e))?;
+        if !addr.ip().is_loopback() {
+            return Err(format!(
+                "refusing to listen on non-loopback address {}",
+                addr
+            ));
+        }
+        Ok(Endpoint::Tcp(addr))
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Tcp(addr) => write!(f, "{}", addr),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+// ── Requests ────────────────────────────────────────────────────────────────
+
+/// What every request runs under: the limits, and the naming and sanitizer
+/// steps that fill in the fields its params leave out.
+#[derive(Debug, Clone, Default)]
+pub struct RequestSettings {
+    pub limits: Limits,
+    pub naming: Naming,
+    pub sanitizer: SanitizerSteps,
+}
+
+/// Parameters of the `obfuscate` method.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ObfuscateParams {
+    pub source: String,
+    #[serde(default)]
+    pub naming: Naming,
+    #[serde(default)]
+    pub sanitizer: SanitizerSteps,
+}
+
+/// Result of the `obfuscate` method.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ObfuscateResult {
+    pub obfuscated: String,
+    /// Original identifier → generated name.
+    pub mapping: BTreeMap<String, String>,
+    /// Needed the literal-blanker fallback; literals are blanked.
+    pub fallback: bool,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl fmt::Display) -> Self {
+        RpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+}
+
+impl From<crate::error::Error> for RpcError {
+    fn from(e: crate::error::Error) -> Self {
+        RpcError {
+            data: Some(json!({"category": e.category()})),
+            ..RpcError::new(OBFUSCATION_FAILED, e)
+        }
+    }
+}
+
+/// Sanitize and obfuscate one source within `limits`.
+pub fn obfuscate_request(
+    params: &ObfuscateParams,
+    limits: &Limits,
+) -> crate::error::Result<ObfuscateResult> {
+    let sanitized = sanitize_structural_with(&params.source, &params.sanitizer);
+    let obfuscation = obfuscate_source(&sanitized, &params.naming, limits)?;
+    // Fallback output has blanked literals; align against the blanked original.
+    let mapping = match &obfuscation.blanked {
+        Some((prompt, response)) => rename_mapping(response, prompt),
+        None => rename_mapping(&sanitized, &obfuscation.source),
+    };
+    Ok(ObfuscateResult {
+        obfuscated: obfuscation.source,
+        mapping,
+        fallback: obfuscation.fallback,
+    })
+}
+
+/// `params` with the `naming` and `sanitizer` of `settings` under the fields
+/// it leaves out.
+fn with_defaults(mut params: Value, settings: &RequestSettings) -> Result<Value, RpcError> {
+    let Value::Object(fields) = &mut params else {
+        return Ok(params);
+    };
+    let defaults = [
+        ("naming", serde_json::to_value(&settings.naming)),
+        ("sanitizer", serde_json::to_value(settings.sanitizer)),
+    ];
+    for (key, default) in defaults {
+        let Value::Object(mut merged) = default.map_err(|e| RpcError::new(INTERNAL_ERROR, e))?
+        else {
+            continue;
+        };
+        match fields.get_mut(key) {
+            Some(Value::Object(given)) => {
+                merged.extend(std::mem::take(given));
+                *given = merged;
+            }
+            Some(_) => {}
+            None => {
+                fields.insert(key.to_string(), Value::Object(merged));
+            }
+        }
+    }
+    Ok(params)
+}
+
+fn dispatch(method: &str, params: Value, settings: &RequestSettings) -> Result<Value, RpcError> {
+    match method {
+        "obfuscate" => {
+            let params: ObfuscateParams = serde_json::from_value(with_defaults(params, settings)?)
+                .map_err(|e| RpcError::new(INVALID_PARAMS, e))?;
+            let result = obfuscate_request(&params, &settings.limits)?;
+            Ok(serde_json::to_value(result).expect("result serializes"))
+        }
+        "ping" => Ok(json!("pong")),
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("unknown method {:?}", method),
+        )),
+    }
+}
+
+/// Answer one JSON-RPC request line.  Notifications (no `id`) get no reply.
+pub fn handle_request(line: &str, settings: &RequestSettings) -> Option<String> {
+    let request: Value = match serde_json::from_str(line) {
+        Ok(v) => v,
+        Err(e) => return Some(error_response(Value::Null, RpcError::new(PARSE_ERROR, e))),
+    };
+    let id = request.get("id").cloned();
+    let Some(method) = request.get("method").and_then(Value::as_str) else {
+        let err = RpcError::new(INVALID_REQUEST, "missing method");
+        return Some(error_response(id.unwrap_or(Value::Null), err));
+    };
+    let params = request.get("params").cloned().unwrap_or(Value::Null);
+    let outcome = dispatch(method, params, settings);
+    let id = id?;
+    Some(match outcome {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}).to_string(),
+        Err(e) => error_response(id, e),
+    })
+}
+
+fn error_response(id: Value, e: RpcError) -> String {
+    let mut error = json!({"code": e.code, "message": e.message});
+    if let Some(data) = e.data {
+        error["data"] = data;
+    }
+    json!({"jsonrpc": "2.0", "id": id, "error": error}).to_string()
+}
+
+// ── Connections ─────────────────────────────────────────────────────────────
+
+/// The request pool and the settings every request runs under.
+struct Workers {
+    pool: ThreadPool,
+    settings: RequestSettings,
+}
+
+impl Workers {
+    fn handle(&self, line: &str) -> Option<String> {
+        self.guarded(line, |line| handle_request(line, &self.settings))
+    }
+
+    /// `handle` on the pool.  A panic is answered with an internal error, so
+    /// the client gets a reply and the connection stays usable.
+    fn guarded(
+        &self,
+        line: &str,
+        handle: impl Fn(&str) -> Option<String> + Sync,
+    ) -> Option<String> {
+        let handled = panic::catch_unwind(AssertUnwindSafe(|| self.pool.install(|| handle(line))));
+        handled.unwrap_or_else(|payload| {
+            let id = serde_json::from_str::<Value>(line).ok()?.get("id")?.clone();
+            let message = format!("internal error: {}", panic_message(payload.as_ref()));
+            Some(error_response(id, RpcError::new(INTERNAL_ERROR, message)))
+        })
+    }
+}
+
+/// Serve requests from one connection until it closes.  A connection is
+/// either line-delimited JSON-RPC or HTTP/1.1, decided per request by its
+/// first line.
+fn serve_connection(
+    reader: impl Read,
+    mut writer: impl Write,
+    workers: &Workers,
+) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut line = Vec::new();
+    loop {
+        let Some(n) = read_line_bounded(&mut reader, &mut line)? else {
+            let err = RpcError::new(
+                INVALID_REQUEST,
+                format!("request exceeds {} bytes", MAX_REQUEST_BYTES),
+            );
+            writeln!(writer, "{}", error_response(Value::Null, err))?;
+            return writer.flush();
+        };
+        if n == 0 {
+            return Ok(());
+        }
+        let line = String::from_utf8_lossy(&line);
+        let request = line.trim_end();
+        if request.is_empty() {
+            continue;
+        }
+        if let Some(rest) = request.split_once(' ').map(|(method, _)| method)
+            && rest.bytes().all(|b| b.is_ascii_uppercase())
+            && request.ends_with("HTTP/1.1")
+        {
+            if !serve_http(request, &mut reader, &mut writer, workers)? {
+                return writer.flush();
+            }
+        } else if let Some(reply) = workers.handle(request) {
+            writeln!(writer, "{}", reply)?;
+        }
+        writer.flush()?;
+    }
+}
+
+/// Read one line into `buf`, newline included.  Returns the bytes read, or
+/// `None` when the line is longer than [`MAX_REQUEST_BYTES`]; its rest is
+/// left unread.
+fn read_line_bounded(reader: &mut impl BufRead, buf: &mut Vec<u8>) -> io::Result<Option<usize>> {
+    buf.clear();
+    let limit = MAX_REQUEST_BYTES as u64 + 1;
+    let n = reader.take(limit).read_until(b'\n', buf)?;
+    Ok((n <= MAX_REQUEST_BYTES || buf.ends_with(b"\n")).then_some(n))
+}
+
+/// Answer one HTTP request whose request line has been read.  Returns false
+/// when the request was too large, as the connection cannot be read on.
+fn serve_http(
+    request_line: &str,
+    reader: &mut impl BufRead,
+    writer: &mut impl Write,
+    workers: &Workers,
+) -> io::Result<bool> {
+    let mut content_length = 0;
+    let mut header = Vec::new();
+    loop {
+        let Some(n) = read_line_bounded(reader, &mut header)? else {
+            write_http(writer, "413 Payload Too Large", "")?;
+            return Ok(false);
+        };
+        let header = String::from_utf8_lossy(&header);
+        if n == 0 || header.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':')
+            && name.eq_ignore_ascii_case("content-length")
+        {
+            content_length = value.trim().parse().unwrap_or(0);
+        }
+    }
+    if content_length > MAX_REQUEST_BYTES {
+        write_http(writer, "413 Payload Too Large", "")?;
+        return Ok(false);
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (status, reply) = if !request_line.starts_with("POST ") {
+        ("405 Method Not Allowed", String::new())
+    } else {
+        let body = String::from_utf8_lossy(&body);
+        match workers.handle(&body) {
+            Some(reply) => ("200 OK", reply),
+            None => ("204 No Content", String::new()),
+        }
+    };
+    write_http(writer, status, &reply)?;
+    Ok(true)
+}
+
+fn write_http(writer: &mut impl Write, status: &str, reply: &str) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+        status,
+        reply.len(),
+        reply
+    )
+}
+
+// ── Server ──────────────────────────────────────────────────────────────────
+
+type ErrorFn = Box<dyn Fn(&io::Error) + Send + Sync>;
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+/// A bound server; call [`Server::serve`] to accept connections.
+pub struct Server {
+    listener: Listener,
+    endpoint: Endpoint,
+    workers: Arc<Workers>,
+    stopped: Arc<AtomicBool>,
+    on_error: Option<ErrorFn>,
+}
+
+/// Stops a running [`Server`] from another thread.
+#[derive(Clone)]
+pub struct ServerHandle {
+    endpoint: Endpoint,
+    stopped: Arc<AtomicBool>,
+}
+
+impl ServerHandle {
+    /// Make `serve` return after the connection it is waiting for.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        // Wake the blocking accept.
+        let _ = match &self.endpoint {
+            Endpoint::Tcp(addr) => TcpStream::connect(addr).map(drop),
+            #[cfg(unix)]
+            Endpoint::Unix(path) => UnixStream::connect(path).map(drop),
+        };
+    }
+}
+
+impl Server {
+    /// Bind `endpoint`.  `threads` sizes the request pool; 0 uses one thread
+    /// per CPU.  Every request runs under `settings`.  A stale Unix socket
+    /// file left by a previous server is replaced.
+    pub fn bind(
+        endpoint: &Endpoint,
+        threads: usize,
+        settings: RequestSettings,
+    ) -> io::Result<Self> {
+        let (listener, endpoint) = match endpoint {
+            Endpoint::Tcp(addr) => {
+                let listener = TcpListener::bind(addr)?;
+                let local = listener.local_addr()?;
+                (Listener::Tcp(listener), Endpoint::Tcp(local))
+            }
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                if path.exists() && UnixStream::connect(path).is_err() {
+                    std::fs::remove_file(path)?;
+                }
+                let listener = UnixListener::bind(path)?;
+                (Listener::Unix(listener), endpoint.clone())
+            }
+        };
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .thread_name(|i| format!("obfuscate-{}", i))
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(Server {
+            listener,
+            endpoint,
+            workers: Arc::new(Workers { pool, settings }),
+            stopped: Arc::new(AtomicBool::new(false)),
+            on_error: None,
+        })
+    }
+
+    /// Call `f` with every error accepting a connection.  The server keeps
+    /// accepting after one.
+    pub fn on_error(mut self, f: impl Fn(&io::Error) + Send + Sync + 'static) -> Self {
+        self.on_error = Some(Box::new(f));
+        self
+    }
+
+    /// The bound endpoint, with the actual port when bound to port 0.
+    pub fn endpoint(&self) -> &Endpoint {
+        &self.endpoint
+    }
+
+    pub fn handle(&self) -> ServerHandle {
+        ServerHandle {
+            endpoint: self.endpoint.clone(),
+            stopped: Arc::clone(&self.stopped),
+        }
+    }
+
+    /// Accept connections until [`ServerHandle::stop`] is called.
+    pub fn serve(self) -> io::Result<()> {
+        while !self.stopped.load(Ordering::SeqCst) {
+            // A failed connection only affects its own client, and a failed
+            // accept (e.g. out of file descriptors) only the one connection.
+            if let Err(e) = self.accept() {
+                if let Some(f) = &self.on_error {
+                    f(&e);
+                }
+                // Give a transient condition time to clear instead of spinning.
+                thread::sleep(Duration::from_millis(100));
+            }
+        }
+        #[cfg(unix)]
+        if let Endpoint::Unix(path) = &self.endpoint {
+            let _ = std::fs::remove_file(path);
+        }
+        Ok(())
+    }
+
+    /// Accept one connection and serve it on its own thread.
+    fn accept(&self) -> io::Result<()> {
+        let workers = Arc::clone(&self.workers);
+        match &self.listener {
+            Listener::Tcp(listener) => {
+                let (stream, _) = listener.accept()?;
+                let reader = stream.try_clone()?;
+                thread::spawn(move || serve_connection(reader, stream, &workers));
+            }
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept()?;
+                let reader = stream.try_clone()?;
+                thread::spawn(move || serve_connection(reader, stream, &workers));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str =
+        "public class T { @Test public void testFoo() { int count = 1; assertEquals(1, count); } }";
+
+    fn request(id: u64, source: &str, naming: Value) -> String {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "obfuscate",
+            "params": {"source": source, "naming": naming},
+        })
+        .to_string()
+    }
+
+    fn result(reply: &str) -> Value {
+        let reply: Value = serde_json::from_str(reply).unwrap();
+        reply["result"].clone()
+    }
+
+    #[test]
+    fn obfuscate_returns_text_mapping_and_fallback_flag() {
+        let reply =
+            handle_request(&request(1, SOURCE, json!({})), &RequestSettings::default()).unwrap();
+        let result = result(&reply);
+        assert!(result["obfuscated"].as_str().unwrap().contains("func_1"));
+        assert_eq!(result["mapping"]["testFoo"], "func_1");
+        assert_eq!(result["mapping"]["count"], "var_1");
+        assert_eq!(result["fallback"], false);
+
+        let seeded = handle_request(
+            &request(2, SOURCE, json!({"seed": 3})),
+            &RequestSettings::default(),
+        )
+        .unwrap();
+        assert_ne!(
+            result["obfuscated"],
+            super::tests::result(&seeded)["obfuscated"]
+        );
+    }
+
+    #[test]
+    fn malformed_requests_get_json_rpc_errors() {
+        let code = |line: &str| {
+            let reply: Value =
+                serde_json::from_str(&handle_request(line, &RequestSettings::default()).unwrap())
+                    .unwrap();
+            reply["error"]["code"].as_i64().unwrap()
+        };
+        assert_eq!(code("{not json"), PARSE_ERROR);
+        assert_eq!(code(r#"{"id": 1}"#), INVALID_REQUEST);
+        assert_eq!(code(r#"{"id": 1, "method": "nope"}"#), METHOD_NOT_FOUND);
+        assert_eq!(
+            code(r#"{"id": 1, "method": "obfuscate", "params": {}}"#),
+            INVALID_PARAMS
+        );
+        assert_eq!(
+            handle_request(r#"{"method": "ping"}"#, &RequestSettings::default()),
+            None
+        );
+
+        // Obfuscation failures carry their error-log category.
+        let settings = RequestSettings {
+            limits: Limits {
+                max_bytes: Some(10),
+                ..Limits::default()
+            },
+            ..RequestSettings::default()
+        };
+        let reply = handle_request(&request(1, SOURCE, json!({})), &settings).unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["error"]["code"], OBFUSCATION_FAILED);
+        assert_eq!(reply["error"]["data"]["category"], "limit");
+    }
+
+    #[test]
+    fn settings_fill_in_the_params_a_request_leaves_out() {
+        let settings = RequestSettings {
+            naming: Naming {
+                function_prefix: "f_".to_string(),
+                ..Naming::default()
+            },
+            ..RequestSettings::default()
+        };
+        let reply = handle_request(&request(1, SOURCE, json!({})), &settings).unwrap();
+        assert_eq!(result(&reply)["mapping"]["testFoo"], "f_1");
+
+        let reply = handle_request(
+            &request(2, SOURCE, json!({"variable_prefix": "v_"})),
+            &settings,
+        )
+        .unwrap();
+        let mapping = &result(&reply)["mapping"];
+        assert_eq!(
+            (&mapping["testFoo"], &mapping["count"]),
+            (&json!("f_1"), &json!("v_1"))
+        );
+    }
+
+    #[test]
+    fn a_panicking_request_gets_an_internal_error() {
+        let workers = Workers {
+            pool: ThreadPoolBuilder::new().num_threads(1).build().unwrap(),
+            settings: RequestSettings::default(),
+        };
+        let reply = workers
+            .guarded(r#"{"id": 4, "method": "ping"}"#, |_| panic!("boom"))
+            .unwrap();
+        let reply: Value = serde_json::from_str(&reply).unwrap();
+        assert_eq!(reply["id"], 4);
+        assert_eq!(reply["error"]["code"], INTERNAL_ERROR);
+        assert!(reply["error"]["message"].as_str().unwrap().contains("boom"));
+        assert_eq!(
+            workers.guarded(r#"{"method": "ping"}"#, |_| panic!("boom")),
+            None
+        );
+    }
+
+    #[test]
+    fn oversized_requests_are_refused_without_reading_them() {
+        let workers = Workers {
+            pool: ThreadPoolBuilder::new().num_threads(1).build().unwrap(),
+            settings: RequestSettings::default(),
+        };
+        let serve = |input: Vec<u8>| {
+            let mut reply = Vec::new();
+            serve_connection(io::Cursor::new(input), &mut reply, &workers).unwrap();
+            String::from_utf8(reply).unwrap()
+        };
+
+        let http = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n{{}}",
+            MAX_REQUEST_BYTES + 1
+        );
+        assert!(serve(http.into_bytes()).starts_with("HTTP/1.1 413 "));
+
+        let mut line = vec![b' '; MAX_REQUEST_BYTES + 1];
+        line.extend_from_slice(b"\n{\"id\": 1, \"method\": \"ping\"}\n");
+        let reply: Value = serde_json::from_str(&serve(line)).unwrap();
+        assert_eq!(reply["error"]["code"], INVALID_REQUEST);
+    }
+
+    #[test]
+    fn endpoints_parse_and_must_be_local() {
+        assert!(matches!(
+            Endpoint::parse("127.0.0.1:0"),
+            Ok(Endpoint::Tcp(_))
+        ));
+        assert!(Endpoint::parse("0.0.0.0:7878").is_err());
+        #[cfg(unix)]
+        assert_eq!(
+            Endpoint::parse("unix:/tmp/s.sock"),
+            Ok(Endpoint::Unix(PathBuf::from("/tmp/s.sock")))
+        );
+    }
+
+    fn start(endpoint: Endpoint) -> (Endpoint, ServerHandle, thread::JoinHandle<()>) {
+        let server = Server::bind(&endpoint, 2, RequestSettings::default()).unwrap();
+        let (endpoint, handle) = (server.endpoint().clone(), server.handle());
+        let join = thread::spawn(move || server.serve().unwrap());
+        (endpoint, handle, join)
+    }
+
+    #[test]
+    fn tcp_server_handles_concurrent_clients_and_http() {
+        let (endpoint, handle, join) = start(Endpoint::parse("127.0.0.1:0").unwrap());
+        let Endpoint::Tcp(addr) = endpoint else {
+            unreachable!()
+        };
+
+        let clients: Vec<_> = (0..4)
+            .map(|i| {
+                thread::spawn(move || {
+                    let stream = TcpStream::connect(addr).unwrap();
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    let mut writer = stream;
+                    for j in 0..5 {
+                        writeln!(writer, "{}", request(i * 10 + j, SOURCE, json!({}))).unwrap();
+                        let mut reply = String::new();
+                        reader.read_line(&mut reply).unwrap();
+                        let reply: Value = serde_json::from_str(&reply).unwrap();
+                        assert_eq!(reply["id"], i * 10 + j);
+                        assert_eq!(reply["result"]["fallback"], false);
+                    }
+                })
+            })
+            .collect();
+        for client in clients {
+            client.join().unwrap();
+        }
+
+        let body = request(7, SOURCE, json!({}));
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut status = String::new();
+        reader.read_line(&mut status).unwrap();
+        assert_eq!(status.trim_end(), "HTTP/1.1 200 OK");
+
+        handle.stop();
+        join.join().unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_socket_server_round_trip() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("obfuscate.sock");
+        let (_, handle, join) = start(Endpoint::Unix(path.clone()));
+
+        let stream = UnixStream::connect(&path).unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut writer = stream;
+        writeln!(
+            writer,
+            "{}",
+            request(1, SOURCE, json!({"variable_prefix": "v"}))
+        )
+        .unwrap();
+        let mut reply = String::new();
+        reader.read_line(&mut reply).unwrap();
+        assert_eq!(result(&reply)["mapping"]["count"], "v1");
+
+        handle.stop();
+        join.join().unwrap();
+        assert!(!path.exists());
+    }
+}
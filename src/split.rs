@@ -0,0 +1,273 @@
+use std::fmt;
+use std::path::{Component, Path};
+
+use clap::ValueEnum;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::helper::stable_hash;
+
+/// One of the three dataset partitions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Split {
+    Train,
+    Val,
+    Test,
+}
+
+impl Split {
+    pub const ALL: [Split; 3] = [Split::Train, Split::Val, Split::Test];
+
+    /// Directory name used for this split under the JSONL output directory.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Split::Train => "train",
+            Split::Val => "val",
+            Split::Test => "test",
+        }
+    }
+}
+
+impl fmt::Display for Split {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Relative sizes of the train / val / test partitions.
+///
+/// Ratios are normalised on construction, so `80,10,10` and `0.8,0.1,0.1`
+/// describe the same split.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SplitRatios {
+    pub train: f64,
+    pub val: f64,
+    pub test: f64,
+}
+
+impl Default for SplitRatios {
+    fn default() -> Self {
+        SplitRatios {
+            train: 0.8,
+            val: 0.1,
+            test: 0.1,
+        }
+    }
+}
+
+impl SplitRatios {
+    pub fn new(train: f64, val: f64, test: f64) -> Result<Self, String> {
+        let parts = [train, val, test];
+        if parts.iter().any(|r| !r.is_finite() || *r < 0.0) {
+            return Err("split ratios must be finite and non-negative".to_string());
+        }
+        let total: f64 = parts.iter().sum();
+        if total <= 0.0 {
+            return Err("split ratios must not all be zero".to_string());
+        }
+        Ok(SplitRatios {
+            train: train / total,
+            val: val / total,
+            test: test / total,
+        })
+    }
+
+    /// Parse `"train,val,test"`, e.g. `80,10,10` or `0.8,0.1,0.1`.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let parts: Vec<f64> = s
+            .split(',')
+            .map(|p| {
+                p.trim()
+                    .parse::<f64>()
+                    .map_err(|e| format!("invalid split ratio {:?}: {}", p.trim(), e))
+            })
+            .collect::<Result<_, _>>()?;
+        match parts.as_slice() {
+            [train, val, test] => SplitRatios::new(*train, *val, *test),
+            _ => Err(format!(
+                "expected three comma-separated ratios (train,val,test), got {:?}",
+                s
+            )),
+        }
+    }
+}
+
+/// How files are grouped before a split is assigned.
+///
+/// Every file in the same group lands in the same split, so grouping by
+/// project or package keeps near-identical generated tests from straddling
+/// train and test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SplitGroup {
+    /// Each file is its own group.
+    #[default]
+    File,
+    /// First directory component below the input root.
+    Project,
+    /// The Java `package` declaration (falls back to the file when absent).
+    Package,
+}
+
+/// Derive the grouping key for `file`.
+///
+/// Keys are built from the path relative to `input_root` with `/` separators
+/// so that the same tree yields the same assignment on every platform.
+/// `source` is only consulted for [`SplitGroup::Package`].
+pub fn group_key(
+    input_root: &Path,
+    file: &Path,
+    source: Option<&str>,
+    group: SplitGroup,
+) -> String {
+    let relative = file.strip_prefix(input_root).unwrap_or(file);
+    let components: Vec<String> = relative
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy().into_owned()),
+            _ => None,
+        })
+        .collect();
+    let file_key = format!("file:{}", components.join("/"));
+
+    match group {
+        SplitGroup::File => file_key,
+        SplitGroup::Project => {
+            if components.len() > 1 {
+                format!("project:{}", components[0])
+            } else {
+                // Files directly under the input root have no project directory.
+                file_key
+            }
+        }
+        SplitGroup::Package => match source.and_then(java_package) {
+            Some(pkg) => format!("package:{}", pkg),
+            None => file_key,
+        },
+    }
+}
+
+/// Extract the `package a.b.c;` name from a Java source, if present.
+pub fn java_package(source: &str) -> Option<String> {
+    static PACKAGE_RE: std::sync::OnceLock<Regex> = std::sync::OnceLock::new();
+    let re = PACKAGE_RE.get_or_init(|| {
+        Regex::new(r"(?m)^\s*package\s+([A-Za-z_$][\w$]*(?:\s*\.\s*[A-Za-z_$][\w$]*)*)\s*;")
+            .unwrap()
+    });
+    re.captures(source)
+        .map(|c| c[1].chars().filter(|ch| !ch.is_whitespace()).collect())
+}
+
+/// Assign `key` to a split by hashing it together with `seed`.
+///
+/// The assignment depends only on the key, the seed and the ratios, so adding
+/// or removing other files never moves an existing group to another split.
+pub fn assign_split(key: &str, ratios: &SplitRatios, seed: u64) -> Split {
+    let mut bytes = seed.to_le_bytes().to_vec();
+    bytes.extend_from_slice(key.as_bytes());
+    let hash = stable_hash(&bytes);
+
+    // Top 53 bits → uniform f64 in [0, 1).
+    let point = (hash >> 11) as f64 / (1u64 << 53) as f64;
+    if point < ratios.train {
+        Split::Train
+    } else if point < ratios.train + ratios.val {
+        Split::Val
+    } else {
+        Split::Test
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn ratios_are_normalised() {
+        let r = SplitRatios::parse("80,10,10").unwrap();
+        assert!((r.train - 0.8).abs() < 1e-9);
+        assert!((r.val - 0.1).abs() < 1e-9);
+        assert!((r.test - 0.1).abs() < 1e-9);
+        assert_eq!(r, SplitRatios::parse("0.8, 0.1, 0.1").unwrap());
+    }
+
+    #[test]
+    fn ratios_reject_bad_input() {
+        assert!(SplitRatios::parse("80,20").is_err());
+        assert!(SplitRatios::parse("a,b,c").is_err());
+        assert!(SplitRatios::parse("0,0,0").is_err());
+        assert!(SplitRatios::parse("-1,1,1").is_err());
+    }
+
+    #[test]
+    fn assignment_is_deterministic() {
+        let r = SplitRatios::default();
+        for i in 0..100 {
+            let key = format!("file:TestClass{i}.java");
+            assert_eq!(assign_split(&key, &r, 7), assign_split(&key, &r, 7));
+        }
+    }
+
+    #[test]
+    fn assignment_roughly_follows_ratios() {
+        let r = SplitRatios::parse("60,20,20").unwrap();
+        let mut counts = [0usize; 3];
+        for i in 0..10_000 {
+            let split = assign_split(&format!("file:TestClass{i}.java"), &r, 0);
+            counts[split as usize] += 1;
+        }
+        assert!((5_500..6_500).contains(&counts[0]), "train={}", counts[0]);
+        assert!((1_500..2_500).contains(&counts[1]), "val={}", counts[1]);
+        assert!((1_500..2_500).contains(&counts[2]), "test={}", counts[2]);
+    }
+
+    #[test]
+    fn zero_ratio_split_is_never_assigned() {
+        let r = SplitRatios::parse("1,0,1").unwrap();
+        for i in 0..1_000 {
+            assert_ne!(assign_split(&format!("k{i}"), &r, 0), Split::Val);
+        }
+    }
+
+    #[test]
+    fn project_group_uses_first_directory() {
+        let root = Path::new("data");
+        let a = group_key(
+            root,
+            Path::new("data/projA/x/T1.java"),
+            None,
+            SplitGroup::Project,
+        );
+        let b = group_key(
+            root,
+            Path::new("data/projA/y/T2.java"),
+            None,
+            SplitGroup::Project,
+        );
+        assert_eq!(a, b);
+        assert_eq!(a, "project:projA");
+    }
+
+    #[test]
+    fn package_group_reads_package_declaration() {
+        let root = Path::new("data");
+        let src = "// header\npackage com.example.foo ;\npublic class T {}";
+        let key = group_key(
+            root,
+            Path::new("data/T.java"),
+            Some(src),
+            SplitGroup::Package,
+        );
+        assert_eq!(key, "package:com.example.foo");
+
+        let no_pkg = group_key(
+            root,
+            Path::new("data/T.java"),
+            Some("class T {}"),
+            SplitGroup::Package,
+        );
+        assert_eq!(no_pkg, "file:T.java");
+    }
+}
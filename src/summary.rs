@@ -0,0 +1,308 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::encoding::Encoding;
+use crate::manifest::Outcome;
+use crate::processor::{count_tokens, tokens};
+
+/// File name of the run summary inside the JSONL output directory.
+pub const SUMMARY_FILE: &str = "summary.json";
+
+/// Number of distinct identifiers of `original` that were renamed in
+/// `obfuscated`.
+///
+/// Both sides have the same structural token stream except for renamed
+/// identifiers, so the renames are the distinct original tokens paired with a
+/// different token at the same position.
+pub fn renamed_identifiers(original: &str, obfuscated: &str) -> usize {
+    tokens(original)
+        .zip(tokens(obfuscated))
+        .filter(|(a, b)| a != b)
+        .map(|(a, _)| a)
+        .collect::<HashSet<_>>()
+        .len()
+}
+
+/// Generated name of each renamed identifier of `original`, aligned by
+/// structural token like [`renamed_identifiers`].
+pub fn rename_mapping(original: &str, obfuscated: &str) -> BTreeMap<String, String> {
+    tokens(original)
+        .zip(tokens(obfuscated))
+        .filter(|(a, b)| a != b)
+        .map(|(a, b)| (a.to_string(), b.to_string()))
+        .collect()
+}
+
+/// Thread-safe accumulator for one run, turned into a [`RunSummary`] at the
+/// end.
+#[derive(Default)]
+pub struct RunStats {
+    inner: Mutex<Counters>,
+}
+
+#[derive(Default)]
+struct Counters {
+    files_seen: usize,
+    already_processed: usize,
+    clean: usize,
+    fallback: usize,
+    skipped_corrupt: usize,
+    filtered: usize,
+    errors_by_stage: BTreeMap<String, usize>,
+    encodings: BTreeMap<Encoding, usize>,
+    mojibake_repairs: usize,
+    mojibake_files: usize,
+    identifiers_renamed: usize,
+    token_lengths: Vec<usize>,
+    stage_time: BTreeMap<String, Duration>,
+}
+
+impl RunStats {
+    fn with<R>(&self, f: impl FnOnce(&mut Counters) -> R) -> R {
+        f(&mut self.inner.lock().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub fn file_seen(&self) {
+        self.with(|c| c.files_seen += 1);
+    }
+
+    /// The file was left alone because the manifest says it is up to date.
+    pub fn already_processed(&self) {
+        self.with(|c| c.already_processed += 1);
+    }
+
+    /// The file was decoded from `encoding`.
+    pub fn encoding(&self, encoding: Encoding) {
+        self.with(|c| *c.encodings.entry(encoding).or_default() += 1);
+    }
+
+    /// The sanitizer repaired `repairs` mis-decoded characters of a file.
+    pub fn mojibake_repairs(&self, repairs: usize) {
+        if repairs > 0 {
+            self.with(|c| {
+                c.mojibake_repairs += repairs;
+                c.mojibake_files += 1;
+            });
+        }
+    }
+
+    pub fn error(&self, stage: &str) {
+        self.with(|c| *c.errors_by_stage.entry(stage.to_string()).or_default() += 1);
+    }
+
+    pub fn outcome(&self, outcome: &Outcome) {
+        match outcome {
+            Outcome::Clean => self.with(|c| c.clean += 1),
+            Outcome::Fallback => self.with(|c| c.fallback += 1),
+            Outcome::Skipped => self.with(|c| c.skipped_corrupt += 1),
+            Outcome::Filtered { .. } => self.with(|c| c.filtered += 1),
+            Outcome::Failed { stage } => self.error(stage),
+        }
+    }
+
+    /// Record one written pair: identifiers renamed and its length in tokens.
+    pub fn pair(&self, original: &str, obfuscated: &str) {
+        let identifiers_renamed = renamed_identifiers(original, obfuscated);
+        let token_length = count_tokens(original);
+        self.with(|c| {
+            c.identifiers_renamed += identifiers_renamed;
+            c.token_lengths.push(token_length);
+        });
+    }
+
+    /// Run `f`, adding its wall time to `stage`.
+    pub fn time<R>(&self, stage: &str, f: impl FnOnce() -> R) -> R {
+        let start = Instant::now();
+        let result = f();
+        let elapsed = start.elapsed();
+        self.with(|c| *c.stage_time.entry(stage.to_string()).or_default() += elapsed);
+        result
+    }
+
+    pub fn finish(self, total: Duration) -> RunSummary {
+        let mut c = self.inner.into_inner().unwrap_or_else(|e| e.into_inner());
+        c.token_lengths.sort_unstable();
+        let pairs = c.token_lengths.len();
+        RunSummary {
+            files_seen: c.files_seen,
+            already_processed: c.already_processed,
+            clean_pairs: c.clean,
+            fallback_pairs: c.fallback,
+            skipped_corrupt: c.skipped_corrupt,
+            filtered: c.filtered,
+            errors_by_stage: c.errors_by_stage,
+            encodings: c
+                .encodings
+                .into_iter()
+                .map(|(encoding, n)| (encoding.as_str().to_string(), n))
+                .collect(),
+            mojibake_repairs: c.mojibake_repairs,
+            mojibake_files: c.mojibake_files,
+            avg_identifiers_renamed: if pairs == 0 {
+                0.0
+            } else {
+                c.identifiers_renamed as f64 / pairs as f64
+            },
+            token_length: TokenLengths::of_sorted(&c.token_lengths),
+            stage_seconds: c
+                .stage_time
+                .into_iter()
+                .map(|(stage, d)| (stage, d.as_secs_f64()))
+                .collect(),
+            total_seconds: total.as_secs_f64(),
+        }
+    }
+}
+
+/// Token-length percentiles over a set of pairs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct TokenLengths {
+    pub p50: usize,
+    pub p90: usize,
+    pub p99: usize,
+    pub max: usize,
+}
+
+impl TokenLengths {
+    pub(crate) fn of_sorted(lengths: &[usize]) -> Self {
+        // Nearest-rank percentile.
+        let pct = |p: usize| match lengths.len() {
+            0 => 0,
+            n => lengths[(p * n).div_ceil(100).clamp(1, n) - 1],
+        };
+        TokenLengths {
+            p50: pct(50),
+            p90: pct(90),
+            p99: pct(99),
+            max: lengths.last().copied().unwrap_or(0),
+        }
+    }
+}
+
+/// End-of-run report, written as `summary.json` and printed to stderr.
+///
+/// Per-file stage times are summed across worker threads, so they can exceed
+/// `total_seconds`.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunSummary {
+    pub files_seen: usize,
+    pub already_processed: usize,
+    pub clean_pairs: usize,
+    pub fallback_pairs: usize,
+    pub skipped_corrupt: usize,
+    pub filtered: usize,
+    pub errors_by_stage: BTreeMap<String, usize>,
+    /// Decoded files per source encoding.
+    pub encodings: BTreeMap<String, usize>,
+    /// Characters repaired by the sanitizer's `mojibake` step.
+    pub mojibake_repairs: usize,
+    /// Files with at least one such repair.
+    pub mojibake_files: usize,
+    pub avg_identifiers_renamed: f64,
+    pub token_length: TokenLengths,
+    pub stage_seconds: BTreeMap<String, f64>,
+    pub total_seconds: f64,
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Files seen:            {}", self.files_seen)?;
+        writeln!(f, "Already processed:     {}", self.already_processed)?;
+        writeln!(f, "Clean pairs:           {}", self.clean_pairs)?;
+        writeln!(f, "Fallback pairs:        {}", self.fallback_pairs)?;
+        writeln!(f, "Skipped corrupt:       {}", self.skipped_corrupt)?;
+        writeln!(f, "Filtered:              {}", self.filtered)?;
+        let errors: usize = self.errors_by_stage.values().sum();
+        writeln!(f, "Errors:                {}", errors)?;
+        for (stage, n) in &self.errors_by_stage {
+            writeln!(f, "  {:<20} {}", stage, n)?;
+        }
+        let encodings: Vec<String> = self
+            .encodings
+            .iter()
+            .map(|(encoding, n)| format!("{}={}", encoding, n))
+            .collect();
+        writeln!(f, "Encodings:             {}", encodings.join(" "))?;
+        writeln!(
+            f,
+            "Mojibake repairs:      {} in {} file(s)",
+            self.mojibake_repairs, self.mojibake_files
+        )?;
+        writeln!(
+            f,
+            "Identifiers renamed:   {:.1} per pair",
+            self.avg_identifiers_renamed
+        )?;
+        let t = self.token_length;
+        writeln!(
+            f,
+            "Token length:          p50={} p90={} p99={} max={}",
+            t.p50, t.p90, t.p99, t.max
+        )?;
+        writeln!(f, "Stage time:")?;
+        for (stage, secs) in &self.stage_seconds {
+            writeln!(f, "  {:<20} {:.2}s", stage, secs)?;
+        }
+        write!(f, "Wall time:             {:.2}s", self.total_seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renamed_identifiers_counts_distinct_renames() {
+        let original = "void testFoo() { int count = 1; count++; }";
+        let obfuscated = "void func_1() { int var_1 = 1; var_1++; }";
+        assert_eq!(renamed_identifiers(original, obfuscated), 2);
+        assert_eq!(renamed_identifiers(original, original), 0);
+
+        let mapping = rename_mapping(original, obfuscated);
+        assert_eq!(mapping["testFoo"], "func_1");
+        assert_eq!(mapping["count"], "var_1");
+        assert_eq!(mapping.len(), 2);
+    }
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let lengths: Vec<usize> = (1..=100).collect();
+        let t = TokenLengths::of_sorted(&lengths);
+        assert_eq!((t.p50, t.p90, t.p99, t.max), (50, 90, 99, 100));
+        assert_eq!(TokenLengths::of_sorted(&[]), TokenLengths::default());
+        assert_eq!(TokenLengths::of_sorted(&[7]).p50, 7);
+    }
+
+    #[test]
+    fn outcomes_and_pairs_are_aggregated() {
+        let stats = RunStats::default();
+        stats.outcome(&Outcome::Clean);
+        stats.outcome(&Outcome::Skipped);
+        stats.outcome(&Outcome::Failed {
+            stage: "obfuscate".to_string(),
+        });
+        stats.error("read");
+        stats.encoding(Encoding::Utf8);
+        stats.encoding(Encoding::Windows1252);
+        stats.encoding(Encoding::Utf8);
+        stats.mojibake_repairs(0);
+        stats.mojibake_repairs(3);
+        stats.pair("a b c d", "x y z w");
+        stats.pair("a b c d e f", "a b c d x y");
+
+        let summary = stats.finish(Duration::from_secs(1));
+        assert_eq!(summary.clean_pairs, 1);
+        assert_eq!(summary.skipped_corrupt, 1);
+        assert_eq!(summary.errors_by_stage["obfuscate"], 1);
+        assert_eq!(summary.errors_by_stage["read"], 1);
+        assert_eq!(summary.encodings["utf-8"], 2);
+        assert_eq!(summary.encodings["windows-1252"], 1);
+        assert_eq!((summary.mojibake_repairs, summary.mojibake_files), (3, 1));
+        assert_eq!(summary.avg_identifiers_renamed, 3.0);
+        assert_eq!(summary.token_length.max, 6);
+    }
+}
@@ -0,0 +1,356 @@
+//! Checks for existing JSONL datasets, including ones produced by older
+//! versions of this tool or by other people.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::commands::pair_files_under;
+use crate::helper::write_atomic;
+use crate::obfuscator::{Naming, SourcePosition, first_parse_error};
+use crate::processor::{OutputSchema, PromptResponse, count_tokens, tokens};
+
+/// One reason a record fails verification.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RecordProblem {
+    /// The line is not a pair under the schema.
+    InvalidRecord,
+    TokenMismatch {
+        prompt: usize,
+        response: usize,
+    },
+    PromptParseError {
+        position: SourcePosition,
+    },
+    ResponseParseError {
+        position: SourcePosition,
+    },
+    /// Token `index` differs in something other than an identifier, or an
+    /// identifier is renamed inconsistently.
+    NotARename {
+        index: usize,
+        prompt: String,
+        response: String,
+    },
+    /// A generated name such as `var_3` appears in the response.
+    GeneratedNameInResponse {
+        name: String,
+    },
+}
+
+/// Verification result of one JSONL line.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RecordCheck {
+    pub file: PathBuf,
+    /// 1-based line number within `file`.
+    pub line: usize,
+    pub problems: Vec<RecordProblem>,
+}
+
+impl RecordCheck {
+    pub fn passed(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Java keywords and literals.  A rename never touches these, so a token
+/// pair that swaps one is a real change (`int` → `long`, `true` → `false`).
+const RESERVED: &[&str] = &[
+    "abstract",
+    "assert",
+    "boolean",
+    "break",
+    "byte",
+    "case",
+    "catch",
+    "char",
+    "class",
+    "const",
+    "continue",
+    "default",
+    "do",
+    "double",
+    "else",
+    "enum",
+    "extends",
+    "false",
+    "final",
+    "finally",
+    "float",
+    "for",
+    "goto",
+    "if",
+    "implements",
+    "import",
+    "instanceof",
+    "int",
+    "interface",
+    "long",
+    "native",
+    "new",
+    "null",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "return",
+    "short",
+    "static",
+    "strictfp",
+    "super",
+    "switch",
+    "synchronized",
+    "this",
+    "throw",
+    "throws",
+    "transient",
+    "true",
+    "try",
+    "void",
+    "volatile",
+    "while",
+    "_",
+];
+
+/// An identifier the obfuscator may have renamed.
+fn is_renamable(token: &str) -> bool {
+    token
+        .bytes()
+        .next()
+        .is_some_and(|b| b.is_ascii_alphabetic() || b == b'_' || b == b'$' || !b.is_ascii())
+        && !RESERVED.contains(&token)
+}
+
+/// `token` is exactly a generated name, e.g. `var_3`.
+fn is_generated(token: &str, generated: &Regex) -> bool {
+    generated
+        .find(token)
+        .is_some_and(|m| m.start() == 0 && m.end() == token.len())
+}
+
+/// Every problem of one pair.  Only the first differing token that is not a
+/// consistent identifier rename is reported.
+pub fn check_pair(pair: &PromptResponse, generated: &Regex) -> Vec<RecordProblem> {
+    let mut problems = Vec::new();
+
+    let (p, r) = (count_tokens(&pair.prompt), count_tokens(&pair.response));
+    if p != r {
+        problems.push(RecordProblem::TokenMismatch {
+            prompt: p,
+            response: r,
+        });
+    }
+    if let Some(position) = first_parse_error(&pair.prompt) {
+        problems.push(RecordProblem::PromptParseError { position });
+    }
+    if let Some(position) = first_parse_error(&pair.response) {
+        problems.push(RecordProblem::ResponseParseError { position });
+    }
+
+    // A differing token must be a generated name in the prompt and a
+    // renamable identifier in the response, and the renaming must be
+    // one-to-one: each original maps to one generated name and back.
+    let mut generated_of: HashMap<&str, &str> = HashMap::new();
+    let mut original_of: HashMap<&str, &str> = HashMap::new();
+    for (index, (prompt, response)) in tokens(&pair.prompt).zip(tokens(&pair.response)).enumerate()
+    {
+        if prompt == response {
+            continue;
+        }
+        let consistent = is_generated(prompt, generated)
+            && is_renamable(response)
+            && *generated_of.entry(response).or_insert(prompt) == prompt
+            && *original_of.entry(prompt).or_insert(response) == response;
+        if !consistent {
+            problems.push(RecordProblem::NotARename {
+                index,
+                prompt: prompt.to_string(),
+                response: response.to_string(),
+            });
+            break;
+        }
+    }
+
+    if let Some(m) = generated.find(&pair.response) {
+        problems.push(RecordProblem::GeneratedNameInResponse {
+            name: m.as_str().to_string(),
+        });
+    }
+    problems
+}
+
+/// Regex matching a name generated under `naming`, e.g. `var_12`.
+pub fn generated_name_regex(naming: &Naming) -> Regex {
+    let prefixes: Vec<String> = [&naming.function_prefix, &naming.variable_prefix]
+        .into_iter()
+        .filter(|p| !p.is_empty())
+        .map(|p| regex::escape(p))
+        .collect();
+    let pattern = if prefixes.is_empty() {
+        // Nothing can be recognised as generated.
+        r"[^\s\S]".to_string()
+    } else {
+        format!(r"\b(?:{})\d+\b", prefixes.join("|"))
+    };
+    Regex::new(&pattern).expect("escaped prefixes form a valid regex")
+}
+
+/// Check every record of every JSONL pair file under `path`.  With `clean`,
+/// the lines of the passing records are copied to that file.
+pub fn verify_dataset(
+    path: &Path,
+    schema: &OutputSchema,
+    naming: &Naming,
+    clean: Option<&Path>,
+) -> io::Result<Vec<RecordCheck>> {
+    let generated = generated_name_regex(naming);
+    let mut checks = Vec::new();
+    let mut clean_lines = String::new();
+    for file in pair_files_under(path)? {
+        for (i, line) in fs::read_to_string(&file)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let problems = match schema.parse_line(line) {
+                Some(pair) => check_pair(&pair, &generated),
+                None => vec![RecordProblem::InvalidRecord],
+            };
+            if problems.is_empty() {
+                clean_lines.push_str(line);
+                clean_lines.push('\n');
+            }
+            checks.push(RecordCheck {
+                file: file.clone(),
+                line: i + 1,
+                problems,
+            });
+        }
+    }
+    if let Some(clean) = clean {
+        write_atomic(clean, |w| w.write_all(clean_lines.as_bytes()))?;
+    }
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn pair(prompt: &str, response: &str) -> PromptResponse {
+        PromptResponse {
+            prompt: prompt.to_string(),
+            response: response.to_string(),
+        }
+    }
+
+    fn problems(prompt: &str, response: &str) -> Vec<RecordProblem> {
+        check_pair(
+            &pair(prompt, response),
+            &generated_name_regex(&Naming::default()),
+        )
+    }
+
+    #[test]
+    fn consistent_renames_pass() {
+        assert!(
+            problems(
+                "class T { void func_1() { int var_1 = 1; var_1++; } }",
+                "class T { void run() { int n = 1; n++; } }",
+            )
+            .is_empty()
+        );
+    }
+
+    #[test]
+    fn each_check_reports_its_problem() {
+        assert!(matches!(
+            problems("class T { int a; }", "class T { int a = 1; }")[..],
+            [RecordProblem::TokenMismatch { .. }, ..]
+        ));
+        assert!(matches!(
+            problems("class T { int var_1 = 2; }", "class T { int n = 1; }")[..],
+            [RecordProblem::NotARename { index: 6, .. }]
+        ));
+        // `n` renamed to two different names.
+        assert!(matches!(
+            problems(
+                "class T { int var_1; int var_2 = var_2; }",
+                "class T { int n; int m = n; }"
+            )[..],
+            [RecordProblem::NotARename { .. }]
+        ));
+        // Two originals renamed to the same generated name.
+        assert!(matches!(
+            problems(
+                "class T { int var_1; int var_1 = 0; }",
+                "class T { int n; int m = 0; }"
+            )[..],
+            [RecordProblem::NotARename { index: 7, .. }]
+        ));
+        // Keywords, literals and names that are not generated are not
+        // renames.
+        for (prompt, response) in [
+            ("class T { long var_1; }", "class T { int n; }"),
+            (
+                "class T { private int var_1; }",
+                "class T { public int n; }",
+            ),
+            (
+                "class T { boolean var_1 = false; }",
+                "class T { boolean n = true; }",
+            ),
+            ("class T { int var_1 = int; }", "class T { int n = long; }"),
+            (
+                "class T { void func_1() { assertNotEquals(1, 2); } }",
+                "class T { void run() { assertEquals(1, 2); } }",
+            ),
+        ] {
+            assert!(
+                matches!(
+                    problems(prompt, response)[..],
+                    [.., RecordProblem::NotARename { .. }]
+                ),
+                "{prompt} / {response}"
+            );
+        }
+        assert_eq!(
+            problems("class T { int var_1; }", "class T { int var_7; }"),
+            vec![RecordProblem::GeneratedNameInResponse {
+                name: "var_7".to_string()
+            }]
+        );
+        assert!(matches!(
+            problems("class T { int x }", "class T { int x }")[..],
+            [
+                RecordProblem::PromptParseError { .. },
+                RecordProblem::ResponseParseError { .. }
+            ]
+        ));
+    }
+
+    #[test]
+    fn verify_dataset_writes_clean_copy() {
+        let dir = TempDir::new().unwrap();
+        let schema = OutputSchema::default();
+        let good = schema.to_line(&pair("class T { int var_1; }", "class T { int n; }"));
+        let bad = schema.to_line(&pair("class T { int var_1; }", "class T { int var_1; }"));
+        fs::write(
+            dir.path().join("data.jsonl"),
+            format!("{}\n{}\n{{}}\n", good, bad),
+        )
+        .unwrap();
+
+        let clean = dir.path().join("clean.out");
+        let checks = verify_dataset(dir.path(), &schema, &Naming::default(), Some(&clean)).unwrap();
+        let passed: Vec<bool> = checks.iter().map(RecordCheck::passed).collect();
+        assert_eq!(passed, [true, false, false]);
+        assert_eq!(checks[2].problems, [RecordProblem::InvalidRecord]);
+        assert_eq!(fs::read_to_string(clean).unwrap(), format!("{}\n", good));
+    }
+}